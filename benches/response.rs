@@ -0,0 +1,35 @@
+use std::hint::black_box;
+use criterion::{criterion_group, criterion_main, Criterion};
+use serde_json::{json, Map, Value};
+
+
+// a GET result of 1000 points, as handle_get collects it
+fn result_table() -> Map<String, Value> {
+
+    (0..1000)
+        .map(|index| (format!("point_{:04}", index), json!(index as f64 * 0.5)))
+        .collect()
+
+}
+
+fn serialize_response(c: &mut Criterion) {
+
+    let results = result_table();
+
+    // stringify the wrapper, pass it through json! again, then let zmq copy the String
+    c.bench_function("get_1000/string", |b| b.iter(|| {
+        let wrapper = Value::Object(Map::from_iter([(String::from("GET"), Value::Object(results.clone()))]));
+        let response: Value = serde_json::from_str(&wrapper.to_string()).unwrap();
+        zmq::Message::from(response.to_string().as_str())
+    }));
+
+    // serialize once into the buffer zmq takes ownership of
+    c.bench_function("get_1000/vec", |b| b.iter(|| {
+        let response = json!({"GET": black_box(&results)});
+        zmq::Message::from(serde_json::to_vec(&response).unwrap())
+    }));
+
+}
+
+criterion_group!(benches, serialize_response);
+criterion_main!(benches);
@@ -0,0 +1,54 @@
+use crate::interface::{BlockType, Interface};
+
+
+fn format_span(block_type: BlockType, start: u16, end: u16) -> String {
+
+    let block = block_type.to_string().to_uppercase();
+    match start == end {
+        true => format!("{} {}", block, start),
+        false => format!("{} {}-{}", block, start, end),
+    }
+
+}
+
+// one line per slave, points in block and address order with the unused addresses between them
+pub fn format_address_map(iface: &Interface) -> String {
+
+    let mut slave_list: Vec<_> = iface.slaves.iter().collect();
+    slave_list.sort_by_key(|(slave_name, slave_data)| (slave_data.id_range().map_or(slave_data.id(), |(first, _)| first), slave_name.as_str()));
+
+    let mut lines = Vec::new();
+    for (slave_name, slave_data) in slave_list {
+        let mut entries = Vec::new();
+        for block_type in [BlockType::Co, BlockType::Di, BlockType::Hr, BlockType::Ir] {
+            let mut next_free = None;
+            for (start, len, name) in slave_data.layout(block_type) {
+                let end = start + len - 1;
+                match next_free {
+                    Some(next_free) if start > next_free => entries.push(format!("{}: gap", format_span(block_type, next_free, start - 1))),
+                    _ => {},
+                }
+                next_free = Some(next_free.map_or(end + 1, |next_free: u16| next_free.max(end + 1)));
+                let value_type = match slave_data.registers().find_in_block(block_type, name) {
+                    Some(modbus_data) => modbus_data.value_type().to_string(),
+                    None => String::from("?"),
+                };
+                entries.push(format!("{}: {} ({})", format_span(block_type, start, end), name, value_type));
+            }
+        }
+        let id = match slave_data.id_range() {
+            Some((first, last)) => format!("{}-{}", first, last),
+            None => slave_data.id().to_string(),
+        };
+        lines.push(format!("Slave {} ({}): {}", id, slave_name, entries.join(", ")));
+    }
+
+    lines.join("\n")
+
+}
+
+pub fn print_address_map(iface: &Interface) {
+
+    println!("{}", format_address_map(iface));
+
+}
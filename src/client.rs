@@ -0,0 +1,157 @@
+use core::fmt;
+use std::io::{self, Write};
+use serde_json::{self, Map, Value};
+use zmq::{Context, Socket, REQ};
+
+use crate::protocol::{self, Method};
+
+
+const TIMEOUT_MS: i32 = 5000;
+
+const USAGE: &str = concat!(
+    "usage: zero_modbus client --connect zmq_address [--timeout-ms N] get /device/slave/point ...\n",
+    "       zero_modbus client --connect zmq_address [--timeout-ms N] set /device/slave/point=value ...\n",
+    "       zero_modbus client --connect zmq_address [--timeout-ms N] list | stats | reload\n",
+    "       zero_modbus client --connect zmq_address [--timeout-ms N] raw --json '<request>'",
+);
+
+pub enum ClientError {
+    Usage(String),
+    Transport(String),
+    Timeout(i32), // no response within this many milliseconds
+    InvalidResponse(String),
+}
+
+// the REQ side of the protocol, one outstanding request at a time
+pub struct Client {
+    socket: Socket,
+    timeout_ms: i32,
+}
+
+impl Client {
+
+    pub fn connect(address: &str, timeout_ms: i32) -> Result<Self, ClientError> {
+
+        let transport_error = |e: zmq::Error| ClientError::Transport(format!("{}", e));
+        let socket = Context::new().socket(REQ).map_err(transport_error)?;
+        socket.set_linger(0).map_err(transport_error)?;
+        socket.set_rcvtimeo(timeout_ms).map_err(transport_error)?;
+        socket.set_sndtimeo(timeout_ms).map_err(transport_error)?;
+        socket.connect(address).map_err(transport_error)?;
+
+        Ok(Client { socket, timeout_ms })
+
+    }
+
+    pub fn request(&self, request: &Value) -> Result<Value, ClientError> {
+
+        let timeout_error = |e: zmq::Error| match e {
+            zmq::Error::EAGAIN => ClientError::Timeout(self.timeout_ms),
+            e => ClientError::Transport(format!("{}", e)),
+        };
+        self.socket.send(request.to_string().as_bytes(), 0).map_err(timeout_error)?;
+        let response = self.socket.recv_bytes(0).map_err(timeout_error)?;
+
+        serde_json::from_slice(&response).map_err(|e| ClientError::InvalidResponse(format!("{}", e)))
+
+    }
+
+}
+
+fn parse_command(args: &[String]) -> Result<Value, ClientError> {
+
+    let (command, rest) = args.split_first()
+        .ok_or_else(|| ClientError::Usage(String::from("Missing command")))?;
+    match (command.as_str(), rest) {
+        ("get", paths) if !paths.is_empty() => Ok(Method::Get.request(Value::from(paths.to_vec()))),
+        ("set", pairs) if !pairs.is_empty() => {
+            let mut values = Map::new();
+            for pair in pairs {
+                let (path, value) = pair.split_once('=')
+                    .ok_or_else(|| ClientError::Usage(format!("Invaild pair '{}', expected path=value", pair)))?;
+                // JSON literals are sent as they are, anything else as a string
+                let value = serde_json::from_str(value).unwrap_or_else(|_| Value::String(String::from(value)));
+                values.insert(String::from(path), value);
+            }
+            Ok(Method::Set.request(Value::Object(values)))
+        },
+        ("list", []) => Ok(Method::List.request(Value::Null)),
+        ("stats", []) => Ok(Method::Stats.request(Value::Null)),
+        ("reload", []) => Ok(Method::Reload.request(Value::Null)),
+        ("raw", [flag, request]) if flag == "--json" => serde_json::from_str(request)
+            .map_err(|e| ClientError::Usage(format!("Invaild request JSON: {}", e))),
+        _ => Err(ClientError::Usage(format!("Invaild command: '{}'", args.join(" ")))),
+    }
+
+}
+
+// exit code: 0 for a successful response, 1 for an error response, 2 when no response was received
+pub fn run(args: &[String]) -> i32 {
+
+    let mut address = None;
+    let mut timeout_ms = TIMEOUT_MS;
+    let mut index = 0;
+    while index < args.len() {
+        match args[index].as_str() {
+            "--connect" => address = args.get(index + 1).cloned(),
+            "--timeout-ms" => match args.get(index + 1).and_then(|value| value.parse().ok()) {
+                Some(value) if value > 0 => timeout_ms = value,
+                _ => {
+                    eprintln!("Invaild timeout: '{}'\n{}", args.get(index + 1).map_or("", String::as_str), USAGE);
+                    return 2;
+                }
+            },
+            _ => break,
+        }
+        index += 2;
+    }
+
+    let result = match address {
+        Some(address) => parse_command(&args[index..])
+            .and_then(|request| Client::connect(&address, timeout_ms)?.request(&request)),
+        None => Err(ClientError::Usage(String::from("Missing --connect"))),
+    };
+    let response = match result {
+        Ok(response) => response,
+        Err(e @ ClientError::Usage(_)) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return 2;
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            return 2;
+        },
+    };
+
+    // a closed pipe (e.g. `| head`) is not worth a panic
+    let _ = writeln!(io::stdout(), "{}", serde_json::to_string_pretty(&response).unwrap_or_else(|_| response.to_string()));
+    // a SET that went through partially still reports what failed
+    match protocol::response_error(&response).is_some() || response.get("failed").is_some() {
+        true => 1,
+        false => 0,
+    }
+
+}
+
+impl fmt::Display for ClientError {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        match self {
+            ClientError::Usage(info) => {
+                write!(f, "Usage: {}", info)
+            },
+            ClientError::Transport(info) => {
+                write!(f, "Transport: {}", info)
+            },
+            ClientError::Timeout(timeout_ms) => {
+                write!(f, "Timeout: no response within {}ms", timeout_ms)
+            },
+            ClientError::InvalidResponse(info) => {
+                write!(f, "InvalidResponse: {}", info)
+            },
+        }
+
+    }
+
+}
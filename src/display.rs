@@ -0,0 +1,82 @@
+use crate::interface::{BlockType, Interface};
+
+
+const HEADERS: [&str; 6] = ["Slave", "Block", "Name", "Address", "Type", "Description"];
+
+fn format_row(cells: &[String], widths: &[usize]) -> String {
+
+    let padded: Vec<String> = cells.iter().zip(widths)
+        .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+        .collect();
+
+    format!("| {} |", padded.join(" | "))
+
+}
+
+pub fn format_register_table(iface: &Interface) -> String {
+
+    let mut slave_list: Vec<_> = iface.slaves.iter().collect();
+    slave_list.sort_by_key(|(slave_name, _)| slave_name.as_str());
+
+    let mut rows: Vec<[String; 6]> = Vec::new();
+    for (slave_name, slave_data) in slave_list {
+        for block_type in [BlockType::Co, BlockType::Di, BlockType::Hr, BlockType::Ir] {
+            let mut block_list: Vec<_> = slave_data.registers().iter_block(block_type).collect();
+            block_list.sort_by_key(|(name, modbus_data)| (modbus_data.address(), *name));
+            for (name, modbus_data) in block_list {
+                let address = match modbus_data.words() {
+                    Some(words) => words.iter().map(|word| word.to_string()).collect::<Vec<_>>().join(","),
+                    None => modbus_data.address().to_string(),
+                };
+                let mut value_type = modbus_data.value_type().to_string().to_lowercase();
+                if modbus_data.invert() {
+                    value_type.push_str(" (inverted)");
+                }
+                rows.push([
+                    slave_name.clone(),
+                    block_type.to_string().to_lowercase(),
+                    String::from(name),
+                    address,
+                    value_type,
+                    String::from(modbus_data.description().unwrap_or_default()),
+                ]);
+            }
+        }
+    }
+
+    let mut widths: Vec<usize> = HEADERS.iter().map(|header| header.len()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let separator: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+    let mut table = vec![
+        format_row(&HEADERS.map(String::from), &widths),
+        format!("|-{}-|", separator.join("-|-")),
+    ];
+    table.extend(rows.iter().map(|row| format_row(row, &widths)));
+
+    table.join("\n")
+
+}
+
+// offset, 16 bytes in two groups of 8 and their printable ascii, like `hexdump -C`
+pub fn format_hexdump(bytes: &[u8]) -> String {
+
+    let mut lines = Vec::new();
+    for (index, chunk) in bytes.chunks(16).enumerate() {
+        let hex: Vec<String> = (0..16)
+            .map(|column| chunk.get(column).map_or(String::from("  "), |byte| format!("{:02x}", byte)))
+            .collect();
+        let ascii: String = chunk.iter()
+            .map(|byte| if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' })
+            .collect();
+        lines.push(format!("{:08x}  {}  {}  |{}|", index * 16, hex[..8].join(" "), hex[8..].join(" "), ascii));
+    }
+    lines.push(format!("{:08x}", bytes.len()));
+
+    lines.join("\n")
+
+}
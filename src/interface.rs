@@ -1,11 +1,46 @@
-use std::{collections::HashMap, fs::File, fmt};
-use serde_yaml::{self, Value};
+use std::{cmp::Reverse, collections::{BTreeMap, BTreeSet, HashMap}, fs, fmt, ops::RangeInclusive, time::Duration};
+use log::*;
+use serde::{Deserialize, Serialize};
+use serde_yaml::{self, Mapping, Value};
+
+use crate::{display, modbus::{self, ModbusSession}};
 
 
 #[derive(Copy, Clone, PartialEq)]
 pub enum ModbusProtocol {
     Rtu,
     Tcp,
+    #[cfg(feature = "usb_hid")]
+    UsbHid,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum FlowControl {
+    None,
+    Hardware,
+    Software,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum ConnectMode {
+    Eager,
+    Lazy,
+}
+
+// what a SET does on this interface, reads are never affected
+#[derive(Copy, Clone, PartialEq)]
+pub enum WriteMode {
+    Enabled,
+    Simulate, // checked and encoded, answered as written, nothing goes on the bus
+    Reject,
+}
+
+// reopens a wedged serial port, see modbus::batch_request_items
+#[derive(Copy, Clone, PartialEq)]
+pub struct Watchdog {
+    pub timeouts: usize, // consecutive timeouts, from at least two slaves, before the port is reopened
+    pub reopen_attempts: u32, // failed reopens before the device is marked offline
+    pub window: Duration, // timeouts older than this are forgotten
 }
 
 #[derive(Copy, Clone, PartialEq)]
@@ -14,7 +49,8 @@ pub enum RequestFunction {
     Multiple,
 }
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum BlockType {
     Co, Di,
     Hr, Ir,
@@ -25,6 +61,14 @@ pub enum ValueType {
     Bool,
     U16, I16,
     U32, I32, F32,
+    BcdU16, BcdU32, // one decimal digit per nibble
+    Custom(&'static str), // registered through modbus::register_value_type
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Endian {
+    Big,
+    Little,
 }
 
 impl ValueType {
@@ -38,22 +82,141 @@ impl ValueType {
             ValueType::U32 => 2,
             ValueType::I32 => 2,
             ValueType::F32 => 2,
+            ValueType::BcdU16 => 1,
+            ValueType::BcdU32 => 2,
+            ValueType::Custom(name) => modbus::custom_type_words(name),
         }
 
     }
 
 }
 
-#[derive(Clone)]
+// raw -> engineering rows from a csv file, sorted by raw value
+#[derive(Clone, PartialEq)]
+pub struct CalTable {
+    path: String,
+    rows: Vec<(f64, f64)>,
+}
+
+impl CalTable {
+
+    pub fn path(&self) -> &str {
+
+        &self.path
+
+    }
+
+    // linear between the neighbouring rows, clamped to the first and last one
+    pub fn interpolate(&self, raw: f64) -> f64 {
+
+        let (first, last) = (self.rows[0], self.rows[self.rows.len() - 1]);
+        if raw <= first.0 {
+            return first.1;
+        }
+        if raw >= last.0 {
+            return last.1;
+        }
+        let upper = self.rows.partition_point(|(row_raw, _)| *row_raw < raw);
+        let ((raw_0, value_0), (raw_1, value_1)) = (self.rows[upper - 1], self.rows[upper]);
+
+        value_0 + (raw - raw_0) * (value_1 - value_0) / (raw_1 - raw_0)
+
+    }
+
+}
+
+// how a shadow register is derived from the words of its point
+#[derive(Copy, Clone, PartialEq)]
+pub enum ShadowFormula {
+    Invert, // 0xFFFF ^ word
+    Copy,
+}
+
+impl ShadowFormula {
+
+    pub fn apply(&self, word: u16) -> u16 {
+
+        match self {
+            ShadowFormula::Invert => 0xFFFF ^ word,
+            ShadowFormula::Copy => word,
+        }
+
+    }
+
+}
+
+// a second copy of a point at another address, read with it to catch corrupted values
+#[derive(Copy, Clone, PartialEq)]
+pub struct Shadow {
+    address: u8,
+    formula: ShadowFormula,
+}
+
+impl Shadow {
+
+    pub fn address(&self) -> u8 {
+
+        self.address
+
+    }
+
+    pub fn formula(&self) -> ShadowFormula {
+
+        self.formula
+
+    }
+
+}
+
+#[derive(Clone, PartialEq)]
 pub struct ModbusData {
     address: u8,
     block_type: BlockType,
     value_type: ValueType,
     requestfunction: RequestFunction,
+    count: u16, // registers accessed, defaults to value_type.size()
+    words: Option<Vec<u8>>, // explicit word addresses, high word first
+    byte_order: Endian, // bytes within each register
+    word_order: Endian, // registers within a 32-bit value
+    pulse_ms: Option<u64>, // coils written true are reset to false after this delay
+    active_low: bool, // discrete inputs read inverted
+    invert: bool, // bools are flipped on read and write, per element for arrays and flags
+    flags: Option<BTreeMap<u8, String>>, // bit index -> name, the register reads as named booleans
+    view: bool, // meant to overlap other points, not reported as an overlap
+    description: Option<String>,
+    unit: Option<String>, // GET with_units answers {"value", "unit"} for these points
+    poll_priority: u8, // order of SNAPSHOT reads, 0 last and 255 first; there is no poll scheduler
+    cal_table: Option<CalTable>, // reads are interpolated through it, writes are refused
+    shadow: Option<Shadow>, // reads fail when the shadow does not match the point
 }
 
 impl ModbusData {
 
+    pub fn new(address: u8, block_type: BlockType, value_type: ValueType, requestfunction: RequestFunction) -> Self {
+
+        ModbusData {
+            address,
+            block_type,
+            value_type,
+            requestfunction,
+            count: value_type.size() as u16,
+            words: None,
+            byte_order: Endian::Big,
+            word_order: Endian::Big,
+            pulse_ms: None,
+            active_low: false,
+            invert: false,
+            flags: None,
+            view: false,
+            description: None,
+            unit: None,
+            poll_priority: 0,
+            cal_table: None,
+            shadow: None,
+        }
+
+    }
+
     pub fn address(&self) -> u8 {
 
         self.address
@@ -77,370 +240,2219 @@ impl ModbusData {
         self.requestfunction
         
     }
-    
-}
 
-#[derive(Clone)]
-pub struct SlaveData {
-    id: u8,
-    co: HashMap<String, ModbusData>,
-    di: HashMap<String, ModbusData>,
-    hr: HashMap<String, ModbusData>,
-    ir: HashMap<String, ModbusData>,
-}
+    pub fn count(&self) -> u16 {
 
-impl SlaveData {
+        self.count
 
-    pub fn new(id: u8,
-        co: HashMap<String, ModbusData>,
-        di: HashMap<String, ModbusData>,
-        hr: HashMap<String, ModbusData>,
-        ir: HashMap<String, ModbusData>,
-    ) -> Self {
+    }
 
-        SlaveData {
-            id: id,
-            co: co,
-            di: di,
-            hr: hr,
-            ir: ir,
-        }
+    pub fn words(&self) -> Option<&[u8]> {
+
+        self.words.as_deref()
 
     }
 
-    pub fn id(&self) -> u8 {
+    pub fn byte_order(&self) -> Endian {
 
-        self.id
+        self.byte_order
 
     }
 
-    pub fn find(&self, name: &str) -> Option<ModbusData> {
-        if self.co.contains_key(name) {
-            return Some(self.co[name].clone());
-        }
-        if self.di.contains_key(name) {
-            return Some(self.di[name].clone());
-        }
-        if self.hr.contains_key(name) {
-            return Some(self.hr[name].clone());
-        }
-        if self.ir.contains_key(name) {
-            return Some(self.ir[name].clone());
-        }
-        return None
+    pub fn word_order(&self) -> Endian {
+
+        self.word_order
 
     }
 
-}
+    pub fn pulse_ms(&self) -> Option<u64> {
 
+        self.pulse_ms
 
-#[derive(Clone)]
-pub struct Interface {
-    modbusprotocol: ModbusProtocol,
-    address: String,
-    config: u32, // tcp port or serial baudrate
-    pub slaves: HashMap<String, SlaveData>,
-}
+    }
 
-impl Interface {
-    
-    pub fn modbusprotocol(&self) -> ModbusProtocol {
+    pub fn active_low(&self) -> bool {
+
+        self.active_low
 
-        self.modbusprotocol
-        
     }
-    
-    pub fn address(&self) -> String {
 
-        self.address.clone()
-        
+    pub fn invert(&self) -> bool {
+
+        self.invert
+
     }
-    
-    pub fn config(&self) -> u32 {
 
-        self.config
-        
+    pub fn flags(&self) -> Option<&BTreeMap<u8, String>> {
+
+        self.flags.as_ref()
+
     }
 
-}
+    pub fn view(&self) -> bool {
 
-macro_rules! missing_required_message {
-    ($key:expr) => {
-        format!("Missing required '{}'", $key).as_str()
-    };
-}
+        self.view
 
-macro_rules! invailed_type_message {
-    ($type:expr, $required:expr) => {
-        format!("Invild type of '{}', required {}", $type, $required).as_str()
-    };
+    }
+
+    pub fn description(&self) -> Option<&str> {
+
+        self.description.as_deref()
+
+    }
+
+    pub fn unit(&self) -> Option<&str> {
+
+        self.unit.as_deref()
+
+    }
+
+    pub fn poll_priority(&self) -> u8 {
+
+        self.poll_priority
+
+    }
+
+    pub fn cal_table(&self) -> Option<&CalTable> {
+
+        self.cal_table.as_ref()
+
+    }
+
+    pub fn shadow(&self) -> Option<Shadow> {
+
+        self.shadow
+
+    }
+
+    pub fn address_range(&self) -> RangeInclusive<u16> {
+
+        let start = self.address as u16;
+        start..=(start + self.count - 1)
+
+    }
+
+    fn occupied(&self) -> Vec<u16> {
+
+        match &self.words {
+            Some(words) => words.iter().map(|word| *word as u16).collect(),
+            None => self.address_range().collect(),
+        }
+
+    }
+    
 }
 
-macro_rules! invailed_value_message {
-    ($name:expr, $value:expr) => {
-        format!("Invaild value of '{}': '{}'", $name, $value).as_str()
-    };
+const SUGGEST_ALL_LIMIT: usize = 10;
+const SUGGEST_MAX_DISTANCE: usize = 2;
+
+fn levenshtein(a: &str, b: &str) -> usize {
+
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = diagonal + (a_char != *b_char) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+
+    row[b.len()]
+
 }
 
-macro_rules! get_yaml_string {
+pub fn closest_names<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
 
-    ($object:expr, $key:expr) => {
+    let mut candidates: Vec<&str> = candidates.collect();
+    candidates.sort();
+    candidates.dedup();
 
-        String::from($object.get($key)
-            .expect(missing_required_message!($key))
-            .as_str()
-            .expect(invailed_type_message!($key, "string"))
-        )
+    // short lists are returned whole, long ones only keep near misses
+    if candidates.len() <= SUGGEST_ALL_LIMIT {
+        return candidates;
+    }
+    let mut matches: Vec<(usize, &str)> = candidates.into_iter()
+        .map(|candidate| (levenshtein(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= SUGGEST_MAX_DISTANCE)
+        .collect();
+    matches.sort();
 
-    };
+    matches.into_iter().map(|(_, candidate)| candidate).collect()
 
 }
 
-macro_rules! get_modbus_block_value {
+pub fn poll_order<'a>(slave_name: &'a str, name: &'a str, modbus_data: &ModbusData) -> (Reverse<u8>, &'a str, BlockType, u8, &'a str) {
 
-    ($slave_info:expr, $key:expr) => {
-    
-        match $slave_info.get(&$key) {
-            Some(value) => match value.as_sequence() {
-                Some(map) => Some(map),
-                None => {
-                    panic!("Invaild value of data block, required sequence");
-                },
-            },
-            None => None,
-        }
+    (Reverse(modbus_data.poll_priority), slave_name, modbus_data.block_type, modbus_data.address, name)
 
-    };
+}
 
+#[derive(PartialEq)]
+pub enum ConfigWarning {
+    OverlappingRegisters { a: String, b: String },
+    TypeMismatch { name: String, block_type: BlockType, value_type: ValueType }, // coils and discrete inputs only hold bools
 }
 
-fn load_data_block(block_type: BlockType, block_infos: &Vec<Value>, map: &mut HashMap<String, ModbusData>) {
+// datapoints of one slave by block, names are unique across blocks
+#[derive(Clone, Default, PartialEq)]
+pub struct ModbusDataMap {
+    co: HashMap<String, ModbusData>,
+    di: HashMap<String, ModbusData>,
+    hr: HashMap<String, ModbusData>,
+    ir: HashMap<String, ModbusData>,
+}
 
-    for _block_info in block_infos {
+impl ModbusDataMap {
 
-        let block_map = match _block_info
-            .as_mapping() {
-            Some(map) => match map.len() {
-                1 => map,
-                _ => {
-                    panic!("Invaild data block format");
-                },
-            },
-            None => {
-                panic!("Invaild data block format");
-            },
-        };
-        for (_block_name, block_info) in block_map {
+    pub fn new() -> Self {
 
-            let block_name = _block_name
-                .as_str()
-                .expect(invailed_type_message!("block name", "string"));
+        ModbusDataMap::default()
 
-            let (address_key, value_type_key, function_key) = (
-                Value::String(String::from("addr")),
-                Value::String(String::from("type")),
-                Value::String(String::from("func")),
-            );
-    
-            let address_u64 = block_info.get(address_key)
-                .expect(missing_required_message!("addr"))
-                .as_u64()
-                .expect(invailed_type_message!("addr", "string"));
-            let address;
-            if address_u64 < u8::MAX as u64 {
-                address = address_u64 as u8;
-            } else {
-                panic!("{}", invailed_value_message!("addr", address_u64));
-            }
-    
-            let mut value_type ;
-            match block_type {
-                BlockType::Co | BlockType::Di => {
-                    value_type = ValueType::Bool
-                }
-                BlockType::Hr | BlockType::Ir => {
-                    value_type = ValueType::Bool
-                }
-            }
-            let value_type_option = block_info.get(value_type_key);
-            if value_type_option.is_some() {
-                let value_type_str = value_type_option
-                    .unwrap()
-                    .as_str()
-                    .expect(invailed_type_message!("type", "string"));
-                value_type = match value_type_str.to_lowercase().as_str() {
-                    "bool" => ValueType::Bool,
-                    "u16" => ValueType::U16,
-                    "i16" => ValueType::I16,
-                    "u32" => ValueType::U32,
-                    "i32" => ValueType::I32,
-                    "f32" => ValueType::F32,
-                    _ => {
-                        panic!("{}", invailed_value_message!("type", value_type_str));
-                    }
-                };
-            }
-            
-            let mut requestfunction = RequestFunction::Multiple;
-            if block_type == BlockType::Co || block_type == BlockType::Hr {
-                let function_option = block_info.get(function_key);
-                if function_option.is_some() {
-                    let function_str = match function_option
-                        .unwrap()
-                        .as_str() {
-                            Some(str) => str,
-                            None => {
-                                panic!("{}", invailed_type_message!("func", "string"));
-                            },
-                    };
-                    requestfunction = match function_str.to_ascii_lowercase().as_str() {
-                        "single" => RequestFunction::Single,
-                        "multiple" => RequestFunction::Multiple,
-                        _ => {
-                            panic!("{}", invailed_value_message!("func", function_str));
-                        },
-                    }
-                }
-            }
-    
-            map.insert(String::from(block_name), ModbusData {
-                address: address,
-                block_type: block_type,
-                value_type: value_type,
-                requestfunction: requestfunction,
-            });
+    }
+
+    fn block(&self, block: BlockType) -> &HashMap<String, ModbusData> {
 
+        match block {
+            BlockType::Co => &self.co,
+            BlockType::Di => &self.di,
+            BlockType::Hr => &self.hr,
+            BlockType::Ir => &self.ir,
         }
 
     }
 
-}
+    fn block_mut(&mut self, block: BlockType) -> &mut HashMap<String, ModbusData> {
 
-impl Interface {
-   
-    pub fn from_yaml(yaml_filename: &str) -> Interface {
-    
-        let yaml_file = File::open(yaml_filename)
-            .expect(format!("Could not open file '{}'", yaml_filename).as_str());
-    
-        let yaml_config: Value = serde_yaml::from_reader(yaml_file)
-            .expect(format!("Failed to parse yaml file '{}'", yaml_filename).as_str());
+        match block {
+            BlockType::Co => &mut self.co,
+            BlockType::Di => &mut self.di,
+            BlockType::Hr => &mut self.hr,
+            BlockType::Ir => &mut self.ir,
+        }
 
-        let protocol_name = get_yaml_string!(&yaml_config, "protocol");
-        let protocol_cased = protocol_name.to_lowercase();
+    }
+
+    pub fn insert(&mut self, block: BlockType, name: String, data: ModbusData) {
+
+        self.block_mut(block).insert(name, data);
+
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<ModbusData> {
+
+        [BlockType::Co, BlockType::Di, BlockType::Hr, BlockType::Ir].into_iter()
+            .find_map(|block| self.block_mut(block).remove(name))
+
+    }
+
+    pub fn find(&self, name: &str) -> Option<&ModbusData> {
+
+        [BlockType::Co, BlockType::Di, BlockType::Hr, BlockType::Ir].into_iter()
+            .find_map(|block| self.find_in_block(block, name))
+
+    }
+
+    pub fn find_in_block(&self, block: BlockType, name: &str) -> Option<&ModbusData> {
+
+        self.block(block).get(name)
+
+    }
+
+    pub fn iter_block(&self, block: BlockType) -> impl Iterator<Item = (&str, &ModbusData)> {
+
+        self.block(block).iter().map(|(name, modbus_data)| (name.as_str(), modbus_data))
+
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+
+        [&self.co, &self.di, &self.hr, &self.ir].into_iter()
+            .flat_map(|map| map.keys().map(String::as_str))
+
+    }
+
+    pub fn total_count(&self) -> usize {
+
+        self.co.len() + self.di.len() + self.hr.len() + self.ir.len()
+
+    }
+
+}
+
+#[derive(Clone, PartialEq)]
+pub struct SlaveData {
+    id: u8,
+    registers: ModbusDataMap,
+    wakeup: Option<u16>, // holding register read to wake the slave before a batch
+    id_range: Option<(u8, u8)>, // parametric slave addressed as '<name>_<unit>'
+    aliases: HashMap<String, String>, // alias -> canonical datapoint name
+    min_read_quantity: u16, // shorter reads are padded up to this many registers or coils
+}
+
+impl SlaveData {
+
+    pub fn new(id: u8, registers: ModbusDataMap) -> Self {
+
+        SlaveData {
+            id,
+            registers,
+            wakeup: None,
+            id_range: None,
+            aliases: HashMap::new(),
+            min_read_quantity: 1,
+        }
+
+    }
+
+    pub fn id(&self) -> u8 {
+
+        self.id
+
+    }
+
+    pub fn wakeup(&self) -> Option<u16> {
+
+        self.wakeup
+
+    }
+
+    pub fn min_read_quantity(&self) -> u16 {
+
+        self.min_read_quantity
+
+    }
+
+    pub fn id_range(&self) -> Option<(u8, u8)> {
+
+        self.id_range
+
+    }
+
+    // the unit ids answered, as written in the config
+    fn unit_ids(&self) -> String {
+
+        match self.id_range {
+            Some((first, last)) => format!("{}-{}", first, last),
+            None => self.id.to_string(),
+        }
+
+    }
+
+    pub fn canonical<'a>(&'a self, name: &'a str) -> &'a str {
+
+        let name = name.trim();
+        self.aliases.get(name).map(String::as_str).unwrap_or(name)
+
+    }
+
+    pub fn registers(&self) -> &ModbusDataMap {
+
+        &self.registers
+
+    }
+
+    pub fn find(&self, name: &str) -> Option<ModbusData> {
+
+        self.registers.find(self.canonical(name)).cloned()
+
+    }
+
+    pub fn suggest(&self, name: &str) -> Vec<&str> {
+
+        let names = self.registers.names()
+            .chain(self.aliases.keys().map(String::as_str));
+
+        closest_names(name, names)
+
+    }
+
+    pub fn find_by_address(&self, block: BlockType, address: u16) -> Option<(&str, &ModbusData)> {
+
+        // multi-register points also match on their trailing registers
+        self.registers.iter_block(block)
+            .find(|(_, modbus_data)| modbus_data.address_range().contains(&address))
+
+    }
+
+    pub fn layout(&self, block: BlockType) -> Vec<(u16, u16, &str)> {
+
+        // (start, length, name), scattered points occupy one span per word
+        let mut spans = Vec::new();
+        for (name, modbus_data) in self.registers.iter_block(block) {
+            match &modbus_data.words {
+                Some(words) => for word in words {
+                    spans.push((*word as u16, 1, name));
+                },
+                None => {
+                    let range = modbus_data.address_range();
+                    spans.push((*range.start(), range.len() as u16, name));
+                },
+            }
+        }
+        spans.sort();
+
+        spans
+
+    }
+
+    pub fn add_register(&mut self, block: BlockType, name: String, data: ModbusData) -> Result<(), ConfigError> {
+
+        if name.trim() != name || name.is_empty() {
+            return Err(ConfigError::Invalid(format!("Invaild datapoint name '{}'", name)));
+        }
+        if self.registers.find(&name).is_some() || self.aliases.contains_key(&name) {
+            return Err(ConfigError::Invalid(format!("Duplicate datapoint '{}'", name)));
+        }
+        if data.block_type != block {
+            return Err(ConfigError::Invalid(format!("'{}' belongs to {}, not {}", name,
+                data.block_type.to_string().to_lowercase(), block.to_string().to_lowercase())));
+        }
+        // views may overlap on purpose, anything else needs registers of its own
+        if !data.view {
+            let occupied = data.occupied();
+            let conflict = self.registers.iter_block(block)
+                .find(|(_, modbus_data)| !modbus_data.view && modbus_data.occupied().iter().any(|address| occupied.contains(address)));
+            if let Some((other_name, _)) = conflict {
+                return Err(ConfigError::Invalid(format!("'{}' overlaps '{}'", name, other_name)));
+            }
+        }
+        self.registers.insert(block, name, data);
+
+        Ok(())
+
+    }
+
+    pub fn remove_register(&mut self, name: &str) -> bool {
+
+        let name = String::from(self.canonical(name));
+        if self.registers.remove(&name).is_none() {
+            return false;
+        }
+        self.aliases.retain(|_, target| *target != name);
+
+        true
+
+    }
+
+    pub fn validate(&self) -> Vec<ConfigWarning> {
+
+        let mut warnings = Vec::new();
+        let is_view = |name: &str| self.registers.find(name).is_some_and(ModbusData::view);
+        for block in [BlockType::Co, BlockType::Di, BlockType::Hr, BlockType::Ir] {
+            let spans = self.layout(block);
+            for (index, (start, len, a)) in spans.iter().enumerate() {
+                let end = start + len - 1;
+                for (next_start, _, b) in &spans[index + 1..] {
+                    if *next_start > end {
+                        break;
+                    }
+                    let warning = ConfigWarning::OverlappingRegisters {
+                        a: a.to_string(),
+                        b: b.to_string(),
+                    };
+                    if a != b && !is_view(a) && !is_view(b) && !warnings.contains(&warning) {
+                        warnings.push(warning);
+                    }
+                }
+            }
+        }
+        for block_type in [BlockType::Co, BlockType::Di] {
+            let mut points: Vec<_> = self.registers.iter_block(block_type)
+                .filter(|(_, modbus_data)| modbus_data.value_type != ValueType::Bool)
+                .collect();
+            points.sort_by_key(|(name, _)| *name);
+            for (name, modbus_data) in points {
+                warnings.push(ConfigWarning::TypeMismatch {
+                    name: String::from(name),
+                    block_type,
+                    value_type: modbus_data.value_type,
+                });
+            }
+        }
+
+        warnings
+
+    }
+
+}
+
+
+// the file a config was loaded from, two configs compare equal wherever they came from
+#[derive(Clone, Default)]
+struct ConfigSource(Option<String>);
+
+impl PartialEq for ConfigSource {
+
+    fn eq(&self, _: &Self) -> bool {
+
+        true
+
+    }
+
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Interface {
+    modbusprotocol: ModbusProtocol,
+    address: String,
+    config: u32, // tcp port, serial baudrate or usb product id
+    flow_control: FlowControl, // rtu only
+    interframe_delay_ms: Option<u64>, // rtu only, forces a longer gap than 3.5 character times
+    watchdog: Option<Watchdog>, // rtu only
+    connect_timeout_ms: u64, // tcp only, bounds the connect attempt, not the requests
+    connect: ConnectMode, // eager interfaces are connected at startup
+    writes: WriteMode,
+    lenient_coil_echo: bool,
+    lenient_width: bool, // 32-bit points answered with too few words are zero-padded
+    max_batch: Option<usize>, // points per GET/SET on this bus
+    byte_order: Endian, // default for points that do not set their own
+    word_order: Endian,
+    auto_save: bool, // runtime register changes are written back to the source file
+    strict: bool, // questionable points fail the load instead of being warned about
+    audit_log: Option<String>, // JSON lines file for writes, overrides the --audit-log default
+    source: ConfigSource,
+    pub slaves: HashMap<String, SlaveData>,
+}
+
+impl Interface {
+    
+    pub fn modbusprotocol(&self) -> ModbusProtocol {
+
+        self.modbusprotocol
+        
+    }
+    
+    pub fn address(&self) -> String {
+
+        self.address.clone()
+        
+    }
+    
+    pub fn config(&self) -> u32 {
+
+        self.config
+        
+    }
+
+    pub fn usb_hid_ids(&self) -> Option<(u16, u16)> {
+
+        // vendor id is kept in the address, product id in the config
+        match self.modbusprotocol {
+            #[cfg(feature = "usb_hid")]
+            ModbusProtocol::UsbHid => {
+                let vendor_id = u16::from_str_radix(self.address.trim_start_matches("0x"), 16).ok()?;
+                Some((vendor_id, self.config as u16))
+            },
+            _ => None,
+        }
+
+    }
+
+    pub fn flow_control(&self) -> FlowControl {
+
+        self.flow_control
+
+    }
+
+    pub fn interframe_gap(&self) -> Option<Duration> {
+
+        if self.modbusprotocol != ModbusProtocol::Rtu {
+            return None;
+        }
+
+        // 3.5 characters of 10 bits (8N1), fixed at 1.75ms above 19200 baud as the spec allows
+        let character_gap = match self.config {
+            0 => Duration::ZERO,
+            baudrate if baudrate > 19200 => Duration::from_micros(1750),
+            baudrate => Duration::from_micros(35_000_000 / baudrate as u64),
+        };
+
+        Some(character_gap.max(Duration::from_millis(self.interframe_delay_ms.unwrap_or(0))))
+
+    }
+
+    pub fn connect_timeout(&self) -> Duration {
+
+        Duration::from_millis(self.connect_timeout_ms)
+
+    }
+
+    pub fn watchdog(&self) -> Option<Watchdog> {
+
+        self.watchdog
+
+    }
+
+    pub fn connect(&self) -> ConnectMode {
+
+        self.connect
+
+    }
+
+    pub fn writes(&self) -> WriteMode {
+
+        self.writes
+
+    }
+
+    pub fn lenient_coil_echo(&self) -> bool {
+
+        self.lenient_coil_echo
+
+    }
+
+    pub fn lenient_width(&self) -> bool {
+
+        self.lenient_width
+
+    }
+
+    pub fn strict(&self) -> bool {
+
+        self.strict
+
+    }
+
+    pub fn max_batch(&self) -> Option<usize> {
+
+        self.max_batch
+
+    }
+
+    pub fn byte_order(&self) -> Endian {
+
+        self.byte_order
+
+    }
+
+    pub fn word_order(&self) -> Endian {
+
+        self.word_order
+
+    }
+
+    pub fn resolve_slave(&self, slave_name: &str) -> Option<(&SlaveData, u8)> {
+
+        // plain slaves match by name, parametric ones by '<name>_<unit>' within their id_range
+        if let Some(slave) = self.slaves.get(slave_name) {
+            if slave.id_range.is_none() {
+                return Some((slave, slave.id));
+            }
+        }
+        let (base_name, unit) = slave_name.rsplit_once('_')?;
+        let unit: u8 = unit.parse().ok()?;
+        let slave = self.slaves.get(base_name)?;
+        let (first, last) = slave.id_range?;
+
+        (first..=last).contains(&unit).then_some((slave, unit))
+
+    }
+
+    // (slave, point, data) with the highest poll_priority first, equal priorities in address order;
+    // the order SNAPSHOT reads in, for a poller to build its queue from once there is one
+    pub fn registers_by_priority(&self) -> Vec<(&str, &str, &ModbusData)> {
+
+        let mut registers: Vec<(&str, &str, &ModbusData)> = self.slaves.iter()
+            .flat_map(|(slave_name, slave)| [BlockType::Co, BlockType::Di, BlockType::Hr, BlockType::Ir].into_iter()
+                .flat_map(|block_type| slave.registers().iter_block(block_type))
+                .map(move |(name, modbus_data)| (slave_name.as_str(), name, modbus_data)))
+            .collect();
+        registers.sort_by_key(|(slave_name, name, modbus_data)| poll_order(slave_name, name, modbus_data));
+
+        registers
+
+    }
+
+    // swaps one slave's register map in place, the unit id it answers to must stay the same
+    pub fn update_slave(&mut self, name: &str, slave: SlaveData) -> Result<(), ConfigError> {
+
+        let current = self.slaves.get_mut(name)
+            .ok_or_else(|| ConfigError::Invalid(format!("Unknown slave '{}'", name)))?;
+        if (current.id, current.id_range) != (slave.id, slave.id_range) {
+            return Err(ConfigError::SlaveIdChanged {
+                slave: String::from(name),
+                old: current.unit_ids(),
+                new: slave.unit_ids(),
+            });
+        }
+        for warning in slave.validate() {
+            warn!("'{}': {}", name, warning);
+        }
+        *current = slave;
+
+        self.save()
+
+    }
+
+    pub fn add_register(&mut self, slave_name: &str, block: BlockType, name: String, data: ModbusData) -> Result<(), ConfigError> {
+
+        self.slaves.get_mut(slave_name)
+            .ok_or_else(|| ConfigError::Invalid(format!("Unknown slave '{}'", slave_name)))?
+            .add_register(block, name, data)?;
+
+        self.save()
+
+    }
+
+    pub fn remove_register(&mut self, slave_name: &str, name: &str) -> Result<bool, ConfigError> {
+
+        let removed = self.slaves.get_mut(slave_name)
+            .ok_or_else(|| ConfigError::Invalid(format!("Unknown slave '{}'", slave_name)))?
+            .remove_register(name);
+        if removed {
+            self.save()?;
+        }
+
+        Ok(removed)
+
+    }
+
+    pub fn auto_save(&self) -> bool {
+
+        self.auto_save
+
+    }
+
+    pub fn audit_log(&self) -> Option<&str> {
+
+        self.audit_log.as_deref()
+
+    }
+
+    fn save(&self) -> Result<(), ConfigError> {
+
+        if !self.auto_save {
+            return Ok(());
+        }
+        // comments and anchors of the hand written file do not survive the rewrite
+        let file_name = self.source.0.as_ref()
+            .ok_or_else(|| ConfigError::Save(String::from("auto_save is set but the config was not loaded from a file")))?;
+        fs::write(file_name, self.to_yaml_string())
+            .map_err(|e| ConfigError::Save(format!("'{}': {}", file_name, e)))?;
+        info!("Config saved to '{}'", file_name);
+
+        Ok(())
+
+    }
+
+    pub fn slave_names(&self) -> Vec<String> {
+
+        let mut slave_names = Vec::new();
+        for (slave_name, slave) in &self.slaves {
+            match slave.id_range {
+                Some((first, last)) => slave_names.extend((first..=last).map(|unit| format!("{}_{}", slave_name, unit))),
+                None => slave_names.push(slave_name.clone()),
+            }
+        }
+        slave_names.sort();
+
+        slave_names
+
+    }
+
+    // the connection of the session with the one scanned slave, named 'slave_<id>' as in from_csv
+    pub async fn from_modbus_scan(session: &mut ModbusSession, slave_id: u8) -> Interface {
+
+        let mut interface = Interface {
+            source: ConfigSource::default(),
+            slaves: HashMap::new(),
+            ..session.interface.clone()
+        };
+        interface.slaves.insert(format!("slave_{}", slave_id), modbus::scan_slave(&mut session.context, slave_id).await);
+
+        interface
+
+    }
+
+}
+
+macro_rules! missing_required {
+    ($key:expr) => {
+        ConfigError::Invalid(format!("Missing required '{}'", $key))
+    };
+}
+
+macro_rules! invailed_type {
+    ($type:expr, $required:expr) => {
+        ConfigError::Invalid(format!("Invild type of '{}', required {}", $type, $required))
+    };
+}
+
+macro_rules! invailed_value {
+    ($name:expr, $value:expr) => {
+        ConfigError::Invalid(format!("Invaild value of '{}': '{}'", $name, $value))
+    };
+}
+
+macro_rules! invailed_config {
+    ($($arg:tt)*) => {
+        ConfigError::Invalid(format!($($arg)*))
+    };
+}
+
+macro_rules! get_yaml_string {
+
+    ($object:expr, $key:expr) => {
+
+        String::from($object.get($key)
+            .ok_or_else(|| missing_required!($key))?
+            .as_str()
+            .ok_or_else(|| invailed_type!($key, "string"))?
+        )
+
+    };
+
+}
+
+macro_rules! get_modbus_block_value {
+
+    ($slave_info:expr, $key:expr) => {
+    
+        match $slave_info.get(&$key) {
+            Some(value) => match value.as_sequence() {
+                Some(map) => Some(map),
+                None => {
+                    return Err(invailed_config!("Invaild value of data block, required sequence"));
+                },
+            },
+            None => None,
+        }
+
+    };
+
+}
+
+pub enum ConfigError {
+    Parse(String),
+    UnsupportedFormat(String),
+    Invalid(String),
+    SlaveIdChanged { slave: String, old: String, new: String }, // a live update would address another device, ids or id ranges
+    Save(String), // a runtime change could not be written back
+    TypeMismatch { name: String, block_type: BlockType, value_type: ValueType }, // ConfigWarning::TypeMismatch with strict set
+}
+
+// two numeric columns per line, raw then engineering value; a header line is skipped
+fn load_cal_table(path: &str) -> Result<CalTable, ConfigError> {
+
+    let text = fs::read_to_string(path)
+        .map_err(|e| invailed_config!("Could not open cal_table '{}': {}", path, e))?;
+
+    let mut rows = Vec::new();
+    let mut first_line = true;
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        // a header may follow leading blank lines
+        let is_header = std::mem::replace(&mut first_line, false);
+        let row = match line.split(',').map(|field| field.trim().parse::<f64>()).collect::<Result<Vec<f64>, _>>() {
+            Ok(row) if row.len() == 2 && row.iter().all(|field| field.is_finite()) => (row[0], row[1]),
+            Err(_) if is_header => continue,
+            _ => return Err(invailed_config!("Invaild cal_table '{}': line {}: '{}'", path, index + 1, line)),
+        };
+        rows.push(row);
+    }
+    rows.sort_by(|a, b| a.0.total_cmp(&b.0));
+    if rows.len() < 2 {
+        return Err(invailed_config!("Invaild cal_table '{}': at least two rows are required", path));
+    }
+    if let Some(pair) = rows.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+        return Err(invailed_config!("Invaild cal_table '{}': raw value {} is listed more than once", path, pair[0].0));
+    }
+
+    Ok(CalTable { path: String::from(path), rows })
+
+}
+
+fn parse_address(value: &Value, name: &str) -> Result<u8, ConfigError> {
+
+    let address_u64 = value
+        .as_u64()
+        .ok_or_else(|| invailed_type!(name, "unsigned integetr"))?;
+    if address_u64 < u8::MAX as u64 {
+        Ok(address_u64 as u8)
+    } else {
+        Err(invailed_value!(name, address_u64))
+    }
+
+}
+
+fn parse_id_range(value: &Value) -> Result<(u8, u8), ConfigError> {
+
+    // "first-last", both unit ids inclusive
+    let range_str = value
+        .as_str()
+        .ok_or_else(|| invailed_type!("id_range", "string"))?;
+    let (first, last) = range_str.split_once('-')
+        .ok_or_else(|| invailed_value!("id_range", range_str))?;
+    match (first.trim().parse::<u8>(), last.trim().parse::<u8>()) {
+        (Ok(first), Ok(last)) if first <= last && last < u8::MAX => Ok((first, last)),
+        _ => Err(invailed_value!("id_range", range_str)),
+    }
+
+}
+
+fn parse_endian(value: &Value, name: &str) -> Result<Endian, ConfigError> {
+
+    let endian_str = value
+        .as_str()
+        .ok_or_else(|| invailed_type!(name, "string"))?;
+    match endian_str.to_lowercase().as_str() {
+        "big" => Ok(Endian::Big),
+        "little" => Ok(Endian::Little),
+        _ => Err(invailed_value!(name, endian_str)),
+    }
+
+}
+
+const CONNECT_TIMEOUT_MS: u64 = 5000; // a filtered host would otherwise hang for the os default
+
+const PULSE_MS_MAX: u64 = 10000; // the server waits for the pulse to finish
+
+fn load_data_block(block_type: BlockType, block_infos: &[Value], defaults: (Endian, Endian), strict: bool, registers: &mut ModbusDataMap, aliases: &mut HashMap<String, String>) -> Result<(), ConfigError> {
+
+    for _block_info in block_infos {
+
+        let block_map = match _block_info
+            .as_mapping() {
+            Some(map) => match map.len() {
+                1 => map,
+                _ => {
+                    return Err(invailed_config!("Invaild data block format"));
+                },
+            },
+            None => {
+                return Err(invailed_config!("Invaild data block format"));
+            },
+        };
+        for (_block_name, block_info) in block_map {
+
+            let untrimmed_name = _block_name
+                .as_str()
+                .ok_or_else(|| invailed_type!("block name", "string"))?;
+            // hand edited yaml easily picks up stray spaces that no request path would match
+            let block_name = untrimmed_name.trim();
+            if block_name != untrimmed_name {
+                warn!("Point name '{}' has leading or trailing whitespace, loaded as '{}'", untrimmed_name, block_name);
+            }
+            if registers.find(block_name).is_some() {
+                return Err(invailed_config!("Point '{}' is defined more than once, names are compared without surrounding whitespace", block_name));
+            }
+
+            let (address_key, value_type_key, function_key, count_key) = (
+                Value::String(String::from("addr")),
+                Value::String(String::from("type")),
+                Value::String(String::from("func")),
+                Value::String(String::from("count")),
+            );
+
+            let words: Option<Vec<u8>> = match block_info.get("words") {
+                Some(words_option) => {
+                    if block_type == BlockType::Co || block_type == BlockType::Di {
+                        return Err(invailed_config!("'{}': words are only supported in hr and ir blocks", block_name));
+                    }
+                    Some(words_option.as_sequence()
+                        .ok_or_else(|| invailed_type!("words", "sequence"))?
+                        .iter()
+                        .map(|word| parse_address(word, "words"))
+                        .collect::<Result<_, _>>()?)
+                },
+                None => None,
+            };
+    
+            let address = match (block_info.get(address_key), &words) {
+                (Some(address_option), _) => parse_address(address_option, "addr")?,
+                (None, Some(words)) if !words.is_empty() => words[0],
+                _ => return Err(missing_required!("addr")),
+            };
+    
+            let mut value_type ;
+            match block_type {
+                BlockType::Co | BlockType::Di => {
+                    value_type = ValueType::Bool
+                }
+                BlockType::Hr | BlockType::Ir => {
+                    value_type = ValueType::Bool
+                }
+            }
+            if let Some(value_type_option) = block_info.get(value_type_key) {
+                let value_type_str = value_type_option
+                    .as_str()
+                    .ok_or_else(|| invailed_type!("type", "string"))?;
+                value_type = match value_type_str.to_lowercase().as_str() {
+                    "bool" => ValueType::Bool,
+                    "u16" => ValueType::U16,
+                    "i16" => ValueType::I16,
+                    "u32" => ValueType::U32,
+                    "i32" => ValueType::I32,
+                    "f32" => ValueType::F32,
+                    "bcd16" => ValueType::BcdU16,
+                    "bcd32" => ValueType::BcdU32,
+                    name => match modbus::custom_value_type(name) {
+                        Some(custom) => custom,
+                        None => return Err(invailed_value!("type", value_type_str)),
+                    },
+                };
+            }
+            if matches!(block_type, BlockType::Co | BlockType::Di) && value_type != ValueType::Bool {
+                let name = String::from(block_name);
+                // otherwise left to SlaveData::validate
+                if strict {
+                    return Err(ConfigError::TypeMismatch { name, block_type, value_type });
+                }
+            }
+            
+            let mut requestfunction = RequestFunction::Multiple;
+            if block_type == BlockType::Co || block_type == BlockType::Hr {
+                if let Some(function_option) = block_info.get(function_key) {
+                    let function_str = match function_option
+                        .as_str() {
+                            Some(str) => str,
+                            None => {
+                                return Err(invailed_type!("func", "string"));
+                            },
+                    };
+                    requestfunction = match function_str.to_ascii_lowercase().as_str() {
+                        "single" => RequestFunction::Single,
+                        "multiple" => RequestFunction::Multiple,
+                        _ => {
+                            return Err(invailed_value!("func", function_str));
+                        },
+                    }
+                }
+            }
+
+            let mut count = value_type.size() as u16;
+            if let Some(count_option) = block_info.get(count_key) {
+                let count_u64 = count_option
+                    .as_u64()
+                    .ok_or_else(|| invailed_type!("count", "unsigned integetr"))?;
+                let count_max = match block_type {
+                    BlockType::Co | BlockType::Di => 1968,
+                    BlockType::Hr | BlockType::Ir => 123,
+                };
+                if count_u64 == 0 || count_u64 > count_max {
+                    return Err(invailed_value!("count", count_u64));
+                }
+                count = count_u64 as u16;
+                if block_type == BlockType::Co && count > 1 && requestfunction == RequestFunction::Single {
+                    return Err(invailed_config!("'{}' uses func single, which writes exactly one coil", block_name));
+                }
+                if (block_type == BlockType::Hr || block_type == BlockType::Ir) && (count as usize) < value_type.size() {
+                    warn!("'{}' of type {} only accesses {} register(s), value will not fit", block_name, value_type, count);
+                }
+            }
+            if let Some(words) = &words {
+                if words.len() != value_type.size() {
+                    return Err(invailed_config!("'{}' of type {} needs {} word address(es), got {}", block_name, value_type, value_type.size(), words.len()));
+                }
+                count = words.len() as u16;
+            }
+            // a scattered point is written one word at a time anyway, func only picks the function code there
+            if block_type == BlockType::Hr && count > 1 && words.is_none() && requestfunction == RequestFunction::Single {
+                if strict {
+                    return Err(invailed_config!("'{}' uses func single, which writes exactly one register, {} needs {}", block_name, value_type, count));
+                }
+                warn!("'{}' uses func single, which writes exactly one register, writing all {} with func multiple", block_name, count);
+                requestfunction = RequestFunction::Multiple;
+            }
+
+            let (mut byte_order, mut word_order) = defaults;
+            for (key, endian) in [("byte_order", &mut byte_order), ("word_order", &mut word_order)] {
+                if let Some(endian_option) = block_info.get(key) {
+                    if block_type == BlockType::Co || block_type == BlockType::Di {
+                        return Err(invailed_config!("'{}': {} is only supported in hr and ir blocks", block_name, key));
+                    }
+                    *endian = parse_endian(endian_option, key)?;
+                }
+            }
+
+            let pulse_ms = match block_info.get("pulse_ms") {
+                Some(pulse_option) => {
+                    if block_type != BlockType::Co || count != 1 {
+                        return Err(invailed_config!("'{}': pulse_ms is only supported on single coils", block_name));
+                    }
+                    let pulse_u64 = pulse_option
+                        .as_u64()
+                        .ok_or_else(|| invailed_type!("pulse_ms", "unsigned integetr"))?;
+                    if pulse_u64 == 0 || pulse_u64 > PULSE_MS_MAX {
+                        return Err(invailed_value!("pulse_ms", pulse_u64));
+                    }
+                    Some(pulse_u64)
+                },
+                None => None,
+            };
+
+            let active_low = match block_info.get("active_low") {
+                Some(active_low_option) => {
+                    if block_type != BlockType::Di {
+                        return Err(invailed_config!("'{}': active_low is only supported on discrete inputs", block_name));
+                    }
+                    active_low_option
+                        .as_bool()
+                        .ok_or_else(|| invailed_type!("active_low", "bool"))?
+                },
+                None => false,
+            };
+
+            let flags = match block_info.get("flags") {
+                Some(flags_value) => {
+                    if !matches!(block_type, BlockType::Hr | BlockType::Ir) || !matches!(value_type, ValueType::U16 | ValueType::U32) {
+                        return Err(invailed_config!("'{}': flags are only supported on u16 and u32 registers", block_name));
+                    }
+                    let mut flags = BTreeMap::new();
+                    for (bit_value, name_value) in flags_value.as_mapping().ok_or_else(|| invailed_type!("flags", "mapping"))? {
+                        let bit = bit_value.as_u64()
+                            .ok_or_else(|| invailed_type!("flag bit", "unsigned integetr"))?;
+                        if bit >= value_type.size() as u64 * 16 {
+                            return Err(invailed_value!("flag bit", bit));
+                        }
+                        let name = name_value.as_str()
+                            .ok_or_else(|| invailed_type!("flag name", "string"))?;
+                        if flags.values().any(|flag| flag == name) {
+                            return Err(invailed_config!("'{}': flag '{}' is used more than once", block_name, name));
+                        }
+                        flags.insert(bit as u8, String::from(name));
+                    }
+                    Some(flags)
+                },
+                None => None,
+            };
+
+            // fail-safe wiring, the raw state is the opposite of the logical one
+            let invert = match block_info.get("invert") {
+                Some(invert_option) => {
+                    let is_bool = matches!(block_type, BlockType::Co | BlockType::Di) || value_type == ValueType::Bool;
+                    if !is_bool && flags.is_none() {
+                        return Err(invailed_config!("'{}': invert is only supported on bool points and flags", block_name));
+                    }
+                    if active_low {
+                        return Err(invailed_config!("'{}': active_low and invert can not be combined", block_name));
+                    }
+                    invert_option
+                        .as_bool()
+                        .ok_or_else(|| invailed_type!("invert", "bool"))?
+                },
+                None => false,
+            };
+
+            let view = match block_info.get("view") {
+                Some(view_option) => view_option
+                    .as_bool()
+                    .ok_or_else(|| invailed_type!("view", "bool"))?,
+                None => false,
+            };
+
+            let description = match block_info.get("description") {
+                Some(description_option) => Some(String::from(description_option
+                    .as_str()
+                    .ok_or_else(|| invailed_type!("description", "string"))?)),
+                None => None,
+            };
+
+            let unit = match block_info.get("unit") {
+                Some(unit_option) => Some(String::from(unit_option
+                    .as_str()
+                    .ok_or_else(|| invailed_type!("unit", "string"))?)),
+                None => None,
+            };
+
+            let poll_priority = match block_info.get("poll_priority") {
+                Some(priority_option) => {
+                    let priority_u64 = priority_option
+                        .as_u64()
+                        .ok_or_else(|| invailed_type!("poll_priority", "unsigned integetr"))?;
+                    u8::try_from(priority_u64).map_err(|_| invailed_value!("poll_priority", priority_u64))?
+                },
+                None => 0,
+            };
+
+            let cal_table = match block_info.get("cal_table") {
+                Some(cal_table_option) => {
+                    if !matches!(block_type, BlockType::Hr | BlockType::Ir) || matches!(value_type, ValueType::Bool | ValueType::Custom(_)) || flags.is_some() {
+                        return Err(invailed_config!("'{}': cal_table is only supported on numeric registers", block_name));
+                    }
+                    Some(load_cal_table(cal_table_option
+                        .as_str()
+                        .ok_or_else(|| invailed_type!("cal_table", "string"))?)?)
+                },
+                None => None,
+            };
+
+            let shadow = match block_info.get("shadow_register") {
+                Some(shadow_value) => {
+                    if !matches!(block_type, BlockType::Hr | BlockType::Ir) || words.is_some() {
+                        return Err(invailed_config!("'{}': shadow_register is only supported on contiguous registers", block_name));
+                    }
+                    let formula = match block_info.get("shadow_formula") {
+                        Some(formula_value) => {
+                            let formula_str = formula_value
+                                .as_str()
+                                .ok_or_else(|| invailed_type!("shadow_formula", "string"))?;
+                            match formula_str {
+                                "invert" => ShadowFormula::Invert,
+                                "copy" => ShadowFormula::Copy,
+                                _ => return Err(invailed_value!("shadow_formula", formula_str)),
+                            }
+                        },
+                        None => ShadowFormula::Invert,
+                    };
+                    Some(Shadow { address: parse_address(shadow_value, "shadow_register")?, formula })
+                },
+                None if block_info.get("shadow_formula").is_some() => return Err(missing_required!("shadow_register")),
+                None => None,
+            };
+
+            // one alias or a list of them, collisions are checked once the whole slave is loaded
+            let alias_list = match block_info.get("alias") {
+                Some(Value::String(alias)) => vec![alias.clone()],
+                Some(Value::Sequence(alias_values)) => alias_values.iter()
+                    .map(|alias| alias.as_str().map(String::from).ok_or_else(|| invailed_type!("alias", "string")))
+                    .collect::<Result<Vec<String>, ConfigError>>()?,
+                Some(_) => return Err(invailed_type!("alias", "string or sequence")),
+                None => Vec::new(),
+            };
+            for alias in alias_list {
+                if aliases.insert(alias.clone(), String::from(block_name)).is_some() {
+                    return Err(invailed_config!("Alias '{}' is used more than once", alias));
+                }
+            }
+    
+            registers.insert(block_type, String::from(block_name), ModbusData {
+                address,
+                block_type,
+                value_type,
+                requestfunction,
+                count,
+                words,
+                byte_order,
+                word_order,
+                pulse_ms,
+                active_low,
+                invert,
+                flags,
+                view,
+                description,
+                unit,
+                poll_priority,
+                cal_table,
+                shadow,
+            });
+
+        }
+
+    }
+
+    Ok(())
+
+}
+
+impl Interface {
+   
+    pub fn from_yaml(yaml_filename: &str) -> Interface {
+    
+        Interface::load(yaml_filename)
+            .unwrap_or_else(|e| panic!("Failed to load '{}': {}", yaml_filename, e))
+    
+    }
+
+    pub fn load(yaml_filename: &str) -> Result<Interface, ConfigError> {
+
+        let data = fs::read(yaml_filename)
+            .map_err(|e| invailed_config!("Could not open file '{}': {}", yaml_filename, e))?;
+
+        let mut interface = Interface::from_bytes(&data, "yaml")?;
+        interface.source = ConfigSource(Some(String::from(yaml_filename)));
+
+        Ok(interface)
+
+    }
+
+    pub fn from_bytes(data: &[u8], format_hint: &str) -> Result<Interface, ConfigError> {
+
+        let mut config: Value = match format_hint.to_lowercase().as_str() {
+            "yaml" | "yml" => serde_yaml::from_slice(data)
+                .map_err(|e| ConfigError::Parse(e.to_string()))?,
+            "json" => serde_json::from_slice(data)
+                .map_err(|e| ConfigError::Parse(e.to_string()))?,
+            "toml" => {
+                let text = std::str::from_utf8(data)
+                    .map_err(|e| ConfigError::Parse(e.to_string()))?;
+                toml::from_str(text)
+                    .map_err(|e| ConfigError::Parse(e.to_string()))?
+            },
+            _ => return Err(ConfigError::UnsupportedFormat(String::from(format_hint))),
+        };
+        // flatten '<<: *template' merge keys before any lookup
+        config.apply_merge()
+            .map_err(|e| ConfigError::Parse(e.to_string()))?;
+
+        Interface::from_value(&config)
+
+    }
+
+    pub fn from_uri(uri: &str) -> Result<Interface, ConfigError> {
+
+        // modbus+tcp://host[:port] or modbus+rtu:///dev/tty?baudrate=9600, other query
+        // parameters are passed on as config keys; the interface has no slaves yet
+        let (scheme, rest) = uri.split_once("://")
+            .ok_or_else(|| invailed_config!("Invaild uri '{}'", uri))?;
+        let (location, query) = rest.split_once('?').unwrap_or((rest, ""));
+
+        let mut config = Mapping::new();
+        match scheme {
+            "modbus+tcp" => {
+                // an IPv6 host is bracketed, its own colons do not separate the port
+                let (host, port) = match location.strip_prefix('[') {
+                    Some(bracketed) => match bracketed.split_once(']') {
+                        Some((host, "")) => (host, None),
+                        Some((host, port)) => (host, Some(port.strip_prefix(':')
+                            .ok_or_else(|| invailed_value!("address", location))?)),
+                        None => return Err(invailed_value!("address", location)),
+                    },
+                    None => match location.rsplit_once(':') {
+                        Some((host, _)) if host.contains(':') => return Err(invailed_value!("address", location)),
+                        Some((host, port)) => (host, Some(port)),
+                        None => (location, None),
+                    },
+                };
+                let port = match port {
+                    Some(port) => port.parse::<u64>().map_err(|_| invailed_value!("tcp_port", port))?,
+                    None => 502,
+                };
+                config.insert(Value::from("protocol"), Value::from("tcp"));
+                config.insert(Value::from("address"), Value::from(host));
+                config.insert(Value::from("tcp_port"), Value::from(port));
+            },
+            "modbus+rtu" => {
+                config.insert(Value::from("protocol"), Value::from("rtu"));
+                config.insert(Value::from("address"), Value::from(location));
+            },
+            _ => return Err(invailed_value!("scheme", scheme)),
+        }
+
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=')
+                .ok_or_else(|| invailed_config!("Invaild uri parameter '{}'", pair))?;
+            // serial framing is fixed at 8N1
+            if key == "parity" {
+                if value != "none" {
+                    return Err(invailed_value!("parity", value));
+                }
+                continue;
+            }
+            let value = match (value.parse::<u64>(), value.parse::<bool>()) {
+                (Ok(number), _) => Value::from(number),
+                (_, Ok(flag)) => Value::from(flag),
+                _ => Value::from(value),
+            };
+            config.insert(Value::from(key), value);
+        }
+        config.insert(Value::from("slaves"), Value::Sequence(Vec::new()));
+
+        Interface::from_value(&Value::Mapping(config))
+
+    }
+
+    // a vendor register map with one point per row, slaves are named 'slave_<id>'; the connection
+    // and interface settings are taken from `connection`, its own slaves are replaced
+    pub fn from_csv(path: &str, connection: &Interface) -> Result<Interface, ConfigError> {
+
+        // saving would write the csv slaves over the ones in the yaml
+        if connection.auto_save {
+            return Err(invailed_config!("'{}': auto_save can not be used with a csv register map", path));
+        }
+
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_path(path)
+            .map_err(|e| invailed_config!("Could not open '{}': {}", path, e))?;
+        let headers = reader.headers()
+            .map_err(|e| ConfigError::Parse(e.to_string()))?
+            .clone();
+        let column = |name: &str| headers.iter().position(|header| header.eq_ignore_ascii_case(name));
+        let mut required = Vec::new();
+        for name in ["slave_id", "block_type", "name", "address", "type"] {
+            required.push(column(name).ok_or_else(|| missing_required!(name))?);
+        }
+        let optional: Vec<(&str, Option<usize>)> = ["description", "unit", "scale", "offset", "min", "max"].into_iter()
+            .map(|name| (name, column(name)))
+            .collect();
+
+        // slave id -> block -> points, in file order within a block
+        let mut slaves: BTreeMap<u8, BTreeMap<String, Vec<Value>>> = BTreeMap::new();
+        let mut ignored = BTreeSet::new();
+        for record in reader.records() {
+            let record = record.map_err(|e| ConfigError::Parse(e.to_string()))?;
+            let line = record.position().map_or(0, |position| position.line());
+            let field = |index: usize| record.get(index).unwrap_or_default();
+            let [slave_id, block_type, name, address, value_type] = [0, 1, 2, 3, 4].map(|index| field(required[index]));
+
+            let slave_id: u8 = slave_id.parse()
+                .map_err(|_| invailed_config!("'{}' line {}: invaild slave_id '{}'", path, line, slave_id))?;
+            let block_type = block_type.to_lowercase();
+            if !["co", "di", "hr", "ir"].contains(&block_type.as_str()) {
+                return Err(invailed_config!("'{}' line {}: invaild block_type '{}'", path, line, block_type));
+            }
+            let address: u64 = address.parse()
+                .map_err(|_| invailed_config!("'{}' line {}: invaild address '{}'", path, line, address))?;
+
+            let mut point = Mapping::new();
+            point.insert(Value::from("addr"), Value::from(address));
+            if !value_type.is_empty() {
+                point.insert(Value::from("type"), Value::from(value_type.to_lowercase()));
+            }
+            for (key, index) in &optional {
+                let value = match index.map(field) {
+                    Some(value) if !value.is_empty() => value,
+                    _ => continue,
+                };
+                match *key {
+                    "description" | "unit" => { point.insert(Value::from(*key), Value::from(value)); },
+                    // points have no scaling or limits, the columns are skipped so vendor maps load as exported
+                    _ => { ignored.insert(*key); },
+                }
+            }
+            let mut entry = Mapping::new();
+            entry.insert(Value::from(name), Value::Mapping(point));
+            slaves.entry(slave_id).or_default()
+                .entry(block_type).or_default()
+                .push(Value::Mapping(entry));
+        }
+
+        if !ignored.is_empty() {
+            warn!("'{}': columns {} are ignored, raw register values are served", path,
+                ignored.into_iter().collect::<Vec<_>>().join(", "));
+        }
+
+        let mut slave_list = Vec::new();
+        for (slave_id, blocks) in slaves {
+            let mut slave_info = Mapping::new();
+            slave_info.insert(Value::from("id"), Value::from(slave_id));
+            for (block_type, points) in blocks {
+                slave_info.insert(Value::from(block_type), Value::Sequence(points));
+            }
+            let mut slave = Mapping::new();
+            slave.insert(Value::from(format!("slave_{}", slave_id)), Value::Mapping(slave_info));
+            slave_list.push(Value::Mapping(slave));
+        }
+
+        let mut config = connection.to_yaml();
+        config["slaves"] = Value::Sequence(slave_list);
+
+        Interface::from_value(&config)
+
+    }
+
+    fn from_value(yaml_config: &Value) -> Result<Interface, ConfigError> {
+
+        let protocol_name = get_yaml_string!(yaml_config, "protocol");
+        let protocol_cased = protocol_name.to_lowercase();
         let modbusprotocol = match protocol_cased.as_str() {
             "rtu" => ModbusProtocol::Rtu,
             "tcp" => ModbusProtocol::Tcp,
+            #[cfg(feature = "usb_hid")]
+            "usb_hid" => ModbusProtocol::UsbHid,
             _ => {
-                panic!("Invailed modbusprotocol '{}'", protocol_name);
+                return Err(invailed_config!("Invailed modbusprotocol '{}'", protocol_name));
             },
         };
     
-        let address = get_yaml_string!(&yaml_config, "address");
+        let address = match modbusprotocol {
+            #[cfg(feature = "usb_hid")]
+            ModbusProtocol::UsbHid => {
+                let vendor_id = yaml_config.get("vendor_id")
+                    .ok_or_else(|| missing_required!("vendor_id"))?
+                    .as_u64()
+                    .ok_or_else(|| invailed_type!("vendor_id", "unsigned integetr"))?;
+                if vendor_id > u16::MAX as u64 {
+                    return Err(invailed_value!("vendor_id", vendor_id));
+                }
+                format!("0x{:04x}", vendor_id)
+            },
+            _ => get_yaml_string!(yaml_config, "address"),
+        };
 
         let config_key = match modbusprotocol {
             ModbusProtocol::Rtu => "baudrate",
             ModbusProtocol::Tcp => "tcp_port",
+            #[cfg(feature = "usb_hid")]
+            ModbusProtocol::UsbHid => "product_id",
         };
         let config_u64 = yaml_config.get(config_key)
-            .expect(format!("Missing required '{}' in '{}' modbusprotocol", config_key, protocol_name).as_str())
+            .ok_or_else(|| invailed_config!("Missing required '{}' in '{}' modbusprotocol", config_key, protocol_name))?
             .as_u64()
-            .expect(invailed_type_message!(config_key, "unsigned integetr"));
+            .ok_or_else(|| invailed_type!(config_key, "unsigned integetr"))?;
         let config = match modbusprotocol {
             ModbusProtocol::Rtu => {
                 if config_u64 < u32::MAX as u64 {
                     config_u64 as u32
                 } else {
-                    panic!("{}", invailed_value_message!("baudrate", config_u64));
+                    return Err(invailed_value!("baudrate", config_u64));
+                }
+            }
+            ModbusProtocol::Tcp => {
+                if config_u64 < u16::MAX as u64 {
+                    config_u64 as u32
+                } else {
+                    return Err(invailed_value!("tcp_port", config_u64));
+                }
+            }
+            #[cfg(feature = "usb_hid")]
+            ModbusProtocol::UsbHid => {
+                if config_u64 <= u16::MAX as u64 {
+                    config_u64 as u32
+                } else {
+                    return Err(invailed_value!("product_id", config_u64));
+                }
+            }
+        };
+
+        let flow_control = match yaml_config.get("flow_control") {
+            Some(value) => {
+                if modbusprotocol != ModbusProtocol::Rtu {
+                    return Err(invailed_config!("'flow_control' is only supported in 'rtu' modbusprotocol"));
+                }
+                let flow_control_str = value.as_str()
+                    .ok_or_else(|| invailed_type!("flow_control", "string"))?;
+                match flow_control_str.to_lowercase().as_str() {
+                    "none" => FlowControl::None,
+                    "hardware" => FlowControl::Hardware,
+                    "software" => FlowControl::Software,
+                    _ => return Err(invailed_value!("flow_control", flow_control_str)),
+                }
+            },
+            None => FlowControl::None,
+        };
+
+        let interframe_delay_ms = match yaml_config.get("interframe_delay_ms") {
+            Some(value) => {
+                if modbusprotocol != ModbusProtocol::Rtu {
+                    return Err(invailed_config!("'interframe_delay_ms' is only supported in 'rtu' modbusprotocol"));
+                }
+                Some(value.as_u64()
+                    .ok_or_else(|| invailed_type!("interframe_delay_ms", "unsigned integetr"))?)
+            },
+            None => None,
+        };
+
+        let connect_timeout_ms = match yaml_config.get("connect_timeout_ms") {
+            Some(value) => {
+                if modbusprotocol != ModbusProtocol::Tcp {
+                    return Err(invailed_config!("'connect_timeout_ms' is only supported in 'tcp' modbusprotocol"));
+                }
+                match value.as_u64() {
+                    Some(0) => return Err(invailed_value!("connect_timeout_ms", 0)),
+                    Some(connect_timeout_ms) => connect_timeout_ms,
+                    None => return Err(invailed_type!("connect_timeout_ms", "unsigned integetr")),
+                }
+            },
+            None => CONNECT_TIMEOUT_MS,
+        };
+
+        let watchdog = match yaml_config.get("watchdog") {
+            Some(value) => {
+                if modbusprotocol != ModbusProtocol::Rtu {
+                    return Err(invailed_config!("'watchdog' is only supported in 'rtu' modbusprotocol"));
+                }
+                let watchdog_map = value.as_mapping()
+                    .ok_or_else(|| invailed_type!("watchdog", "mapping"))?;
+                let setting = |key: &str, default: u64, min: u64| match watchdog_map.get(key) {
+                    Some(value) => match value.as_u64() {
+                        Some(setting) if setting < min => Err(invailed_value!(key, setting)),
+                        Some(setting) => Ok(setting),
+                        None => Err(invailed_type!(key, "unsigned integetr")),
+                    },
+                    None => Ok(default),
+                };
+                Some(Watchdog {
+                    timeouts: setting("timeouts", 5, 2)? as usize,
+                    reopen_attempts: setting("reopen_attempts", 3, 1)? as u32,
+                    window: Duration::from_secs(setting("window_s", 60, 1)?),
+                })
+            },
+            None => None,
+        };
+
+        let connect = match yaml_config.get("connect") {
+            Some(value) => {
+                let connect_str = value.as_str()
+                    .ok_or_else(|| invailed_type!("connect", "string"))?;
+                match connect_str.to_lowercase().as_str() {
+                    "eager" => ConnectMode::Eager,
+                    "lazy" => ConnectMode::Lazy,
+                    _ => return Err(invailed_value!("connect", connect_str)),
+                }
+            },
+            None => ConnectMode::Lazy,
+        };
+
+        let writes = match yaml_config.get("writes") {
+            Some(value) => {
+                let writes_str = value.as_str()
+                    .ok_or_else(|| invailed_type!("writes", "string"))?;
+                match writes_str.to_lowercase().as_str() {
+                    "enabled" => WriteMode::Enabled,
+                    "simulate" => WriteMode::Simulate,
+                    "reject" => WriteMode::Reject,
+                    _ => return Err(invailed_value!("writes", writes_str)),
+                }
+            },
+            None => WriteMode::Enabled,
+        };
+
+        let lenient_coil_echo = match yaml_config.get("lenient_coil_echo") {
+            Some(value) => value.as_bool()
+                .ok_or_else(|| invailed_type!("lenient_coil_echo", "bool"))?,
+            None => false,
+        };
+
+        let lenient_width = match yaml_config.get("lenient_width") {
+            Some(value) => value.as_bool()
+                .ok_or_else(|| invailed_type!("lenient_width", "bool"))?,
+            None => false,
+        };
+
+        let auto_save = match yaml_config.get("auto_save") {
+            Some(value) => value.as_bool()
+                .ok_or_else(|| invailed_type!("auto_save", "bool"))?,
+            None => false,
+        };
+
+        let strict = match yaml_config.get("strict") {
+            Some(value) => value.as_bool()
+                .ok_or_else(|| invailed_type!("strict", "bool"))?,
+            None => false,
+        };
+
+        let audit_log = match yaml_config.get("audit_log") {
+            Some(value) => Some(String::from(value.as_str()
+                .ok_or_else(|| invailed_type!("audit_log", "string"))?)),
+            None => None,
+        };
+
+        let max_batch = match yaml_config.get("max_batch") {
+            Some(value) => match value.as_u64() {
+                Some(0) => return Err(invailed_value!("max_batch", 0)),
+                Some(max_batch) => Some(max_batch as usize),
+                None => return Err(invailed_type!("max_batch", "unsigned integetr")),
+            },
+            None => None,
+        };
+
+        let byte_order = match yaml_config.get("byte_order") {
+            Some(value) => parse_endian(value, "byte_order")?,
+            None => Endian::Big,
+        };
+        let word_order = match yaml_config.get("word_order") {
+            Some(value) => parse_endian(value, "word_order")?,
+            None => Endian::Big,
+        };
+
+        let mut interface = Interface{
+            modbusprotocol,
+            address: address.clone(),
+            config,
+            flow_control,
+            interframe_delay_ms,
+            watchdog,
+            connect_timeout_ms,
+            connect,
+            writes,
+            lenient_coil_echo,
+            lenient_width,
+            max_batch,
+            byte_order,
+            word_order,
+            auto_save,
+            strict,
+            audit_log,
+            source: ConfigSource::default(),
+            slaves: HashMap::new(),
+        };
+
+        let slaves = yaml_config.get("slaves")
+            .ok_or_else(|| missing_required!("slaves"))?
+            .as_sequence()
+            .ok_or_else(|| invailed_type!("slaves", "sequence"))?;
+        for slavedata in slaves {
+            let slave_info_map = slavedata.as_mapping()
+                .ok_or_else(|| invailed_type!("slavedata", "mapping"))?;
+            if slave_info_map.len() != 1 {
+                return Err(invailed_config!("Invaild slavedata format"));
+            }
+            for (_slave_name, _slave_info) in slave_info_map {
+
+                let slave_name = String::from(_slave_name.as_str()
+                    .ok_or_else(|| invailed_type!("slavedata name", "string"))?
+                );
+                let slave_info = _slave_info.as_mapping()
+                    .ok_or_else(|| invailed_type!("slavedata info", "mapping"))?;
+                
+                let id_range = match slave_info.get("id_range") {
+                    Some(value) => {
+                        if slave_info.contains_key("id") {
+                            return Err(invailed_config!("'{}' sets both 'id' and 'id_range'", slave_name));
+                        }
+                        Some(parse_id_range(value)?)
+                    },
+                    None => None,
+                };
+                let id = match id_range {
+                    Some((first, _)) => first,
+                    None => {
+                        let key_id = Value::String(String::from("id"));
+                        let id_u64 = slave_info.get(&key_id)
+                            .ok_or_else(|| missing_required!("id"))?
+                            .as_u64()
+                            .ok_or_else(|| invailed_type!("id", "unsigned integetr"))?;
+                        if id_u64 < u8::MAX as u64 {
+                            id_u64 as u8
+                        } else {
+                            return Err(invailed_config!("Invaild value of id '{}'", id_u64));
+                        }
+                    },
+                };
+
+                let (co_key, di_key, hr_key, ir_key) = (
+                    Value::String(String::from("co")),
+                    Value::String(String::from("di")),
+                    Value::String(String::from("hr")),
+                    Value::String(String::from("ir")),
+                );
+                let (co_list, di_list, hr_list, ir_list) = (
+                    get_modbus_block_value!(slave_info, co_key),
+                    get_modbus_block_value!(slave_info, di_key),
+                    get_modbus_block_value!(slave_info, hr_key),
+                    get_modbus_block_value!(slave_info, ir_key),
+                );
+                let mut registers = ModbusDataMap::new();
+                let mut aliases = HashMap::new();
+                if let Some(list) = co_list { load_data_block(BlockType::Co, list, (byte_order, word_order), strict, &mut registers, &mut aliases)? }
+                if let Some(list) = di_list { load_data_block(BlockType::Di, list, (byte_order, word_order), strict, &mut registers, &mut aliases)? }
+                if let Some(list) = hr_list { load_data_block(BlockType::Hr, list, (byte_order, word_order), strict, &mut registers, &mut aliases)? }
+                if let Some(list) = ir_list { load_data_block(BlockType::Ir, list, (byte_order, word_order), strict, &mut registers, &mut aliases)? }
+                for alias in aliases.keys() {
+                    if registers.find(alias).is_some() {
+                        return Err(invailed_config!("'{}': alias '{}' collides with a datapoint", slave_name, alias));
+                    }
+                }
+
+                let mut slave_data = SlaveData::new(id, registers);
+                slave_data.aliases = aliases;
+                slave_data.id_range = id_range;
+                let wakeup = match slave_info.get("wakeup") {
+                    Some(value) => value.as_bool()
+                        .ok_or_else(|| invailed_type!("wakeup", "bool"))?,
+                    None => false,
+                };
+                if wakeup {
+                    let register = match slave_info.get("wakeup_register") {
+                        Some(value) => parse_address(value, "wakeup_register")?,
+                        None => 0,
+                    };
+                    slave_data.wakeup = Some(register as u16);
+                }
+                if let Some(value) = slave_info.get("min_read_quantity") {
+                    let quantity_u64 = value.as_u64()
+                        .ok_or_else(|| invailed_type!("min_read_quantity", "unsigned integetr"))?;
+                    // a padded read still has to fit the smallest read limit, 125 registers
+                    if quantity_u64 == 0 || quantity_u64 > 125 {
+                        return Err(invailed_value!("min_read_quantity", quantity_u64));
+                    }
+                    slave_data.min_read_quantity = quantity_u64 as u16;
+                }
+                for warning in slave_data.validate() {
+                    warn!("'{}': {}", slave_name, warning);
+                }
+                interface.slaves.insert(slave_name, slave_data);
+
+            }
+        }
+
+        Ok(interface)
+    
+    }
+
+}
+
+fn dump_data_block<'a>(block: impl Iterator<Item = (&'a str, &'a ModbusData)>, defaults: (Endian, Endian), aliases: &HashMap<String, String>) -> Value {
+
+    let mut block_list: Vec<(&str, &ModbusData)> = block.collect();
+    block_list.sort_by_key(|(block_name, modbus_data)| (modbus_data.address, *block_name));
+
+    let mut block_infos = Vec::new();
+    for (block_name, modbus_data) in block_list {
+        let mut block_info = Mapping::new();
+        block_info.insert(Value::from("addr"), Value::from(modbus_data.address));
+        block_info.insert(Value::from("type"), Value::from(modbus_data.value_type.to_string().to_lowercase()));
+        if modbus_data.block_type == BlockType::Co || modbus_data.block_type == BlockType::Hr {
+            block_info.insert(Value::from("func"), Value::from(modbus_data.requestfunction.to_string().to_lowercase()));
+        }
+        if modbus_data.count as usize != modbus_data.value_type.size() {
+            block_info.insert(Value::from("count"), Value::from(modbus_data.count));
+        }
+        if let Some(words) = &modbus_data.words {
+            block_info.insert(Value::from("words"), Value::Sequence(words.iter().map(|word| Value::from(*word)).collect()));
+        }
+        if let Some(pulse_ms) = modbus_data.pulse_ms {
+            block_info.insert(Value::from("pulse_ms"), Value::from(pulse_ms));
+        }
+        if modbus_data.active_low {
+            block_info.insert(Value::from("active_low"), Value::from(true));
+        }
+        if modbus_data.invert {
+            block_info.insert(Value::from("invert"), Value::from(true));
+        }
+        if modbus_data.view {
+            block_info.insert(Value::from("view"), Value::from(true));
+        }
+        if let Some(description) = &modbus_data.description {
+            block_info.insert(Value::from("description"), Value::from(description.as_str()));
+        }
+        if let Some(unit) = &modbus_data.unit {
+            block_info.insert(Value::from("unit"), Value::from(unit.as_str()));
+        }
+        if modbus_data.poll_priority != 0 {
+            block_info.insert(Value::from("poll_priority"), Value::from(modbus_data.poll_priority));
+        }
+        if let Some(shadow) = modbus_data.shadow {
+            block_info.insert(Value::from("shadow_register"), Value::from(shadow.address));
+            if shadow.formula != ShadowFormula::Invert {
+                block_info.insert(Value::from("shadow_formula"), Value::from(shadow.formula.to_string().to_lowercase()));
+            }
+        }
+        if let Some(cal_table) = &modbus_data.cal_table {
+            block_info.insert(Value::from("cal_table"), Value::from(cal_table.path.as_str()));
+        }
+        if let Some(flags) = &modbus_data.flags {
+            block_info.insert(Value::from("flags"), Value::Mapping(flags.iter()
+                .map(|(bit, name)| (Value::from(*bit), Value::from(name.as_str())))
+                .collect()));
+        }
+        let mut alias_list: Vec<&String> = aliases.iter()
+            .filter(|(_, canonical)| canonical.as_str() == block_name)
+            .map(|(alias, _)| alias)
+            .collect();
+        alias_list.sort();
+        match alias_list.len() {
+            0 => {},
+            1 => { block_info.insert(Value::from("alias"), Value::from(alias_list[0].as_str())); },
+            _ => { block_info.insert(Value::from("alias"), Value::Sequence(alias_list.into_iter().map(|alias| Value::from(alias.as_str())).collect())); },
+        }
+        for (key, endian, default) in [
+            ("byte_order", modbus_data.byte_order, defaults.0),
+            ("word_order", modbus_data.word_order, defaults.1),
+        ] {
+            if endian != default {
+                block_info.insert(Value::from(key), Value::from(endian.to_string().to_lowercase()));
+            }
+        }
+        let mut block_map = Mapping::new();
+        block_map.insert(Value::from(block_name), Value::Mapping(block_info));
+        block_infos.push(Value::Mapping(block_map));
+    }
+
+    Value::Sequence(block_infos)
+
+}
+
+fn env_name(name: &str) -> String {
+
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect()
+
+}
+
+fn flatten_env(prefix: &str, value: &Value, lines: &mut Vec<String>) {
+
+    match value {
+        Value::Mapping(map) => {
+            for (key, value) in map {
+                flatten_env(&format!("{}_{}", prefix, env_name(key.as_str().unwrap_or_default())), value, lines);
+            }
+        },
+        // slaves and points are one-key mappings, their names become part of the key
+        Value::Sequence(list) if list.iter().all(Value::is_mapping) => {
+            for value in list {
+                flatten_env(prefix, value, lines);
+            }
+        },
+        Value::Sequence(list) => {
+            let items: Vec<String> = list.iter().map(|item| match item {
+                Value::String(string) => string.clone(),
+                _ => serde_yaml::to_string(item).unwrap_or_default().trim().to_string(),
+            }).collect();
+            lines.push(format!("{}={}", prefix, items.join(",")));
+        },
+        Value::String(string) => lines.push(format!("{}={}", prefix, string)),
+        _ => lines.push(format!("{}={}", prefix, serde_yaml::to_string(value).unwrap_or_default().trim())),
+    }
+
+}
+
+impl Interface {
+
+    pub fn to_dotenv(&self, prefix: &str) -> String {
+
+        let mut lines = Vec::new();
+        flatten_env(&env_name(prefix), &self.to_yaml(), &mut lines);
+        lines.push(String::new());
+
+        lines.join("\n")
+
+    }
+
+    pub fn to_yaml(&self) -> Value {
+
+        let (protocol_name, config_key) = match self.modbusprotocol {
+            ModbusProtocol::Rtu => ("rtu", "baudrate"),
+            ModbusProtocol::Tcp => ("tcp", "tcp_port"),
+            #[cfg(feature = "usb_hid")]
+            ModbusProtocol::UsbHid => ("usb_hid", "product_id"),
+        };
+
+        let mut slave_list: Vec<(&String, &SlaveData)> = self.slaves.iter().collect();
+        slave_list.sort_by_key(|(slave_name, _)| slave_name.as_str());
+
+        let mut slaves = Vec::new();
+        for (slave_name, slave_data) in slave_list {
+            let mut slave_info = Mapping::new();
+            match slave_data.id_range {
+                Some((first, last)) => slave_info.insert(Value::from("id_range"), Value::from(format!("{}-{}", first, last))),
+                None => slave_info.insert(Value::from("id"), Value::from(slave_data.id)),
+            };
+            if let Some(register) = slave_data.wakeup {
+                slave_info.insert(Value::from("wakeup"), Value::from(true));
+                if register != 0 {
+                    slave_info.insert(Value::from("wakeup_register"), Value::from(register));
                 }
             }
-            ModbusProtocol::Tcp => {
-                if config_u64 < u16::MAX as u64 {
-                    config_u64 as u32
-                } else {
-                    panic!("{}", invailed_value_message!("tcp_port", config_u64));
+            if slave_data.min_read_quantity > 1 {
+                slave_info.insert(Value::from("min_read_quantity"), Value::from(slave_data.min_read_quantity));
+            }
+            for (block_key, block) in [
+                ("co", BlockType::Co), ("di", BlockType::Di),
+                ("hr", BlockType::Hr), ("ir", BlockType::Ir),
+            ] {
+                if slave_data.registers.iter_block(block).next().is_some() {
+                    slave_info.insert(Value::from(block_key), dump_data_block(slave_data.registers.iter_block(block), (self.byte_order, self.word_order), &slave_data.aliases));
+                }
+            }
+            let mut slave_map = Mapping::new();
+            slave_map.insert(Value::from(slave_name.as_str()), Value::Mapping(slave_info));
+            slaves.push(Value::Mapping(slave_map));
+        }
+
+        let mut yaml_config = Mapping::new();
+        yaml_config.insert(Value::from("protocol"), Value::from(protocol_name));
+        match self.usb_hid_ids() {
+            Some((vendor_id, _)) => yaml_config.insert(Value::from("vendor_id"), Value::from(vendor_id)),
+            None => yaml_config.insert(Value::from("address"), Value::from(self.address.as_str())),
+        };
+        yaml_config.insert(Value::from(config_key), Value::from(self.config));
+        if self.flow_control != FlowControl::None {
+            yaml_config.insert(Value::from("flow_control"), Value::from(self.flow_control.to_string().to_lowercase()));
+        }
+        if let Some(interframe_delay_ms) = self.interframe_delay_ms {
+            yaml_config.insert(Value::from("interframe_delay_ms"), Value::from(interframe_delay_ms));
+        }
+        if self.connect_timeout_ms != CONNECT_TIMEOUT_MS {
+            yaml_config.insert(Value::from("connect_timeout_ms"), Value::from(self.connect_timeout_ms));
+        }
+        if let Some(watchdog) = self.watchdog {
+            let mut watchdog_map = Mapping::new();
+            watchdog_map.insert(Value::from("timeouts"), Value::from(watchdog.timeouts as u64));
+            watchdog_map.insert(Value::from("reopen_attempts"), Value::from(watchdog.reopen_attempts));
+            watchdog_map.insert(Value::from("window_s"), Value::from(watchdog.window.as_secs()));
+            yaml_config.insert(Value::from("watchdog"), Value::Mapping(watchdog_map));
+        }
+        if self.connect != ConnectMode::Lazy {
+            yaml_config.insert(Value::from("connect"), Value::from(self.connect.to_string().to_lowercase()));
+        }
+        if self.writes != WriteMode::Enabled {
+            yaml_config.insert(Value::from("writes"), Value::from(self.writes.to_string().to_lowercase()));
+        }
+        if self.lenient_coil_echo {
+            yaml_config.insert(Value::from("lenient_coil_echo"), Value::from(true));
+        }
+        if self.lenient_width {
+            yaml_config.insert(Value::from("lenient_width"), Value::from(true));
+        }
+        if self.strict {
+            yaml_config.insert(Value::from("strict"), Value::from(true));
+        }
+        if let Some(max_batch) = self.max_batch {
+            yaml_config.insert(Value::from("max_batch"), Value::from(max_batch as u64));
+        }
+        if self.auto_save {
+            yaml_config.insert(Value::from("auto_save"), Value::from(true));
+        }
+        if let Some(audit_log) = &self.audit_log {
+            yaml_config.insert(Value::from("audit_log"), Value::from(audit_log.as_str()));
+        }
+        for (key, endian) in [("byte_order", self.byte_order), ("word_order", self.word_order)] {
+            if endian != Endian::Big {
+                yaml_config.insert(Value::from(key), Value::from(endian.to_string().to_lowercase()));
+            }
+        }
+        yaml_config.insert(Value::from("slaves"), Value::Sequence(slaves));
+
+        Value::Mapping(yaml_config)
+
+    }
+
+    pub fn to_yaml_string(&self) -> String {
+
+        serde_yaml::to_string(&self.to_yaml())
+            .expect("Failed to serialize interface to yaml")
+
+    }
+
+    // HELP and TYPE lines for points exposed as gauges, only points with a description are listed
+    pub fn export_prometheus_metadata(&self) -> String {
+
+        let mut slave_list: Vec<_> = self.slaves.iter().collect();
+        slave_list.sort_by_key(|(slave_name, _)| slave_name.as_str());
+
+        let mut metrics = BTreeMap::new();
+        for (_, slave_data) in slave_list {
+            for block_type in [BlockType::Co, BlockType::Di, BlockType::Hr, BlockType::Ir] {
+                for (name, modbus_data) in slave_data.registers().iter_block(block_type) {
+                    if let Some(description) = modbus_data.description() {
+                        let name: String = name.chars()
+                            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                            .collect();
+                        // points of the same name on several slaves share one metric, the first slave's description is kept
+                        metrics.entry(name).or_insert(description);
+                    }
                 }
             }
-        };
+        }
 
-        let mut interface = Interface{
-            modbusprotocol: modbusprotocol,
-            address: address.clone(),
-            config: config,
-            slaves: HashMap::new(),
+        let mut lines = Vec::new();
+        for (name, description) in metrics {
+            let help = description.replace('\\', "\\\\").replace('\n', "\\n");
+            lines.push(format!("# HELP modbus_register_value_{} {}", name, help));
+            lines.push(format!("# TYPE modbus_register_value_{} gauge", name));
+        }
+
+        lines.join("\n")
+
+    }
+
+}
+
+// where a device was loaded from, loaded again on reload
+#[derive(Clone)]
+pub struct DeviceSource {
+    pub name: String,
+    pub path: String, // yaml file or connection uri
+    pub csv_map: Option<String>, // vendor register map replacing the slaves of the yaml
+}
+
+impl DeviceSource {
+
+    pub fn load(&self) -> Result<Interface, ConfigError> {
+
+        let interface = match self.path.contains("://") {
+            true => Interface::from_uri(&self.path)?,
+            false => Interface::load(&self.path)?,
         };
+        match &self.csv_map {
+            Some(csv_map) => Interface::from_csv(csv_map, &interface),
+            None => Ok(interface),
+        }
 
-        let slaves = yaml_config.get("slaves")
-            .expect(missing_required_message!("slaves"))
-            .as_sequence()
-            .expect(invailed_type_message!("slaves", "sequence"));
-        for slavedata in slaves {
-            let slave_info_map = slavedata.as_mapping()
-                .expect(invailed_type_message!("slavedata", "mapping"));
-            if slave_info_map.len() != 1 {
-                panic!("Invaild slavedata format");
-            }
-            for (_slave_name, _slave_info) in slave_info_map {
+    }
 
-                let slave_name = String::from(_slave_name.as_str()
-                    .expect(invailed_type_message!("slavedata name", "string"))
-                );
-                let slave_info = _slave_info.as_mapping()
-                    .expect(invailed_type_message!("slavedata info", "mapping"));
-                
-                let key_id = Value::String(String::from("id"));
-                let id_u64 = slave_info.get(&key_id)
-                    .expect(missing_required_message!("id"))
-                    .as_u64()
-                    .expect(invailed_type_message!("id", "unsigned integetr"));
-                let id;
-                if id_u64 < u8::MAX as u64 {
-                    id = id_u64 as u8;
-                } else {
-                    panic!("Invaild value of id '{}'", id_u64);
-                }
+}
 
-                let (co_key, di_key, hr_key, ir_key) = (
-                    Value::String(String::from("co")),
-                    Value::String(String::from("di")),
-                    Value::String(String::from("hr")),
-                    Value::String(String::from("ir")),
-                );
-                let (co_list, di_list, hr_list, ir_list) = (
-                    get_modbus_block_value!(slave_info, co_key),
-                    get_modbus_block_value!(slave_info, di_key),
-                    get_modbus_block_value!(slave_info, hr_key),
-                    get_modbus_block_value!(slave_info, ir_key),
-                );
-                let (mut co, mut di, mut hr, mut ir) = (
-                    HashMap::new(), HashMap::new(), HashMap::new(), HashMap::new()
-                );
-                match co_list {
-                    Some(list) => load_data_block(BlockType::Co, list, &mut co),
-                    None => {},
-                }
-                match di_list {
-                    Some(list) => load_data_block(BlockType::Di, list, &mut di),
-                    None => {},
-                }
-                match hr_list {
-                    Some(list) => load_data_block(BlockType::Hr, list, &mut hr),
-                    None => {},
-                }
-                match ir_list {
-                    Some(list) => load_data_block(BlockType::Ir, list, &mut ir),
-                    None => {},
-                }
+#[derive(Default, Serialize)]
+pub struct ConfigDiff {
+    pub added: Vec<String>, // need a new session
+    pub removed: Vec<String>, // need their session closed
+    pub changed: Vec<String>, // need close and reopen
+    pub unchanged: Vec<String>, // keep their session
+    pub devices: BTreeMap<String, DeviceDiff>, // what changed inside each changed device
+}
+
+// datapoints are named '<slave>/<point>', slave level settings '<slave>'
+#[derive(Default, PartialEq, Serialize)]
+pub struct DeviceDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub modified: BTreeMap<String, Vec<&'static str>>, // changed config keys
+    pub reconnect: Vec<&'static str>, // connection settings that force a reconnect
+    pub settings: Vec<&'static str>, // other interface level keys, applied from the next request
+}
+
+impl DeviceDiff {
+
+    pub fn is_empty(&self) -> bool {
+
+        *self == DeviceDiff::default()
+
+    }
 
-                interface.slaves.insert(slave_name, SlaveData::new(id, co, di, hr, ir));
+}
+
+fn point_changes(old: &ModbusData, new: &ModbusData) -> Vec<&'static str> {
+
+    let mut changes = Vec::new();
+    for (key, changed) in [
+        ("addr", old.address != new.address),
+        ("block", old.block_type != new.block_type),
+        ("type", old.value_type != new.value_type),
+        ("func", old.requestfunction != new.requestfunction),
+        ("count", old.count != new.count),
+        ("words", old.words != new.words),
+        ("byte_order", old.byte_order != new.byte_order),
+        ("word_order", old.word_order != new.word_order),
+        ("pulse_ms", old.pulse_ms != new.pulse_ms),
+        ("active_low", old.active_low != new.active_low),
+        ("invert", old.invert != new.invert),
+        ("flags", old.flags != new.flags),
+        ("view", old.view != new.view),
+        ("description", old.description != new.description),
+        ("unit", old.unit != new.unit),
+        ("poll_priority", old.poll_priority != new.poll_priority),
+        ("cal_table", old.cal_table != new.cal_table),
+        ("shadow_register", old.shadow != new.shadow),
+    ] {
+        if changed {
+            changes.push(key);
+        }
+    }
+
+    changes
+
+}
+
+pub fn diff_device(old: &Interface, new: &Interface) -> DeviceDiff {
+
+    let mut diff = DeviceDiff::default();
+
+    for (key, changed) in [
+        ("protocol", old.modbusprotocol != new.modbusprotocol),
+        ("address", old.address != new.address),
+        ("config", old.config != new.config),
+        ("flow_control", old.flow_control != new.flow_control),
+        // both wrap the session when it is opened
+        ("interframe_delay_ms", old.interframe_delay_ms != new.interframe_delay_ms),
+        ("lenient_coil_echo", old.lenient_coil_echo != new.lenient_coil_echo),
+    ] {
+        if changed {
+            // the config value is named after the protocol: tcp_port, baudrate or product_id
+            diff.reconnect.push(match (key, new.modbusprotocol) {
+                ("config", ModbusProtocol::Rtu) => "baudrate",
+                ("config", ModbusProtocol::Tcp) => "tcp_port",
+                #[cfg(feature = "usb_hid")]
+                ("config", ModbusProtocol::UsbHid) => "product_id",
+                (key, _) => key,
+            });
+        }
+    }
+
+    for (key, changed) in [
+        ("watchdog", old.watchdog != new.watchdog),
+        ("connect_timeout_ms", old.connect_timeout_ms != new.connect_timeout_ms),
+        ("connect", old.connect != new.connect),
+        ("writes", old.writes != new.writes),
+        ("lenient_width", old.lenient_width != new.lenient_width),
+        ("max_batch", old.max_batch != new.max_batch),
+        ("byte_order", old.byte_order != new.byte_order),
+        ("word_order", old.word_order != new.word_order),
+        ("auto_save", old.auto_save != new.auto_save),
+        ("strict", old.strict != new.strict),
+        ("audit_log", old.audit_log != new.audit_log),
+    ] {
+        if changed {
+            diff.settings.push(key);
+        }
+    }
 
+    let points = |slave: &SlaveData| -> BTreeMap<String, ModbusData> {
+        [BlockType::Co, BlockType::Di, BlockType::Hr, BlockType::Ir].into_iter()
+            .flat_map(|block| slave.registers.iter_block(block))
+            .map(|(name, modbus_data)| (String::from(name), modbus_data.clone()))
+            .collect()
+    };
+    let no_points = BTreeMap::new();
+    let mut slave_names: Vec<&String> = old.slaves.keys().chain(new.slaves.keys()).collect();
+    slave_names.sort();
+    slave_names.dedup();
+    for slave_name in slave_names {
+        let (old_slave, new_slave) = (old.slaves.get(slave_name), new.slaves.get(slave_name));
+        if let (Some(old_slave), Some(new_slave)) = (old_slave, new_slave) {
+            let mut changes = Vec::new();
+            for (key, changed) in [
+                ("id", old_slave.id != new_slave.id),
+                ("id_range", old_slave.id_range != new_slave.id_range),
+                ("wakeup", old_slave.wakeup != new_slave.wakeup),
+                ("min_read_quantity", old_slave.min_read_quantity != new_slave.min_read_quantity),
+                ("alias", old_slave.aliases != new_slave.aliases),
+            ] {
+                if changed {
+                    changes.push(key);
+                }
+            }
+            if !changes.is_empty() {
+                diff.modified.insert(slave_name.clone(), changes);
+            }
+        }
+        let old_points = old_slave.map(points).unwrap_or_else(|| no_points.clone());
+        let new_points = new_slave.map(points).unwrap_or_else(|| no_points.clone());
+        for (point_name, new_point) in &new_points {
+            let path = format!("{}/{}", slave_name, point_name);
+            match old_points.get(point_name) {
+                Some(old_point) => {
+                    let changes = point_changes(old_point, new_point);
+                    if !changes.is_empty() {
+                        diff.modified.insert(path, changes);
+                    }
+                },
+                None => diff.added.push(path),
             }
         }
+        diff.removed.extend(old_points.keys()
+            .filter(|point_name| !new_points.contains_key(*point_name))
+            .map(|point_name| format!("{}/{}", slave_name, point_name)));
+    }
 
-        interface
-    
+    diff
+
+}
+
+pub fn diff_configs(old: &HashMap<String, Interface>, new: &HashMap<String, Interface>) -> ConfigDiff {
+
+    let mut diff = ConfigDiff::default();
+    for (device_name, interface) in new {
+        match old.get(device_name) {
+            Some(old_interface) if old_interface == interface => diff.unchanged.push(device_name.clone()),
+            Some(old_interface) => {
+                diff.changed.push(device_name.clone());
+                diff.devices.insert(device_name.clone(), diff_device(old_interface, interface));
+            },
+            None => diff.added.push(device_name.clone()),
+        }
+    }
+    diff.removed = old.keys()
+        .filter(|device_name| !new.contains_key(*device_name))
+        .cloned()
+        .collect();
+
+    for names in [&mut diff.added, &mut diff.removed, &mut diff.changed, &mut diff.unchanged] {
+        names.sort();
     }
 
+    diff
+
 }
 
 
@@ -448,25 +2460,32 @@ impl fmt::Display for Interface {
 
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 
+        // "{:#}" lists every register instead of the per-block counts
+        if f.alternate() {
+            return write!(f, "{}", display::format_register_table(self));
+        }
+
         let (protocol_name, config_key) = match self.modbusprotocol {
             ModbusProtocol::Rtu => ("rtu", "baudrate"),
             ModbusProtocol::Tcp => ("tcp", "tcp_port"),
+            #[cfg(feature = "usb_hid")]
+            ModbusProtocol::UsbHid => ("usb_hid", "product_id"),
         };
 
         let mut slaves_info = String::new();
         for (slave_name, slave_info) in &self.slaves {
             slaves_info.push_str(format!("  {}: {}", slave_name, slave_info.id).as_str());
-            slaves_info.push_str(format!("\n    co: {}", slave_info.co.len()).as_str());
-            slaves_info.push_str(format!("\n    di: {}", slave_info.di.len()).as_str());
-            slaves_info.push_str(format!("\n    hr: {}", slave_info.hr.len()).as_str());
-            slaves_info.push_str(format!("\n    ir: {}", slave_info.ir.len()).as_str());
+            for block in [BlockType::Co, BlockType::Di, BlockType::Hr, BlockType::Ir] {
+                let count = slave_info.registers.iter_block(block).count();
+                slaves_info.push_str(format!("\n    {}: {}", block.to_string().to_lowercase(), count).as_str());
+            }
             slaves_info.push('\n');
         }
         
-        write!(f, "{}\n{}\n{}\nslaves:\n{}",
-            format!("modbusprotocol: {}", protocol_name),
-            format!("address: {}", self.address),
-            format!("{}: {}", config_key, self.config),
+        write!(f, "modbusprotocol: {}\naddress: {}\n{}: {}\nslaves:\n{}",
+            protocol_name,
+            self.address,
+            config_key, self.config,
             slaves_info,
         )
 
@@ -497,31 +2516,286 @@ impl fmt::Display for ValueType {
             ValueType::F32 => {
                 write!(f, "F32")
             },
+            ValueType::BcdU16 => {
+                write!(f, "Bcd16")
+            },
+            ValueType::BcdU32 => {
+                write!(f, "Bcd32")
+            },
+            ValueType::Custom(name) => {
+                write!(f, "{}", name)
+            },
         }
 
     }
 
 }
 
-impl fmt::Display for BlockType {
+impl fmt::Display for FlowControl {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        match self {
+            FlowControl::None => {
+                write!(f, "None")
+            },
+            FlowControl::Hardware => {
+                write!(f, "Hardware")
+            },
+            FlowControl::Software => {
+                write!(f, "Software")
+            },
+        }
+
+    }
+
+}
+
+impl fmt::Display for WriteMode {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        match self {
+            WriteMode::Enabled => {
+                write!(f, "Enabled")
+            },
+            WriteMode::Simulate => {
+                write!(f, "Simulate")
+            },
+            WriteMode::Reject => {
+                write!(f, "Reject")
+            },
+        }
+
+    }
+
+}
+
+impl fmt::Display for ConnectMode {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        match self {
+            ConnectMode::Eager => {
+                write!(f, "Eager")
+            },
+            ConnectMode::Lazy => {
+                write!(f, "Lazy")
+            },
+        }
+
+    }
+
+}
+
+impl fmt::Display for ShadowFormula {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        match self {
+            ShadowFormula::Invert => {
+                write!(f, "Invert")
+            },
+            ShadowFormula::Copy => {
+                write!(f, "Copy")
+            },
+        }
+
+    }
+
+}
+
+impl fmt::Display for Endian {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        match self {
+            Endian::Big => {
+                write!(f, "Big")
+            },
+            Endian::Little => {
+                write!(f, "Little")
+            },
+        }
+
+    }
+
+}
+
+impl fmt::Display for RequestFunction {
 
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 
         match self {
-            &BlockType::Co => {
+            RequestFunction::Single => {
+                write!(f, "Single")
+            },
+            RequestFunction::Multiple => {
+                write!(f, "Multiple")
+            },
+        }
+
+    }
+
+}
+
+impl fmt::Display for BlockType {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        match *self {
+            BlockType::Co => {
                 write!(f, "Co")
             },
-            &BlockType::Di => {
+            BlockType::Di => {
                 write!(f, "Di")
             },
-            &BlockType::Hr => {
+            BlockType::Hr => {
                 write!(f, "Hr")
             },
-            &BlockType::Ir => {
+            BlockType::Ir => {
                 write!(f, "Ir")
             },
         }
 
     }
 
-}
\ No newline at end of file
+}
+
+impl fmt::Display for ConfigWarning {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        match self {
+            ConfigWarning::OverlappingRegisters { a, b } => {
+                write!(f, "OverlappingRegisters: '{}' and '{}'", a, b)
+            },
+            ConfigWarning::TypeMismatch { name, block_type, value_type } => {
+                write!(f, "TypeMismatch: '{}' is a {} point of type {}, only Bool is supported", name, block_type, value_type)
+            },
+        }
+
+    }
+
+}
+
+impl fmt::Display for ConfigError {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        match self {
+            ConfigError::Parse(info) => {
+                write!(f, "Parse: {}", info)
+            },
+            ConfigError::UnsupportedFormat(info) => {
+                write!(f, "UnsupportedFormat: {}", info)
+            },
+            ConfigError::Invalid(info) => {
+                write!(f, "Invalid: {}", info)
+            },
+            ConfigError::SlaveIdChanged { slave, old, new } => {
+                write!(f, "SlaveIdChanged: '{}' from {} to {}", slave, old, new)
+            },
+            ConfigError::Save(info) => {
+                write!(f, "Save: {}", info)
+            },
+            ConfigError::TypeMismatch { name, block_type, value_type } => {
+                write!(f, "TypeMismatch: '{}' is a {} point of type {}, only Bool is supported", name, block_type, value_type)
+            },
+        }
+
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn write_cal_table(name: &str, text: &str) -> Result<CalTable, ConfigError> {
+
+        let path = std::env::temp_dir().join(format!("zero_modbus_{}_{}.csv", name, std::process::id()));
+        fs::write(&path, text).unwrap();
+        let cal_table = load_cal_table(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+
+        cal_table
+
+    }
+
+    #[test]
+    fn cal_table_interpolates_between_rows() {
+
+        let path = format!("{}/tests/fixtures/level_cal.csv", env!("CARGO_MANIFEST_DIR"));
+        let cal_table = load_cal_table(&path).unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(cal_table.interpolate(2000.0), 50.0);
+        assert_eq!(cal_table.interpolate(1500.0), 25.0);
+        // clamped to the first and last row
+        assert_eq!(cal_table.interpolate(0.0), 0.0);
+        assert_eq!(cal_table.interpolate(4000.0), 100.0);
+
+    }
+
+    #[test]
+    fn cal_table_header_may_follow_blank_lines() {
+
+        let cal_table = write_cal_table("header", "\n\nraw,level\n3000,100\n1000,0\n").unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(cal_table.interpolate(2000.0), 50.0);
+
+        let cal_table = write_cal_table("no_header", "\n1000,0\n3000,100\n").unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(cal_table.interpolate(2000.0), 50.0);
+
+    }
+
+    #[test]
+    fn cal_table_text_after_the_first_row_is_refused() {
+
+        let error = write_cal_table("late_header", "1000,0\nraw,level\n3000,100\n").err().map(|e| e.to_string()).expect("a late header was skipped");
+        assert!(error.contains("line 2: 'raw,level'"), "{}", error);
+
+    }
+
+    #[test]
+    fn one_character_typos_are_one_edit_away() {
+
+        assert_eq!(levenshtein("temp", "temb"), 1);
+        assert_eq!(levenshtein("temp", "tmp"), 1);
+        assert_eq!(levenshtein("temp", "temps"), 1);
+        assert_eq!(levenshtein("temp", "tepm"), 2);
+        assert_eq!(levenshtein("", "temp"), 4);
+
+    }
+
+    #[test]
+    fn long_candidate_lists_keep_the_near_misses() {
+
+        let candidates = ["flow", "level", "levels", "pressure", "pump", "speed", "state", "temp", "total", "valve", "volts", "mode"];
+        assert_eq!(closest_names("levek", candidates.into_iter()), vec!["level", "levels"]);
+        assert_eq!(closest_names("humidity", candidates.into_iter()), Vec::<&str>::new());
+        // short lists are returned whole
+        assert_eq!(closest_names("humidity", candidates[..3].iter().copied()), vec!["flow", "level", "levels"]);
+
+    }
+
+    #[test]
+    fn slave_suggests_point_names_and_aliases() {
+
+        let interface = Interface::from_bytes(b"
+protocol: tcp
+address: 127.0.0.1
+tcp_port: 502
+slaves:
+- meter:
+    id: 1
+    hr:
+    - level: {addr: 0, type: u16}
+    - setpoint: {addr: 1, type: u16, alias: [sp]}
+", "yaml").unwrap_or_else(|e| panic!("{}", e));
+        let (slave, _) = interface.resolve_slave("meter").expect("no slave 'meter'");
+        assert!(slave.find("levle").is_none());
+        assert_eq!(slave.suggest("levle"), vec!["level", "setpoint", "sp"]);
+
+    }
+
+}
@@ -0,0 +1,8 @@
+pub mod address_map;
+pub mod client;
+pub mod display;
+pub mod interface;
+pub mod modbus;
+pub mod protocol;
+pub mod server;
+pub mod validate;
@@ -1,38 +1,115 @@
-use std::{collections::HashMap, env};
+use std::{collections::HashMap, env, fs, io, process};
+use tokio::runtime::{self, Runtime};
 use simple_logger::SimpleLogger;
 use log::*;
 
-pub mod interface;
-pub mod modbus;
-pub mod server;
-use interface::Interface;
-use server::Server;
+use zero_modbus::address_map;
+use zero_modbus::client;
+use zero_modbus::interface::{ConnectMode, DeviceSource, Interface};
+use zero_modbus::modbus::{self, ModbusSession};
+use zero_modbus::server::{KeyCase, LogFormat, SendFailure, Server, ServerOptions};
+use zero_modbus::validate;
 
 
-#[tokio::main]
-async fn main() {
+macro_rules! missing_value_message {
+    ($option:expr) => {
+        format!("Missing value of '{}'", $option).as_str()
+    };
+}
 
-    SimpleLogger::new().init().expect("Failed to init logger");
-    
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("usage: {} zmq_address device_1_name:<device_1.yaml> device_2_name:<device_2.yaml> ...\n", args[0]);
+// device_name:<device.yaml>: or device_name:<uri>, a uri device has no slaves unless a register map is given
+fn parse_device_arg(arg: &str) -> (&str, &str) {
+
+    if let Some((device_name, uri)) = arg.split_once(':').filter(|(_, uri)| uri.contains("://")) {
+        return (device_name, uri);
+    }
+    let arg_parts: Vec<&str> = arg.split(':').collect();
+    if arg_parts.len() != 3 {
+        panic!("Invaild arg format: '{}'", arg);
+    }
+
+    (arg_parts[0], arg_parts[1])
+
+}
+
+async fn auto_discover(args: &[String]) {
+
+    if args.len() != 3 {
+        panic!("usage: --auto-discover <uri>|<connection.yaml> slave_id output.yaml");
+    }
+
+    // of a yaml only the connection is used, the slaves written are the scanned one
+    let source = DeviceSource { name: String::from("scan"), path: args[0].clone(), csv_map: None };
+    let interface = source.load()
+        .unwrap_or_else(|e| panic!("Failed to load '{}': {}", args[0], e));
+    let slave_id: u8 = args[1].parse()
+        .unwrap_or_else(|_| panic!("Invaild slave id: '{}'", args[1]));
+
+    let mut session = match ModbusSession::open(&interface).await {
+        Ok(session) => session,
+        Err(e) => panic!("Failed to connect to '{}': {}", args[0], e),
+    };
+
+    let discovered = Interface::from_modbus_scan(&mut session, slave_id).await;
+    fs::write(&args[2], discovered.to_yaml_string())
+        .unwrap_or_else(|_| panic!("Failed to write '{}'", args[2]));
+    info!("Discovered config written to '{}'", args[2]);
+
+}
+
+fn build_runtime(worker_threads: Option<usize>) -> io::Result<Runtime> {
+
+    let mut builder = runtime::Builder::new_multi_thread();
+    builder.enable_all();
+    if let Some(worker_threads) = worker_threads {
+        builder.worker_threads(worker_threads);
+    }
+
+    builder.build()
+
+}
+
+fn parse_worker_threads(value: &str) -> usize {
+
+    match value.parse() {
+        Ok(worker_threads) if worker_threads > 0 => worker_threads,
+        _ => panic!("Invaild worker thread count: '{}'", value),
+    }
+
+}
+
+async fn run(args: Vec<String>, mut options: ServerOptions, verbose: bool, require_all_connected: bool, csv_maps: HashMap<String, String>) {
+
+    if args[1] == "--auto-discover" {
+        auto_discover(&args[2..]).await;
+        return;
     }
 
     let mut device_list: HashMap<String, Interface> = HashMap::new();
     for arg in &args[2..] {
 
-        let arg_parts: Vec<&str> = arg.split(':').collect();
-        if arg_parts.len() != 3 {
-            panic!("Invaild arg format: '{}'", arg);
-        }
-
-        let (device_name, file_name) = (arg_parts[0], arg_parts[1]);
-        device_list.insert(String::from(device_name),Interface::from_yaml(file_name));
+        let (device_name, file_name) = parse_device_arg(arg);
+        // the yaml keeps the connection, the registers come from the vendor map
+        let source = DeviceSource {
+            name: String::from(device_name),
+            path: String::from(file_name),
+            csv_map: csv_maps.get(device_name).cloned(),
+        };
+        let interface = source.load()
+            .unwrap_or_else(|e| panic!("Failed to load '{}': {}", file_name, e));
         info!("Config file '{}' loaded.", file_name);
+        if let Some(csv_path) = &source.csv_map {
+            info!("Register map '{}' loaded.", csv_path);
+        }
+        options.device_sources.push(source);
+        device_list.insert(String::from(device_name), interface);
         info!("- {}:", device_name);
         let key = String::from(device_name);
-        for line in format!("{}", &device_list[&key]).split('\n') {
+        let summary = match verbose {
+            true => format!("{:#}", &device_list[&key]),
+            false => format!("{}", &device_list[&key]),
+        };
+        for line in summary.split('\n') {
             if line.len() > 1 {
                 info!(" - {}", line);
             }
@@ -40,6 +117,209 @@ async fn main() {
 
     }
 
-    Server::new(&args[1]).forever(&device_list).await;
+    let mut eager_devices: Vec<&String> = device_list.iter()
+        .filter(|(_, interface)| interface.connect() == ConnectMode::Eager)
+        .map(|(device_name, _)| device_name)
+        .collect();
+    eager_devices.sort();
+    for device_name in eager_devices {
+        match modbus::connect_eager(device_name, &device_list[device_name]).await {
+            Ok(()) => info!("Connected to '{}'", device_name),
+            Err(e) if require_all_connected => panic!("Failed to connect to '{}': {}", device_name, e),
+            Err(e) => error!("Failed to connect to '{}', will retry on first request: {}", device_name, e),
+        }
+    }
+
+    if let Some(ipc_path) = args[1].strip_prefix("ipc://") {
+        let ipc_path = String::from(ipc_path);
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                info!("Shutting down, removing '{}'", ipc_path);
+                let _ = fs::remove_file(&ipc_path);
+                std::process::exit(0);
+            }
+        });
+    }
+
+    Server::new(&args[1], options).forever(&mut device_list).await;
+    // forever only returns with --on-send-failure exit, the server is dropped by now
+    process::exit(1);
+
+}
+
+fn main() {
+
+    // the client only prints responses, it neither logs nor needs a runtime
+    if env::args().nth(1).as_deref() == Some("client") {
+        let client_args: Vec<String> = env::args().skip(2).collect();
+        process::exit(client::run(&client_args));
+    }
+    // the map goes to stdout, where the logger would write as well
+    if env::args().nth(1).as_deref() == Some("--print-address-map") {
+        let config_path = env::args().nth(2).expect(missing_value_message!("--print-address-map"));
+        address_map::print_address_map(&Interface::from_yaml(&config_path));
+        return;
+    }
+    // a dry run of the device files, the report replaces the load log; exit code 1 when any file has an error
+    if env::args().nth(1).as_deref() == Some("--validate") {
+        let mut json = false;
+        let mut paths = Vec::new();
+        for arg in env::args().skip(2) {
+            match arg.as_str() {
+                "--json" => json = true,
+                _ => paths.push(arg),
+            }
+        }
+        if paths.is_empty() {
+            panic!("{}", missing_value_message!("--validate"));
+        }
+        let report = validate::validate_files(&paths);
+        match json {
+            true => println!("{}", serde_json::to_string_pretty(&report).unwrap_or_else(|_| report.to_string())),
+            false => println!("{}", validate::format_report(&report)),
+        }
+        process::exit(if report["valid"] == true { 0 } else { 1 });
+    }
+
+    SimpleLogger::new().init().expect("Failed to init logger");
     
+    let mut options = ServerOptions {
+        stats_interval_s: 60,
+        max_request_items: 10000,
+        max_interface_items: 1000,
+        max_message_size: 64 * 1024,
+        idempotency_keys: 1000,
+        idempotency_ttl_s: 600,
+        stale_ttl_s: 3600,
+        zmq_poll_interval_ms: 10,
+        ..Default::default()
+    };
+    let mut verbose = false;
+    let mut require_all_connected = false;
+    let mut csv_maps = HashMap::new();
+    let mut worker_threads = env::var("ZERO_MODBUS_WORKER_THREADS").ok()
+        .map(|value| parse_worker_threads(&value));
+    let mut args: Vec<String> = Vec::new();
+    let mut env_args = env::args();
+    while let Some(arg) = env_args.next() {
+        match arg.as_str() {
+            "--dedup" => options.dedup = true,
+            "--serve-stale" => options.serve_stale = true,
+            "--verbose" => verbose = true,
+            "--pretty" => options.pretty = true,
+            "--require-all-connected" => require_all_connected = true,
+            "--read-only" => options.read_only = true,
+            "--stats-interval-s" => {
+                let value = env_args.next().expect(missing_value_message!("--stats-interval-s"));
+                options.stats_interval_s = value.parse()
+                    .unwrap_or_else(|_| panic!("Invaild stats interval: '{}'", value));
+            },
+            "--max-request-items" | "--max-interface-items" => {
+                let value = env_args.next().expect(missing_value_message!(arg));
+                let limit = value.parse()
+                    .unwrap_or_else(|_| panic!("Invaild item limit: '{}'", value));
+                match arg.as_str() {
+                    "--max-request-items" => options.max_request_items = limit,
+                    _ => options.max_interface_items = limit,
+                }
+            },
+            "--max-message-size-bytes" => {
+                let value = env_args.next().expect(missing_value_message!("--max-message-size-bytes"));
+                options.max_message_size = value.parse()
+                    .unwrap_or_else(|_| panic!("Invaild message size: '{}'", value));
+            },
+            "--idempotency-keys" => {
+                let value = env_args.next().expect(missing_value_message!("--idempotency-keys"));
+                options.idempotency_keys = value.parse()
+                    .unwrap_or_else(|_| panic!("Invaild idempotency key count: '{}'", value));
+            },
+            "--zmq-poll-interval-ms" => {
+                let value = env_args.next().expect(missing_value_message!("--zmq-poll-interval-ms"));
+                options.zmq_poll_interval_ms = value.parse()
+                    .unwrap_or_else(|_| panic!("Invaild poll interval: '{}'", value));
+            },
+            "--stale-ttl-s" => {
+                let value = env_args.next().expect(missing_value_message!("--stale-ttl-s"));
+                options.stale_ttl_s = value.parse()
+                    .unwrap_or_else(|_| panic!("Invaild stale ttl: '{}'", value));
+            },
+            "--idempotency-ttl-s" => {
+                let value = env_args.next().expect(missing_value_message!("--idempotency-ttl-s"));
+                options.idempotency_ttl_s = value.parse()
+                    .unwrap_or_else(|_| panic!("Invaild idempotency ttl: '{}'", value));
+            },
+            "--response-keys" => {
+                let value = env_args.next().expect(missing_value_message!("--response-keys"));
+                options.key_case = KeyCase::parse(&value)
+                    .unwrap_or_else(|| panic!("Invaild response key casing: '{}'", value));
+            },
+            "--log-format" => {
+                let value = env_args.next().expect(missing_value_message!("--log-format"));
+                options.log_format = LogFormat::parse(&value)
+                    .unwrap_or_else(|| panic!("Invaild log format: '{}'", value));
+            },
+            "--on-send-failure" => {
+                let value = env_args.next().expect(missing_value_message!("--on-send-failure"));
+                options.on_send_failure = SendFailure::parse(&value)
+                    .unwrap_or_else(|| panic!("Invaild send failure handling: '{}'", value));
+            },
+            "--audit-log" => {
+                modbus::set_audit_log(Some(env_args.next().expect(missing_value_message!("--audit-log"))));
+            },
+            "--csv-register-map" => {
+                let value = env_args.next().expect(missing_value_message!("--csv-register-map"));
+                let (device_name, csv_path) = value.split_once(':')
+                    .unwrap_or_else(|| panic!("Invaild arg format: '{}'", value));
+                csv_maps.insert(String::from(device_name), String::from(csv_path));
+            },
+            "--monitor-address" => {
+                options.monitor_address = Some(env_args.next().expect(missing_value_message!("--monitor-address")));
+            },
+            "--worker-threads" => {
+                let value = env_args.next().expect(missing_value_message!("--worker-threads"));
+                worker_threads = Some(parse_worker_threads(&value));
+            },
+            _ => args.push(arg),
+        }
+    }
+    if args.len() < 2 {
+        eprintln!("usage: {} [--dedup] [--serve-stale] [--stale-ttl-s N] [--verbose] [--pretty] [--require-all-connected] [--read-only] [--stats-interval-s N] [--max-request-items N] [--max-interface-items N] [--max-message-size-bytes N] [--idempotency-keys N] [--idempotency-ttl-s N] [--zmq-poll-interval-ms N] [--response-keys original|snake|camel] [--log-format text|json] [--on-send-failure reset|exit] [--audit-log file.jsonl] [--csv-register-map device_name:<map.csv>] [--monitor-address zmq_address] [--worker-threads N] zmq_address device_1_name:<device_1.yaml> device_2_name:<uri> ...\n", args[0]);
+        eprintln!("       {} --auto-discover <uri>|<connection.yaml> slave_id output.yaml\n", args[0]);
+        eprintln!("       {} --print-address-map <device.yaml>\n", args[0]);
+        eprintln!("       {} --validate [--json] <device_1.yaml> <device_2.yaml> ...\n", args[0]);
+        eprintln!("       {} client --connect zmq_address [--timeout-ms N] get|set|list|stats|reload|raw ...\n", args[0]);
+        return;
+    }
+
+    let runtime = build_runtime(worker_threads).expect("Failed to build tokio runtime");
+    if let Some(worker_threads) = worker_threads {
+        info!("Tokio runtime started with {} worker thread(s)", worker_threads);
+    }
+    runtime.block_on(run(args, options, verbose, require_all_connected, csv_maps));
+    
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn runtime_has_the_configured_workers() {
+
+        let runtime = build_runtime(Some(2)).unwrap();
+        assert_eq!(runtime.metrics().num_workers(), 2);
+
+    }
+
+    #[test]
+    fn worker_threads_must_be_a_positive_number() {
+
+        assert_eq!(parse_worker_threads("4"), 4);
+        for value in ["0", "-1", "four", ""] {
+            assert!(std::panic::catch_unwind(|| parse_worker_threads(value)).is_err(), "'{}' was accepted", value);
+        }
+
+    }
+
 }
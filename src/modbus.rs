@@ -1,23 +1,57 @@
 use log::*;
 use core::fmt;
-use tokio_modbus::client::{rtu, tcp, Context, Reader, Writer};
+use std::{collections::{BTreeMap, HashMap, HashSet, VecDeque}, fs::OpenOptions, io::{self, ErrorKind, Write}, net::{IpAddr, SocketAddr}, pin::Pin, sync::{atomic::{AtomicUsize, Ordering}, Arc, Mutex}, task::{Context as TaskContext, Poll}, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+use async_trait::async_trait;
+use tokio::{io::{AsyncRead, AsyncWrite, ReadBuf}, net::{self, TcpStream}, time};
+use tokio_modbus::client::{rtu, tcp, Client, Context, Reader, Writer};
 use tokio_modbus::slave::{SlaveContext, Slave};
-use tokio_modbus::ExceptionCode;
+use tokio_modbus::{ExceptionCode, Request, Response};
+use tokio_modbus::prelude::ReadCode;
 use tokio_serial::{self, SerialStream};
-use serde_json::{self, Number, Value};
+use serde::{Deserialize, Serialize};
+use serde_json::{self, json, Number, Value};
 
-use crate::interface::{BlockType, RequestFunction, Interface, ModbusData, ModbusProtocol, ValueType};
+use crate::interface::{poll_order, BlockType, CalTable, ConfigError, Endian, FlowControl, RequestFunction, Interface, ModbusData, ModbusDataMap, ModbusProtocol, Shadow, SlaveData, ValueType, Watchdog};
 
 
+#[derive(Clone, Default)]
+pub struct ItemOptions {
+    pub client: Option<String>, // peer address of the request, recorded in the audit log
+}
+
+#[derive(Clone)]
+pub struct RequestItem {
+    pub slave: String,
+    pub name: String,
+    pub op: GetOrSet,
+    pub value: Option<Value>, // value to write, None for reads
+    pub options: ItemOptions,
+}
+
+pub struct ItemResult {
+    pub slave: String,
+    pub name: String,
+    pub value: Value, // value read, or value actually written
+    pub modbus_data: ModbusData, // the point as it was resolved for the request
+}
+
+pub type RequestInfo = Vec<RequestItem>;
+
 pub enum ModbusError {
     ModbusError(String),
+    TransportError(String), // serial/socket failures (framing, parity) as opposed to protocol errors
+    Timeout(String), // no answer to a transaction in time
     ModbusException(ExceptionCode),
+    WriteException(ExceptionCode, Vec<u16>), // words that were sent
     DataSizeNotMatch(usize),
+    ResponseSizeNotMatch { function_code: u8, address: u16, expected: u16, payload: Vec<u16> }, // device answered with the wrong count
     DataConvertError(ValueType),
     SlaveNotFound(String),
     ValueNotDefined(String),
     WriteInputValue(BlockType),
+    WriteCalibratedValue(String), // points read through a cal_table can not be written
     InvailedValueInput(Value),
+    Context(String, Box<ModbusError>), // what was being done when the error happened
 }
 
 
@@ -27,6 +61,34 @@ pub enum GetOrSet {
     Set,
 }
 
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnError {
+    Abort,    // stop the batch at the first failed item
+    #[default]
+    Continue, // attempt every item and report each failure
+}
+
+// device deviations from the spec that requests work around
+#[derive(Clone, Copy)]
+struct Quirks {
+    lenient_width: bool,
+    min_read_quantity: u16, // reads are padded up to this many registers or coils, the point stays at the start
+}
+
+impl Quirks {
+
+    fn of(interface: &Interface, slave: &SlaveData) -> Self {
+
+        Quirks {
+            lenient_width: interface.lenient_width(),
+            min_read_quantity: slave.min_read_quantity(),
+        }
+
+    }
+
+}
+
 enum ModbusFunction {
     ReadCoils,
     ReadDiscreteInputs,
@@ -38,10 +100,78 @@ enum ModbusFunction {
     WriteMultipleRegisters,
 }
 
+type Decoder = Box<dyn Fn(&[u16]) -> Option<Value> + Send + Sync>;
+type Encoder = Box<dyn Fn(&Value) -> Option<Vec<u16>> + Send + Sync>;
+
+// a value type supplied by the embedding program, words are passed in big endian order
+struct CustomType {
+    words: usize,
+    decode: Decoder,
+    encode: Encoder, // must return exactly `words` words
+}
+
+static CUSTOM_TYPES: Mutex<BTreeMap<&'static str, Arc<CustomType>>> = Mutex::new(BTreeMap::new());
+
+const BUILTIN_TYPES: [&str; 8] = ["bool", "u16", "i16", "u32", "i32", "f32", "bcd16", "bcd32"];
+
+// makes `type: <name>` usable by configs loaded afterwards, names are case insensitive
+pub fn register_value_type<D, E>(name: &str, words: usize, decode: D, encode: E) -> Result<ValueType, ConfigError>
+where
+    D: Fn(&[u16]) -> Option<Value> + Send + Sync + 'static,
+    E: Fn(&Value) -> Option<Vec<u16>> + Send + Sync + 'static,
+{
+
+    let name = name.trim().to_lowercase();
+    if name.is_empty() || BUILTIN_TYPES.contains(&name.as_str()) {
+        return Err(ConfigError::Invalid(format!("'{}' can not be used as a custom type name", name)));
+    }
+    // a register block holds at most 123 registers, see the count option
+    if words == 0 || words > 123 {
+        return Err(ConfigError::Invalid(format!("custom type '{}' can not span {} words", name, words)));
+    }
+
+    let mut custom_types = CUSTOM_TYPES.lock().unwrap();
+    if custom_types.contains_key(name.as_str()) {
+        return Err(ConfigError::Invalid(format!("custom type '{}' is already registered", name)));
+    }
+    // registered types live as long as the program, like the points using them
+    let name: &'static str = Box::leak(name.into_boxed_str());
+    custom_types.insert(name, Arc::new(CustomType { words, decode: Box::new(decode), encode: Box::new(encode) }));
+
+    Ok(ValueType::Custom(name))
+
+}
+
+pub fn custom_value_type(name: &str) -> Option<ValueType> {
+
+    CUSTOM_TYPES.lock().unwrap().get_key_value(name).map(|(name, _)| ValueType::Custom(name))
+
+}
+
+pub fn custom_type_words(name: &str) -> usize {
+
+    CUSTOM_TYPES.lock().unwrap().get(name).map_or(1, |custom_type| custom_type.words)
+
+}
+
+fn custom_type(name: &str) -> Option<Arc<CustomType>> {
+
+    // cloned out so a decoder is never called with the registry locked
+    CUSTOM_TYPES.lock().unwrap().get(name).cloned()
+
+}
 
-fn response_to_value(response: &Vec<u16>, value_type: ValueType) -> Result<Value, ModbusError> {
+fn response_to_value(response: &[u16], value_type: ValueType) -> Result<Value, ModbusError> {
 
     match value_type {
+        ValueType::Custom(name) => {
+            let custom_type = custom_type(name).ok_or(ModbusError::DataConvertError(value_type))?;
+            if response.len() == custom_type.words {
+                (custom_type.decode)(response).ok_or(ModbusError::DataConvertError(value_type))
+            } else {
+                Err(ModbusError::DataSizeNotMatch(response.len()))
+            }
+        },
         ValueType::Bool => {
             if response.len() == 1 {
                 Ok(Value::Bool(response[0] != 0))
@@ -51,20 +181,14 @@ fn response_to_value(response: &Vec<u16>, value_type: ValueType) -> Result<Value
         },
         ValueType::U16 => {
             if response.len() == 1 {
-                Ok(Value::Number(match Number::from_u128(response[0] as u128) {
-                    Some(number) => number, None =>
-                        return Err(ModbusError::DataConvertError(ValueType::U16)),
-                }))
+                Ok(Value::Number(Number::from(response[0])))
             } else {
                 Err(ModbusError::DataSizeNotMatch(response.len()))
             }
         },
         ValueType::I16 => {
             if response.len() == 1 {
-                Ok(Value::Number(match Number::from_i128(response[0] as i16 as i128) {
-                    Some(number) => number, None =>
-                        return Err(ModbusError::DataConvertError(ValueType::I16)),
-                }))
+                Ok(Value::Number(Number::from(response[0] as i16)))
             } else {
                 Err(ModbusError::DataSizeNotMatch(response.len()))
             }
@@ -72,21 +196,15 @@ fn response_to_value(response: &Vec<u16>, value_type: ValueType) -> Result<Value
         ValueType::U32 => {
             if response.len() == 2 {
                 let num_u32 = ((response[0] as u32) << 16) | (response[1] as u32);
-                Ok(Value::Number(match Number::from_u128(num_u32 as u128) {
-                    Some(number) => number, None =>
-                        return Err(ModbusError::DataConvertError(ValueType::U32)),
-                }))
+                Ok(Value::Number(Number::from(num_u32)))
             } else {
                 Err(ModbusError::DataSizeNotMatch(response.len()))
             }
         },
         ValueType::I32 => {
             if response.len() == 2 {
-                let num_i32 = ((response[0] as i32) << 16) | (response[1] as i32);
-                Ok(Value::Number(match Number::from_u128(num_i32 as i32 as u128) {
-                    Some(number) => number, None =>
-                        return Err(ModbusError::DataConvertError(ValueType::I32)),
-                }))
+                let num_i32 = (((response[0] as u32) << 16) | (response[1] as u32)) as i32;
+                Ok(Value::Number(Number::from(num_i32)))
             } else {
                 Err(ModbusError::DataSizeNotMatch(response.len()))
             }
@@ -102,24 +220,132 @@ fn response_to_value(response: &Vec<u16>, value_type: ValueType) -> Result<Value
                 Err(ModbusError::DataSizeNotMatch(response.len()))
             }
         },
+        ValueType::BcdU16 => {
+            if response.len() == 1 {
+                let num = bcd_to_u32(response[0]).ok_or(ModbusError::DataConvertError(ValueType::BcdU16))?;
+                Ok(Value::Number(Number::from(num)))
+            } else {
+                Err(ModbusError::DataSizeNotMatch(response.len()))
+            }
+        },
+        ValueType::BcdU32 => {
+            if response.len() == 2 {
+                let (high, low) = match (bcd_to_u32(response[0]), bcd_to_u32(response[1])) {
+                    (Some(high), Some(low)) => (high, low),
+                    _ => return Err(ModbusError::DataConvertError(ValueType::BcdU32)),
+                };
+                Ok(Value::Number(Number::from(high * 10000 + low)))
+            } else {
+                Err(ModbusError::DataSizeNotMatch(response.len()))
+            }
+        },
+    }
+}
+
+fn bcd_to_u32(word: u16) -> Option<u32> {
+
+    let mut num = 0;
+    for shift in [12, 8, 4, 0] {
+        let digit = ((word >> shift) & 0xF) as u32;
+        if digit > 9 {
+            return None;
+        }
+        num = num * 10 + digit;
+    }
+
+    Some(num)
+
+}
+
+fn u32_to_bcd(num: u32) -> u16 {
+
+    // num must be below 10000
+    let mut word = 0;
+    for (shift, scale) in [(12, 1000), (8, 100), (4, 10), (0, 1)] {
+        word |= ((num / scale % 10) as u16) << shift;
+    }
+
+    word
+
+}
+
+fn flags_to_value(raw: &Value, flags: &BTreeMap<u8, String>) -> Result<Value, ModbusError> {
+
+    let raw = raw.as_u64().ok_or_else(|| ModbusError::InvailedValueInput(raw.clone()))?;
+
+    Ok(Value::Object(flags.iter()
+        .map(|(bit, name)| (name.clone(), Value::Bool(raw & (1 << bit) != 0)))
+        .collect()))
+
+}
+
+fn calibrate(raw: &Value, cal_table: &CalTable) -> Result<Value, ModbusError> {
+
+    let raw = raw.as_f64().ok_or_else(|| ModbusError::InvailedValueInput(raw.clone()))?;
+
+    Ok(Value::from(cal_table.interpolate(raw)))
+
+}
+
+// (bits to set, bits to clear) for a partial {"name": bool} object
+fn value_to_flags(value: &Option<Value>, flags: &BTreeMap<u8, String>) -> Result<(u64, u64), ModbusError> {
+
+    let invailed = || ModbusError::InvailedValueInput(value.clone().unwrap_or(Value::Null));
+    let requested = value.as_ref().and_then(Value::as_object).ok_or_else(invailed)?;
+
+    let (mut set_mask, mut clear_mask) = (0, 0);
+    for (name, state) in requested {
+        let bit = flags.iter()
+            .find(|(_, flag)| *flag == name)
+            .map(|(bit, _)| *bit)
+            .ok_or_else(invailed)?;
+        match state.as_bool().ok_or_else(invailed)? {
+            true => set_mask |= 1 << bit,
+            false => clear_mask |= 1 << bit,
+        }
     }
+
+    Ok((set_mask, clear_mask))
+
+}
+
+// flips bools, including the elements of coil arrays and flag objects
+fn invert_value(value: &Value) -> Value {
+
+    match value {
+        Value::Bool(state) => Value::Bool(!state),
+        Value::Array(array) => Value::Array(array.iter().map(invert_value).collect()),
+        Value::Object(object) => Value::Object(object.iter()
+            .map(|(name, state)| (name.clone(), invert_value(state)))
+            .collect()),
+        value => value.clone(),
+    }
+
 }
 
-fn value_to_bytes(_value: &Option<Value>, value_type: ValueType, count: u16) -> Option<[u16; 2]> {
+fn value_to_bytes(_value: &Option<Value>, value_type: ValueType, count: u16) -> Option<Vec<u16>> {
 
     let value = match _value {
         Some(value) => value,
         None => return None,
     };
 
-    match count {
+    if let ValueType::Custom(name) = value_type {
+        let custom_type = custom_type(name)?;
+        // a slot narrower than the type can not hold the value
+        return (custom_type.encode)(value)
+            .filter(|words| words.len() == custom_type.words && words.len() <= count as usize);
+    }
+
+    // built in types are encoded into the low words of a two word slot
+    match count.min(2) {
         1 => {
             match value_type {
                 ValueType::Bool => {
                     if value.as_bool()? {
-                        Some([0, 1])
+                        Some(vec![0, 1])
                     } else {
-                       Some([0, 0])
+                       Some(vec![0, 0])
                     }
                 },
                 ValueType::U16 => {
@@ -127,16 +353,19 @@ fn value_to_bytes(_value: &Option<Value>, value_type: ValueType, count: u16) ->
                     if num_u64 > u16::MAX as u64 {
                         None
                     } else {
-                        Some([0, num_u64 as u16])
+                        Some(vec![0, num_u64 as u16])
                     }
                 },
                 ValueType::I16 => {
-                    let num_i64 = value.as_i64()?;
-                    let num_u64 = num_i64.abs() as u64;
-                    if num_u64 > i16::MAX.abs() as u64 {
+                    let num_i16 = i16::try_from(value.as_i64()?).ok()?;
+                    Some(vec![0, num_i16 as u16])
+                },
+                ValueType::BcdU16 => {
+                    let num_u64 = value.as_u64()?;
+                    if num_u64 > 9999 {
                         None
                     } else {
-                        Some([0, num_i64 as u16])
+                        Some(vec![0, u32_to_bcd(num_u64 as u32)])
                     }
                 },
                 _ => None
@@ -146,9 +375,9 @@ fn value_to_bytes(_value: &Option<Value>, value_type: ValueType, count: u16) ->
             match value_type {
                 ValueType::Bool => {
                     if value.as_bool()? {
-                        Some([0, 1])
+                        Some(vec![0, 1])
                     } else {
-                       Some([0, 0])
+                       Some(vec![0, 0])
                     }
                 },
                 ValueType::U16 => {
@@ -156,51 +385,55 @@ fn value_to_bytes(_value: &Option<Value>, value_type: ValueType, count: u16) ->
                     if num_u64 > u16::MAX as u64 {
                         None
                     } else {
-                        Some([0, num_u64 as u16])
+                        Some(vec![0, num_u64 as u16])
                     }
                 },
                 ValueType::I16 => {
-                    let num_i64 = value.as_i64()?;
-                    let num_u64 = num_i64.abs() as u64;
-                    if num_u64 > i16::MAX.abs() as u64 {
-                        None
-                    } else {
-                        Some([0, num_i64 as u16])
-                    }
+                    let num_i16 = i16::try_from(value.as_i64()?).ok()?;
+                    Some(vec![0, num_i16 as u16])
                 },
                 ValueType::U32 => {
                     let num_u64 = value.as_u64()?;
                     if num_u64 > u32::MAX as u64 {
                         None
                     } else {
-                        Some([((num_u64 & 0xFFFF0000) >> 16) as u16, (num_u64 & 0xFFFF) as u16])
+                        Some(vec![((num_u64 & 0xFFFF0000) >> 16) as u16, (num_u64 & 0xFFFF) as u16])
                     }
                 },
                 ValueType::I32 => {
-                    let num_i64 = value.as_i64()?;
-                    let num_u64 = num_i64.abs() as u64;
-                    if num_u64 > i32::MAX.abs() as u64 {
-                        None
-                    } else {
-                        Some([
-                            ((num_i64 as u64 & 0xFFFF0000) >> 16) as u16,
-                            (num_i64 as u64 & 0xFFFF) as u16]
-                        )
-                    }
+                    let num_u32 = i32::try_from(value.as_i64()?).ok()? as u32;
+                    Some(vec![(num_u32 >> 16) as u16, (num_u32 & 0xFFFF) as u16])
                 },
                 ValueType::F32 => {
                     let num_f64 = value.as_f64()?;
                     let num_i64 = num_f64.ceil() as i64;
-                    let num_u64 = num_i64.abs() as u64;
-                    if num_u64 > i32::MAX.abs() as u64 {
+                    let num_u64 = num_i64.unsigned_abs();
+                    if num_u64 > i32::MAX.unsigned_abs() as u64 {
                         None
                     } else {
-                        Some([
-                            (((num_f64 as f32).to_bits() as u32 & 0xFFFF0000) >> 16) as u16,
-                            ((num_f64 as f32).to_bits() as u32 & 0xFFFF) as u16]
+                        Some(vec![
+                            (((num_f64 as f32).to_bits() & 0xFFFF0000) >> 16) as u16,
+                            ((num_f64 as f32).to_bits() & 0xFFFF) as u16]
                         )
                     }
                 },
+                ValueType::BcdU16 => {
+                    let num_u64 = value.as_u64()?;
+                    if num_u64 > 9999 {
+                        None
+                    } else {
+                        Some(vec![0, u32_to_bcd(num_u64 as u32)])
+                    }
+                },
+                ValueType::BcdU32 => {
+                    let num_u64 = value.as_u64()?;
+                    if num_u64 > 99999999 {
+                        None
+                    } else {
+                        Some(vec![u32_to_bcd((num_u64 / 10000) as u32), u32_to_bcd((num_u64 % 10000) as u32)])
+                    }
+                },
+                ValueType::Custom(_) => None,
             }
         }
         _ => None
@@ -208,6 +441,88 @@ fn value_to_bytes(_value: &Option<Value>, value_type: ValueType, count: u16) ->
 
 }
 
+fn coils_to_value(response: &[bool], access_size: u16) -> Result<Value, ModbusError> {
+
+    if response.len() != access_size as usize {
+        Err(ModbusError::DataSizeNotMatch(response.len()))
+    } else if access_size == 1 {
+        Ok(Value::Bool(response[0]))
+    } else {
+        Ok(Value::Array(response.iter().map(|coil| Value::Bool(*coil)).collect()))
+    }
+
+}
+
+fn value_to_coils(value: &Option<Value>, access_size: u16) -> Result<Vec<bool>, ModbusError> {
+
+    let value = match value {
+        Some(value) => value,
+        None => return Err(ModbusError::InvailedValueInput(Value::Null)),
+    };
+
+    let coils: Option<Vec<bool>> = match value {
+        Value::Bool(coil) if access_size == 1 => Some(vec![*coil]),
+        Value::Array(array) if array.len() == access_size as usize => {
+            array.iter().map(|coil| coil.as_bool()).collect()
+        },
+        _ => None,
+    };
+
+    coils.ok_or_else(|| ModbusError::InvailedValueInput(value.clone()))
+
+}
+
+fn slot_words(response: &[u16], value_type: ValueType) -> &[u16] {
+
+    // a slot wider than its type keeps the value in its trailing (low) words
+    if response.len() > value_type.size() {
+        &response[response.len() - value_type.size()..]
+    } else {
+        response
+    }
+
+}
+
+fn reorder(words: &mut [u16], byte_order: Endian, word_order: Endian) {
+
+    // converts between wire and big endian order, applying it twice is a no-op
+    if byte_order == Endian::Little {
+        for word in words.iter_mut() {
+            *word = word.swap_bytes();
+        }
+    }
+    if word_order == Endian::Little {
+        words.reverse();
+    }
+
+}
+
+fn read_value(response: &[u16], modbus_data: &ModbusData) -> Result<Value, ModbusError> {
+
+    let mut words = slot_words(response, modbus_data.value_type()).to_vec();
+    reorder(&mut words, modbus_data.byte_order(), modbus_data.word_order());
+
+    response_to_value(&words, modbus_data.value_type())
+
+}
+
+fn wire_words(words: &[u16], modbus_data: &ModbusData) -> Vec<u16> {
+
+    let mut wire = words.to_vec();
+    let start = wire.len().saturating_sub(modbus_data.value_type().size());
+    reorder(&mut wire[start..], modbus_data.byte_order(), modbus_data.word_order());
+
+    wire
+
+}
+
+fn written_value(words: &[u16], value_type: ValueType, access_size: u16) -> Result<Value, ModbusError> {
+
+    let start = words.len().saturating_sub(access_size as usize);
+    response_to_value(slot_words(&words[start..], value_type), value_type)
+
+}
+
 impl ModbusFunction {
     
     pub fn inference(modbus_data: &ModbusData, get_or_set: GetOrSet) -> Option<(Self, u16)> {
@@ -245,109 +560,158 @@ impl ModbusFunction {
             }
         }?;
 
-        let access_size = match modbus_data.value_type() {
-            ValueType::Bool | ValueType::U16 | ValueType::I16 => 1,
-            ValueType::U32 | ValueType::I32 | ValueType::F32 => 2,
-        };
+        let access_size = modbus_data.count();
 
         Some((modbus_function, access_size))
         
 
     }
 
-    pub async fn do_request(&self, context: &mut Context, address: u8, access_size: u16, value_type: ValueType, value: &Option<Value>) -> Result<Value, ModbusError> {
+    fn code(&self) -> u8 {
+
+        match self {
+            Self::ReadCoils => 0x01,
+            Self::ReadDiscreteInputs => 0x02,
+            Self::ReadHodingRegisters => 0x03,
+            Self::ReadInputRegisters => 0x04,
+            Self::WriteSingleCoil => 0x05,
+            Self::WriteSingleRegister => 0x06,
+            Self::WriteMultipleCoils => 0x0F,
+            Self::WriteMultipleRegisters => 0x10,
+        }
+
+    }
+
+    fn check_response_size<T: Copy + Into<u16>>(&self, address: u16, expected: u16, response: &[T]) -> Result<(), ModbusError> {
+
+        // decoding slices the response by type size, so a miscounted answer must be caught here
+        if response.len() == expected as usize {
+            return Ok(());
+        }
+
+        Err(ModbusError::ResponseSizeNotMatch {
+            function_code: self.code(),
+            address,
+            expected,
+            payload: response.iter().map(|item| (*item).into()).collect(),
+        })
+
+    }
+
+    // the words of the point from a read of `read_size`, which is larger than the point when padded
+    fn fit_response(&self, address: u16, access_size: u16, read_size: u16, mut response: Vec<u16>, modbus_data: &ModbusData, lenient_width: bool) -> Result<Vec<u16>, ModbusError> {
+
+        // a device that is narrower than configured answers a 32-bit point with a single word,
+        // lenient_width reads the missing high word as zero instead of failing the point
+        let short = !response.is_empty() && response.len() < access_size as usize;
+        if lenient_width && short && modbus_data.value_type().size() == 2 {
+            warn!("Short response at {}: expected {} words, received {}, zero-padded", address, access_size, response.len());
+            let padding = vec![0; access_size as usize - response.len()];
+            match modbus_data.word_order() {
+                Endian::Big => { response.splice(0..0, padding); },
+                Endian::Little => response.extend(padding),
+            }
+            return Ok(response);
+        }
+        self.check_response_size(address, read_size, &response)?;
+        response.truncate(access_size as usize);
+
+        Ok(response)
+
+    }
+
+    pub async fn do_request(&self, context: &mut Context, modbus_data: &ModbusData, access_size: u16, value: &Option<Value>, quirks: Quirks) -> Result<Value, ModbusError> {
+
+        let (address, value_type) = (modbus_data.address(), modbus_data.value_type());
+        let read_size = access_size.max(quirks.min_read_quantity);
         
         match self {
             Self::ReadCoils => {
-                match context.read_coils(address as u16, access_size).await {
+                match context.read_coils(address as u16, read_size).await {
                     Ok(modbus_response) => {
                         match modbus_response {
-                            Ok(response) => {
-                                if response.len() == 1 {
-                                    Ok(Value::Bool(response[0]))
-                                } else {
-                                    Err(ModbusError::DataSizeNotMatch(response.len()))
-                                }
+                            Ok(mut response) => {
+                                self.check_response_size(address as u16, read_size, &response)?;
+                                response.truncate(access_size as usize);
+                                coils_to_value(&response, access_size)
                             },
                             Err(err) => Err(ModbusError::ModbusException(err)),
                         }
                     } Err(err) => {
-                        Err(ModbusError::ModbusError(err.to_string()))
+                        Err(ModbusError::from(err))
                     }
                 }
             },
             Self::ReadDiscreteInputs => {
-                match context.read_discrete_inputs(address as u16, access_size).await {
+                match context.read_discrete_inputs(address as u16, read_size).await {
                     Ok(modbus_response) => {
                         match modbus_response {
-                            Ok(response) => {
-                                if response.len() == 1 {
-                                    Ok(Value::Bool(response[0]))
-                                } else {
-                                    Err(ModbusError::DataSizeNotMatch(response.len()))
+                            Ok(mut response) => {
+                                self.check_response_size(address as u16, read_size, &response)?;
+                                response.truncate(access_size as usize);
+                                if modbus_data.active_low() {
+                                    response.iter_mut().for_each(|input| *input = !*input);
                                 }
+                                coils_to_value(&response, access_size)
                             },
                             Err(err) => Err(ModbusError::ModbusException(err)),
                         }
                     } Err(err) => {
-                        Err(ModbusError::ModbusError(err.to_string()))
+                        Err(ModbusError::from(err))
                     }
                 }
             },
             Self::ReadHodingRegisters => {
-                match context.read_holding_registers(address as u16, access_size).await {
+                match read_registers(context, BlockType::Hr, address as u16, read_size).await {
                     Ok(modbus_response) => {
                         match modbus_response {
-                            Ok(response) => response_to_value(&response, value_type),
+                            Ok(response) => {
+                                let response = self.fit_response(address as u16, access_size, read_size, response, modbus_data, quirks.lenient_width)?;
+                                read_value(&response, modbus_data)
+                            },
                             Err(err) => Err(ModbusError::ModbusException(err)),
                         }
-                    } Err(err) => Err(ModbusError::ModbusError(err.to_string())),
+                    } Err(err) => Err(ModbusError::from(err)),
                 }
             },
             Self::ReadInputRegisters => {
-                match context.read_input_registers(address as u16, access_size).await {
+                match read_registers(context, BlockType::Ir, address as u16, read_size).await {
                     Ok(modbus_response) => {
                         match modbus_response {
-                            Ok(response) => response_to_value(&response, value_type),
+                            Ok(response) => {
+                                let response = self.fit_response(address as u16, access_size, read_size, response, modbus_data, quirks.lenient_width)?;
+                                read_value(&response, modbus_data)
+                            },
                             Err(err) => Err(ModbusError::ModbusException(err)),
                         }
-                    } Err(err) => Err(ModbusError::ModbusError(err.to_string())),
+                    } Err(err) => Err(ModbusError::from(err)),
                 }
             },
             Self::WriteSingleCoil => {
-                match context.write_single_coil(address as u16, match value {
+                let coil = match value {
                     Some(value) => match value.as_bool() {
                         Some(coil) => coil,
                         None => { return Err(ModbusError::InvailedValueInput(value.clone())); },
                     },
                     None => { return Err(ModbusError::InvailedValueInput(Value::Null)); }
-                }).await {
-                    Ok(modbus_response) => {
-                        match modbus_response {
-                            Ok(_) => Ok(Value::Null),
-                            Err(err) => Err(ModbusError::ModbusException(err)),
-                        }
-                    } Err(err) => Err(ModbusError::ModbusError(err.to_string())),
+                };
+                // through Client::call, the write_single_coil helper asserts the echo in debug builds
+                match context.call(Request::WriteSingleCoil(address as u16, coil)).await {
+                    Ok(Ok(Response::WriteSingleCoil(echoed_address, echoed))) if echoed_address == address as u16 && echoed == coil => Ok(Value::Bool(coil)),
+                    Ok(Ok(response)) => Err(ModbusError::ModbusError(format!("Unexpected echo of coil {}: {:?}", address, response))),
+                    Ok(Err(err)) => Err(ModbusError::ModbusException(err)),
+                    Err(err) => Err(ModbusError::from(err)),
                 }
             },
             Self::WriteMultipleCoils => {
-                let mut coil_array = [false];
-                match context.write_multiple_coils(address as u16, match value {
-                    Some(value) => match value.as_bool() {
-                        Some(coil) => {
-                            coil_array[0] = coil;
-                            &coil_array
-                        },
-                        None => { return Err(ModbusError::InvailedValueInput(value.clone())); },
-                    },
-                    None => { return Err(ModbusError::InvailedValueInput(Value::Null)); }
-                }).await {
+                let coils = value_to_coils(value, access_size)?;
+                match context.write_multiple_coils(address as u16, &coils).await {
                     Ok(modbus_response) => {
                         match modbus_response {
-                            Ok(_) => Ok(Value::Null),
+                            Ok(_) => coils_to_value(&coils, access_size),
                             Err(err) => Err(ModbusError::ModbusException(err)),
                         }
-                    } Err(err) => Err(ModbusError::ModbusError(err.to_string())),
+                    } Err(err) => Err(ModbusError::from(err)),
                 }
             },
             Self::WriteSingleRegister => {
@@ -355,34 +719,19 @@ impl ModbusFunction {
                     Some(words) => words,
                     None => return Err(ModbusError::InvailedValueInput(Value::Null)),
                 };
-                match access_size {
-                    1 => match context.write_single_register(address as u16, words[1]).await {
-                        Ok(modbus_response) => {
-                            match modbus_response {
-                                Ok(_) => Ok(Value::Null),
-                                Err(err) => Err(ModbusError::ModbusException(err)),
-                            }
-                        } Err(err) => Err(ModbusError::ModbusError(err.to_string())),
-                    },
-                    2 => {
-                        match context.write_single_register(address as u16, words[0]).await {
-                            Ok(modbus_response) => {
-                                match modbus_response {
-                                    Ok(_) => {},
-                                    Err(err) => return Err(ModbusError::ModbusException(err)),
-                                }
-                            }, Err(err) => return Err(ModbusError::ModbusError(err.to_string())),
-                        }
-                        match context.write_single_register((address+1) as u16, words[1]).await {
-                            Ok(modbus_response) => {
-                                match modbus_response {
-                                    Ok(_) => Ok(Value::Null),
-                                    Err(err) => Err(ModbusError::ModbusException(err)),
-                                }
-                            } Err(err) => Err(ModbusError::ModbusError(err.to_string())),
+                let wire = wire_words(&words, modbus_data);
+                // loading upgrades func single on wider points to multiple
+                if access_size != 1 || wire.is_empty() {
+                    return Err(ModbusError::DataSizeNotMatch(access_size as usize));
+                }
+                let word = wire[wire.len() - 1];
+                match context.write_single_register(address as u16, word).await {
+                    Ok(modbus_response) => {
+                        match modbus_response {
+                            Ok(_) => written_value(&words, value_type, access_size),
+                            Err(err) => Err(ModbusError::WriteException(err, vec![word])),
                         }
-                    },
-                    _ => panic!("Access size not match"),
+                    } Err(err) => Err(ModbusError::from(err)),
                 }
             },
             Self::WriteMultipleRegisters => {
@@ -392,122 +741,1301 @@ impl ModbusFunction {
                         Some(value) => value.clone(), None => Value::Null,
                     })),
                 };
-                let single_word = [words[1]];
-                match context.write_multiple_registers(address as u16, match access_size {
-                    1 => &single_word,
-                    2 => &words,
-                    _ => return Err(ModbusError::InvailedValueInput(match value {
-                        Some(value) => value.clone(), None => Value::Null,
-                    })),
-                }).await {
+                let wire = wire_words(&words, modbus_data);
+                let mut payload = vec![0; (access_size as usize).saturating_sub(wire.len())];
+                payload.extend_from_slice(&wire[wire.len().saturating_sub(access_size as usize)..]);
+                match context.write_multiple_registers(address as u16, &payload).await {
                     Ok(modbus_response) => {
                         match modbus_response {
-                            Ok(_) => Ok(Value::Null),
-                            Err(err) => Err(ModbusError::ModbusException(err)),
+                            Ok(_) => written_value(&words, value_type, access_size),
+                            Err(err) => Err(ModbusError::WriteException(err, payload)),
                         }
-                    } Err(err) => Err(ModbusError::ModbusError(err.to_string())),
+                    } Err(err) => Err(ModbusError::from(err)),
                 }
             },
         }
         
     }
 
+    pub async fn do_scattered_request(&self, context: &mut Context, words: &[u8], modbus_data: &ModbusData, value: &Option<Value>, quirks: Quirks) -> Result<Value, ModbusError> {
+
+        // word order is given by the explicit word list, only bytes are swapped
+        let (value_type, byte_order) = (modbus_data.value_type(), modbus_data.byte_order());
+
+        match self {
+            Self::ReadHodingRegisters | Self::ReadInputRegisters => {
+                let mut response = Vec::new();
+                for address in words {
+                    let read_size = quirks.min_read_quantity;
+                    let block_type = match self {
+                        Self::ReadHodingRegisters => BlockType::Hr,
+                        _ => BlockType::Ir,
+                    };
+                    let modbus_response = read_registers(context, block_type, *address as u16, read_size).await;
+                    match modbus_response {
+                        Ok(Ok(word)) => {
+                            self.check_response_size(*address as u16, read_size, &word)?;
+                            response.push(word[0]);
+                        },
+                        Ok(Err(err)) => return Err(ModbusError::ModbusException(err)),
+                        Err(err) => return Err(ModbusError::from(err)),
+                    }
+                }
+                reorder(&mut response, byte_order, Endian::Big);
+                response_to_value(&response, value_type)
+            },
+            Self::WriteSingleRegister | Self::WriteMultipleRegisters => {
+                let access_size = words.len() as u16;
+                let encoded = match value_to_bytes(value, value_type, access_size) {
+                    Some(encoded) => encoded,
+                    None => return Err(ModbusError::InvailedValueInput(match value {
+                        Some(value) => value.clone(), None => Value::Null,
+                    })),
+                };
+                let mut payload = encoded[encoded.len() - access_size as usize..].to_vec();
+                reorder(&mut payload, byte_order, Endian::Big);
+                for (address, word) in words.iter().zip(&payload) {
+                    let modbus_response = match self {
+                        Self::WriteSingleRegister => context.write_single_register(*address as u16, *word).await,
+                        _ => context.write_multiple_registers(*address as u16, &[*word]).await,
+                    };
+                    match modbus_response {
+                        Ok(Ok(_)) => {},
+                        Ok(Err(err)) => return Err(ModbusError::WriteException(err, payload.to_vec())),
+                        Err(err) => return Err(ModbusError::from(err)),
+                    }
+                }
+                written_value(&encoded, value_type, access_size)
+            },
+            _ => Err(ModbusError::DataSizeNotMatch(words.len())),
+        }
+
+    }
+
 }
 
-async fn build_rtu_session(serial_port: String, baudrate: u32) -> Result<Context, String> {
+// register reads go through Client::call, the Reader helpers of tokio-modbus assert the answered
+// count in debug builds, which would abort before a miscounted answer is reported or fitted
+async fn read_registers(context: &mut Context, block_type: BlockType, address: u16, count: u16) -> tokio_modbus::Result<Vec<u16>> {
+
+    let request = match block_type {
+        BlockType::Ir => Request::ReadInputRegisters(address, count),
+        _ => Request::ReadHoldingRegisters(address, count),
+    };
+
+    Ok(context.call(request).await?.map(|response| match response {
+        Response::ReadHoldingRegisters(words) | Response::ReadInputRegisters(words) => words,
+        _ => Vec::new(), // the transport already matched the function code
+    }))
+
+}
+
+const RTU_RESPONSE_TIMEOUT: Duration = Duration::from_millis(1000);
+
+async fn build_rtu_session(serial_port: String, baudrate: u32, flow_control: FlowControl, tap: Option<FrameTap>) -> Result<Context, String> {
 
     let builder = tokio_serial::new(&serial_port, baudrate)
         .parity(tokio_serial::Parity::None)
         .stop_bits(tokio_serial::StopBits::One)
         .data_bits(tokio_serial::DataBits::Eight)
-        .timeout(std::time::Duration::from_millis(1000));
+        .flow_control(match flow_control {
+            FlowControl::None => tokio_serial::FlowControl::None,
+            FlowControl::Hardware => tokio_serial::FlowControl::Hardware,
+            FlowControl::Software => tokio_serial::FlowControl::Software,
+        })
+        .timeout(RTU_RESPONSE_TIMEOUT);
 
     let serial: SerialStream = match SerialStream::open(&builder) {
         Ok(serial) => serial,
         Err(e) => return Err(format!("Failed to open {:?}: {:?}", serial_port, e)),
     };
 
-    Ok(rtu::attach(serial))
+    Ok(match tap {
+        Some(tap) => rtu::attach(Tapped { inner: serial, tap }),
+        None => rtu::attach(serial),
+    })
 
 }
 
-async fn build_tcp_session(host_addr: String, port: u32) -> Result<Context, String> {
+#[cfg(feature = "usb_hid")]
+const HID_REPORT_SIZE: usize = 64;
 
-    let mut addr = String::from(host_addr);
-    addr.push_str(":");
-    addr.push_str(&format!("{}", port));
-    
-    let socket_addr = match addr.parse() {
-        Ok(socket_addr) => socket_addr,
-        Err(e) => return Err(format!("Failed to parse socket-addr: {:?}: {:?}", addr, e)),
+#[cfg(feature = "usb_hid")]
+async fn build_usb_hid_session(vendor_id: u16, product_id: u16) -> Result<Context, String> {
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let api = match hidapi::HidApi::new() {
+        Ok(api) => api,
+        Err(e) => return Err(format!("Failed to init hidapi: {:?}", e)),
+    };
+    let device = match api.open(vendor_id, product_id) {
+        Ok(device) => device,
+        Err(e) => return Err(format!("Failed to open {:04x}:{:04x}: {:?}", vendor_id, product_id, e)),
     };
 
-    match tcp::connect(socket_addr).await {
-        Ok(socket) => Ok(socket),
-        Err(e) => Err(format!("Failed to connect to {:?}: {:?}", addr, e)),
-    }
+    // hidapi blocks, so rtu frames are pumped through an in-memory pipe on a dedicated thread;
+    // reports carry the raw frame bytes after a zero report id
+    let (transport, mut bridge) = tokio::io::duplex(HID_REPORT_SIZE * 4);
+    let runtime = tokio::runtime::Handle::current();
+    std::thread::spawn(move || {
+        let mut outgoing = [0u8; HID_REPORT_SIZE];
+        let mut incoming = [0u8; HID_REPORT_SIZE];
+        loop {
+            let pending = runtime.block_on(async {
+                time::timeout(Duration::from_millis(5), bridge.read(&mut outgoing[1..])).await
+            });
+            match pending {
+                Ok(Ok(0)) | Ok(Err(_)) => break, // session dropped
+                Ok(Ok(size)) => {
+                    if device.write(&outgoing[..size + 1]).is_err() {
+                        break;
+                    }
+                },
+                Err(_) => {},
+            }
+            match device.read_timeout(&mut incoming, 5) {
+                Ok(0) => {},
+                Ok(size) => {
+                    if runtime.block_on(bridge.write_all(&incoming[..size])).is_err() {
+                        break;
+                    }
+                },
+                Err(_) => break,
+            }
+        }
+    });
+
+    Ok(rtu::attach(transport))
 
 }
 
+async fn build_tcp_session(host_addr: String, port: u32, connect_timeout: Duration, tap: Option<FrameTap>) -> Result<Context, String> {
 
-pub async fn batch_request(interface: Interface, request_info: Vec<(String, (String, Option<Value>))>, get_or_set: GetOrSet) -> Result<Vec<(String, Value)>, ModbusError> {
+    // joined as an address, not as text, so an IPv6 host needs no brackets
+    let host = host_addr.trim_start_matches('[').trim_end_matches(']');
+    let socket_addrs: Vec<SocketAddr> = match host.parse::<IpAddr>() {
+        Ok(ip_addr) => vec![SocketAddr::new(ip_addr, port as u16)],
+        // a host name is resolved on every connect, so a changed DNS record is followed
+        Err(_) => match time::timeout(connect_timeout, net::lookup_host((host, port as u16))).await {
+            Ok(Ok(resolved)) => resolved.collect(),
+            Ok(Err(e)) => return Err(format!("Failed to resolve {:?}: {:?}", host_addr, e)),
+            Err(_) => return Err(format!("Failed to resolve {:?}: no answer within {:?}", host_addr, connect_timeout)),
+        },
+    };
+    let addr = match socket_addrs.as_slice() {
+        [socket_addr] => socket_addr.to_string(),
+        _ => format!("{}:{}", host, port),
+    };
 
-    let mut context = match interface.modbusprotocol() {
-        ModbusProtocol::Rtu => {
-            match build_rtu_session(interface.address(), interface.config()).await {
-                Ok(context) => context, Err(info) => {
+    // every address a name resolves to is tried in turn
+    match time::timeout(connect_timeout, TcpStream::connect(socket_addrs.as_slice())).await {
+        Ok(Ok(socket)) => Ok(match tap {
+            Some(tap) => tcp::attach(Tapped { inner: socket, tap }),
+            None => tcp::attach(socket),
+        }),
+        Ok(Err(e)) => Err(format!("Failed to connect to {:?}: {:?}", addr, e)),
+        Err(_) => Err(format!("Failed to connect to {:?}: no answer within {:?}", addr, connect_timeout)),
+    }
+
+}
+
+
+static ACTIVE_SESSIONS: AtomicUsize = AtomicUsize::new(0);
+
+// counts a session as active for as long as it is alive
+struct SessionGuard;
+
+impl SessionGuard {
+
+    fn new() -> Self {
+
+        ACTIVE_SESSIONS.fetch_add(1, Ordering::Relaxed);
+        SessionGuard
+
+    }
+
+}
+
+impl Drop for SessionGuard {
+
+    fn drop(&mut self) {
+
+        ACTIVE_SESSIONS.fetch_sub(1, Ordering::Relaxed);
+
+    }
+
+}
+
+pub fn active_sessions() -> usize {
+
+    ACTIVE_SESSIONS.load(Ordering::Relaxed)
+
+}
+
+struct Connection {
+    connected: bool, // outcome of the last connect attempt
+    parked: Option<(Context, SessionGuard)>, // opened by an eager connect, taken by the first request
+}
+
+// connection per interface, absent until a connect was attempted
+static CONNECTIONS: Mutex<BTreeMap<String, Connection>> = Mutex::new(BTreeMap::new());
+
+fn record_connection(interface_name: &str, connected: bool) {
+
+    let mut connections = CONNECTIONS.lock().expect("Connection state poisoned");
+    let state_name = |connected: bool| if connected { "online" } else { "offline" };
+    match connections.get_mut(interface_name) {
+        Some(connection) => {
+            if connection.connected != connected {
+                warn!("Device '{}' changed state: {} -> {}", interface_name, state_name(connection.connected), state_name(connected));
+            }
+            connection.connected = connected;
+        },
+        None => {
+            connections.insert(String::from(interface_name), Connection { connected, parked: None });
+        },
+    }
+
+}
+
+pub fn connection_state(interface_name: &str) -> Option<bool> {
+
+    CONNECTIONS.lock().expect("Connection state poisoned").get(interface_name).map(|connection| connection.connected)
+
+}
+
+// forgets the connection of an interface, closing a session an eager connect left parked
+pub fn close_session(interface_name: &str) {
+
+    CONNECTIONS.lock().expect("Connection state poisoned").remove(interface_name);
+    WATCHDOG_TIMEOUTS.lock().expect("Watchdog state poisoned").remove(interface_name);
+
+}
+
+// the session an eager connect left for this interface, or a new one
+async fn take_session(interface_name: &str, interface: &Interface) -> Result<(Context, SessionGuard), ModbusError> {
+
+    let parked = CONNECTIONS.lock().expect("Connection state poisoned")
+        .get_mut(interface_name)
+        .and_then(|connection| connection.parked.take());
+    if let Some(parked) = parked {
+        return Ok(parked);
+    }
+
+    let session = open_session(interface).await;
+    record_connection(interface_name, session.is_ok());
+    let context = session
+        .map_err(|e| e.with_context(format!("connecting to {}", interface_name)))?;
+
+    Ok((context, SessionGuard::new()))
+
+}
+
+const CONNECT_ATTEMPTS: u32 = 3;
+const CONNECT_BACKOFF: Duration = Duration::from_millis(500);
+
+pub async fn connect_eager(interface_name: &str, interface: &Interface) -> Result<(), ModbusError> {
+
+    let mut backoff = CONNECT_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match open_session(interface).await {
+            Ok(context) => {
+                record_connection(interface_name, true);
+                if let Some(connection) = CONNECTIONS.lock().expect("Connection state poisoned").get_mut(interface_name) {
+                    connection.parked = Some((context, SessionGuard::new()));
+                }
+                return Ok(());
+            },
+            Err(e) if attempt < CONNECT_ATTEMPTS => {
+                warn!("Connect to '{}' failed (attempt {}/{}), retrying in {:?}: {}", interface_name, attempt, CONNECT_ATTEMPTS, backoff, e);
+                time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            },
+            Err(e) => {
+                record_connection(interface_name, false);
+                return Err(e.with_context(format!("connecting to {}", interface_name)));
+            },
+        }
+    }
+
+}
+
+// recent unanswered requests per watched interface as (when, slave id), cleared by any answer
+static WATCHDOG_TIMEOUTS: Mutex<BTreeMap<String, VecDeque<(Instant, u8)>>> = Mutex::new(BTreeMap::new());
+static WATCHDOG_RECOVERIES: Mutex<BTreeMap<String, usize>> = Mutex::new(BTreeMap::new());
+
+// successful reopens per interface since start
+pub fn watchdog_recoveries() -> BTreeMap<String, usize> {
+
+    WATCHDOG_RECOVERIES.lock().expect("Watchdog state poisoned").clone()
+
+}
+
+fn watchdog_tripped(interface_name: &str, watchdog: Watchdog, slave_id: u8, timed_out: bool) -> bool {
+
+    let mut watchdog_timeouts = WATCHDOG_TIMEOUTS.lock().expect("Watchdog state poisoned");
+    let timeouts = watchdog_timeouts.entry(String::from(interface_name)).or_default();
+    if !timed_out {
+        timeouts.clear();
+        return false;
+    }
+
+    timeouts.push_back((Instant::now(), slave_id));
+    while timeouts.front().is_some_and(|(timed_out_at, _)| timed_out_at.elapsed() > watchdog.window) {
+        timeouts.pop_front();
+    }
+    // a single dead slave times out on its own, a wedged port silences all of them
+    let slave_count = timeouts.iter().map(|(_, slave_id)| *slave_id).collect::<HashSet<u8>>().len();
+    if timeouts.len() >= watchdog.timeouts && slave_count > 1 {
+        timeouts.clear();
+        return true;
+    }
+
+    false
+
+}
+
+async fn reopen_session(interface_name: &str, interface: &Interface, watchdog: Watchdog, wedged: Context) -> Result<Context, ModbusError> {
+
+    drop(wedged); // serial ports are opened exclusively, close before reopening
+    for attempt in 1..=watchdog.reopen_attempts {
+        warn!("Watchdog reopening '{}' (attempt {}/{})", interface_name, attempt, watchdog.reopen_attempts);
+        match open_session(interface).await {
+            Ok(context) => {
+                *WATCHDOG_RECOVERIES.lock().expect("Watchdog state poisoned").entry(String::from(interface_name)).or_default() += 1;
+                info!("Watchdog reopened '{}'", interface_name);
+                return Ok(context);
+            },
+            Err(e) => warn!("Watchdog failed to reopen '{}': {}", interface_name, e),
+        }
+    }
+
+    record_connection(interface_name, false);
+    Err(ModbusError::TransportError(format!("watchdog gave up after {} reopen attempts", watchdog.reopen_attempts))
+        .with_context(format!("recovering {}", interface_name)))
+
+}
+
+// end of the last transaction per serial bus, kept across sessions
+static LAST_FRAMES: Mutex<BTreeMap<String, Instant>> = Mutex::new(BTreeMap::new());
+
+// holds each request back until the bus has been silent for the inter-frame gap
+struct PacedClient {
+    inner: Context,
+    bus: String,
+    gap: Duration,
+}
+
+impl SlaveContext for PacedClient {
+
+    fn set_slave(&mut self, slave: Slave) {
+
+        self.inner.set_slave(slave);
+
+    }
+
+}
+
+#[async_trait]
+impl Client for PacedClient {
+
+    async fn call(&mut self, request: Request<'_>) -> tokio_modbus::Result<Response> {
+
+        let last_frame = LAST_FRAMES.lock().expect("Frame times poisoned").get(&self.bus).copied();
+        if let Some(last_frame) = last_frame {
+            let silence = last_frame.elapsed();
+            if silence < self.gap {
+                time::sleep(self.gap - silence).await;
+            }
+        }
+        let response = self.inner.call(request).await;
+        LAST_FRAMES.lock().expect("Frame times poisoned").insert(self.bus.clone(), Instant::now());
+
+        response
+
+    }
+
+    async fn disconnect(&mut self) -> io::Result<()> {
+
+        self.inner.disconnect().await
+
+    }
+
+}
+
+// the bytes read since the last request was written, so an answer the codec refuses can still be logged
+#[derive(Clone, Default, Debug)]
+struct FrameTap(Arc<Mutex<Vec<u8>>>);
+
+impl FrameTap {
+
+    fn frame(&self) -> Vec<u8> {
+
+        self.0.lock().expect("Frame tap poisoned").clone()
+
+    }
+
+}
+
+#[derive(Debug)]
+struct Tapped<T> {
+    inner: T,
+    tap: FrameTap,
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Tapped<T> {
+
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+
+        let filled = buf.filled().len();
+        let polled = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = polled {
+            self.tap.0.lock().expect("Frame tap poisoned").extend_from_slice(&buf.filled()[filled..]);
+        }
+
+        polled
+
+    }
+
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Tapped<T> {
+
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+
+        // a new request, its answer starts with the next read
+        self.tap.0.lock().expect("Frame tap poisoned").clear();
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+
+        Pin::new(&mut self.inner).poll_flush(cx)
+
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+
+    }
+
+}
+
+fn lenient_coil_echo(protocol: ModbusProtocol, tap: Option<FrameTap>, context: Context) -> Context {
+
+    match tap {
+        Some(tap) => Context::from(Box::new(LenientCoilClient { inner: context, protocol, tap }) as Box<dyn Client>),
+        None => context,
+    }
+
+}
+
+// accepts the nonzero echo some devices answer a coil switched on with (0x0001 instead of 0xFF00),
+// the codec refuses any value but 0xFF00 and 0x0000 before the answer could be looked at
+struct LenientCoilClient {
+    inner: Context,
+    protocol: ModbusProtocol,
+    tap: FrameTap,
+}
+
+impl LenientCoilClient {
+
+    // the answer echoes function and address, only its value bytes are off
+    fn is_nonzero_echo(&self, frame: &[u8], address: u16) -> bool {
+
+        let pdu = match (self.protocol, frame.len()) {
+            (ModbusProtocol::Tcp, 12) => &frame[7..], // after the mbap header
+            (ModbusProtocol::Rtu, 8) => &frame[1..6], // between unit id and crc
+            _ => return false,
+        };
+
+        pdu[0] == 0x05 && pdu[1..3] == address.to_be_bytes() && pdu[3..5] != [0, 0]
+
+    }
+
+}
+
+impl SlaveContext for LenientCoilClient {
+
+    fn set_slave(&mut self, slave: Slave) {
+
+        self.inner.set_slave(slave);
+
+    }
+
+}
+
+#[async_trait]
+impl Client for LenientCoilClient {
+
+    async fn call(&mut self, request: Request<'_>) -> tokio_modbus::Result<Response> {
+
+        let switched_on = match request {
+            Request::WriteSingleCoil(address, true) => Some(address),
+            _ => None,
+        };
+        match (self.inner.call(request).await, switched_on) {
+            (Err(tokio_modbus::Error::Transport(err)), Some(address)) if err.kind() == ErrorKind::InvalidData => {
+                let frame = self.tap.frame();
+                if !self.is_nonzero_echo(&frame, address) {
+                    return Err(tokio_modbus::Error::Transport(err));
+                }
+                let frame: Vec<String> = frame.iter().map(|byte| format!("{:02X}", byte)).collect();
+                warn!("Nonstandard coil echo accepted at {}: {}", address, frame.join(" "));
+                Ok(Ok(Response::WriteSingleCoil(address, true)))
+            },
+            (response, _) => response,
+        }
+
+    }
+
+    async fn disconnect(&mut self) -> io::Result<()> {
+
+        self.inner.disconnect().await
+
+    }
+
+}
+
+// bounds each transaction on its own, so requests of several round trips are not cut short;
+// rtu frames carry no transaction id, a timed out session is reopened before the next transaction
+// so the late rest of the abandoned answer can not be read as the next one
+struct TimedClient {
+    inner: Option<Context>,
+    interface: Interface,
+    slave: Option<Slave>,
+    timeout: Duration,
+}
+
+impl SlaveContext for TimedClient {
+
+    fn set_slave(&mut self, slave: Slave) {
+
+        self.slave = Some(slave);
+        if let Some(inner) = &mut self.inner {
+            inner.set_slave(slave);
+        }
+
+    }
+
+}
+
+#[async_trait]
+impl Client for TimedClient {
+
+    async fn call(&mut self, request: Request<'_>) -> tokio_modbus::Result<Response> {
+
+        let inner = match &mut self.inner {
+            Some(inner) => inner,
+            None => {
+                let mut context = open_transport(&self.interface).await
+                    .map_err(|e| io::Error::new(ErrorKind::NotConnected, e.to_string()))?;
+                if let Some(slave) = self.slave {
+                    context.set_slave(slave);
+                }
+                self.inner.insert(context)
+            },
+        };
+        match time::timeout(self.timeout, inner.call(request)).await {
+            Ok(response) => response,
+            Err(_) => {
+                self.inner = None;
+                Err(io::Error::new(ErrorKind::TimedOut, format!("no response within {:?}", self.timeout)).into())
+            },
+        }
+
+    }
+
+    async fn disconnect(&mut self) -> io::Result<()> {
+
+        match &mut self.inner {
+            Some(inner) => inner.disconnect().await,
+            None => Ok(()),
+        }
+
+    }
+
+}
+
+const RECONNECT_BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+// what a session does once its connection is lost
+#[derive(Clone, Copy, PartialEq)]
+pub enum ReconnectPolicy {
+    Immediate, // open again right away, until it succeeds
+    FixedDelay(Duration),
+    ExponentialBackoff { initial: Duration, max: Duration, factor: f64 },
+    NoReconnect, // the transport error is returned as is
+}
+
+impl Default for ReconnectPolicy {
+
+    fn default() -> Self {
+
+        ReconnectPolicy::ExponentialBackoff { initial: CONNECT_BACKOFF, max: RECONNECT_BACKOFF_MAX, factor: 2.0 }
+
+    }
+
+}
+
+impl ReconnectPolicy {
+
+    // the pause before reconnect attempt 1, 2, ..; None when there is no attempt to make
+    pub fn delay(&self, attempt: u32) -> Option<Duration> {
+
+        match *self {
+            ReconnectPolicy::Immediate => Some(Duration::ZERO),
+            ReconnectPolicy::FixedDelay(delay) => Some(delay),
+            ReconnectPolicy::ExponentialBackoff { initial, max, factor } => {
+                let secs = initial.as_secs_f64() * factor.powi(i32::try_from(attempt.saturating_sub(1)).unwrap_or(i32::MAX));
+                Some(Duration::try_from_secs_f64(secs).map_or(max, |delay| delay.min(max)))
+            },
+            ReconnectPolicy::NoReconnect => None,
+        }
+
+    }
+
+}
+
+// an open session along with the connection it was opened on, see Interface::from_modbus_scan
+pub struct ModbusSession {
+    pub context: Context,
+    pub interface: Interface,
+    pub reconnect_policy: ReconnectPolicy,
+}
+
+impl ModbusSession {
+
+    pub async fn open(interface: &Interface) -> Result<ModbusSession, ModbusError> {
+
+        Ok(ModbusSession {
+            context: open_session(interface).await?,
+            interface: interface.clone(),
+            reconnect_policy: ReconnectPolicy::default(),
+        })
+
+    }
+
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+
+        self.reconnect_policy = policy;
+        self
+
+    }
+
+    // opens the connection again, retried until it succeeds with the pauses the policy gives
+    pub async fn reconnect(&mut self) -> Result<(), ModbusError> {
+
+        let mut attempt = 1;
+        loop {
+            let delay = self.reconnect_policy.delay(attempt)
+                .ok_or_else(|| ModbusError::TransportError(String::from("connection lost and reconnecting is disabled")))?;
+            time::sleep(delay).await;
+            match open_session(&self.interface).await {
+                Ok(context) => {
+                    self.context = context;
+                    info!("Reconnected to {} after {} attempt(s)", self.interface.address(), attempt);
+                    return Ok(());
+                },
+                Err(e) => warn!("Reconnect to {} failed (attempt {}), retrying: {}", self.interface.address(), attempt, e),
+            }
+            attempt += 1;
+        }
+
+    }
+
+    // a request that lost the connection is sent once more after reconnecting, unless the policy is NoReconnect
+    pub async fn call(&mut self, slave_id: u8, request: Request<'static>) -> Result<Response, ModbusError> {
+
+        self.context.set_slave(Slave(slave_id));
+        let response = match self.context.call(request.clone()).await {
+            Err(tokio_modbus::Error::Transport(err)) if self.reconnect_policy != ReconnectPolicy::NoReconnect => {
+                warn!("Connection to {} lost: {}", self.interface.address(), err);
+                self.reconnect().await?;
+                self.context.set_slave(Slave(slave_id));
+                self.context.call(request).await
+            },
+            response => response,
+        };
+
+        response.map_err(ModbusError::from)?.map_err(ModbusError::ModbusException)
+
+    }
+
+}
+
+pub async fn open_session(interface: &Interface) -> Result<Context, ModbusError> {
+
+    let context = open_transport(interface).await?;
+
+    Ok(match interface.watchdog() {
+        Some(_) => Context::from(Box::new(TimedClient {
+            inner: Some(context),
+            interface: interface.clone(),
+            slave: None,
+            timeout: RTU_RESPONSE_TIMEOUT,
+        }) as Box<dyn Client>),
+        None => context,
+    })
+
+}
+
+async fn open_transport(interface: &Interface) -> Result<Context, ModbusError> {
+
+    match interface.modbusprotocol() {
+        ModbusProtocol::Rtu => {
+            let tap = interface.lenient_coil_echo().then(FrameTap::default);
+            match build_rtu_session(interface.address(), interface.config(), interface.flow_control(), tap.clone()).await {
+                Ok(context) => Ok(lenient_coil_echo(ModbusProtocol::Rtu, tap, match interface.interframe_gap() {
+                    Some(gap) if !gap.is_zero() => Context::from(Box::new(PacedClient {
+                        inner: context,
+                        bus: interface.address(),
+                        gap,
+                    }) as Box<dyn Client>),
+                    _ => context,
+                })),
+                Err(info) => {
                     let msg = format!("Failed to create rtu session: {}", info);
                     error!("ModbusError: {}", msg);
-                    return Err(ModbusError::ModbusError(msg));
+                    Err(ModbusError::ModbusError(msg))
                 }
             }
         },
         ModbusProtocol::Tcp => {
-            match build_tcp_session(interface.address(), interface.config()).await {
-                Ok(context) => context, Err(info) => {
+            let tap = interface.lenient_coil_echo().then(FrameTap::default);
+            match build_tcp_session(interface.address(), interface.config(), interface.connect_timeout(), tap.clone()).await {
+                Ok(context) => Ok(lenient_coil_echo(ModbusProtocol::Tcp, tap, context)), Err(info) => {
                     let msg = format!("Failed to create tcp session: {}", info);
                     error!("ModbusError: {}", msg);
-                    return Err(ModbusError::ModbusError(msg));
+                    Err(ModbusError::ModbusError(msg))
+                }
+            }
+        },
+        #[cfg(feature = "usb_hid")]
+        ModbusProtocol::UsbHid => {
+            let (vendor_id, product_id) = interface.usb_hid_ids()
+                .ok_or_else(|| ModbusError::ModbusError(format!("Invaild usb hid ids: {}", interface.address())))?;
+            match build_usb_hid_session(vendor_id, product_id).await {
+                Ok(context) => Ok(context), Err(info) => {
+                    let msg = format!("Failed to create usb hid session: {}", info);
+                    error!("ModbusError: {}", msg);
+                    Err(ModbusError::ModbusError(msg))
+                }
+            }
+        },
+    }
+
+}
+
+const SCAN_SIZE: u16 = 125;
+
+async fn scan_block(context: &mut Context, block_type: BlockType, address: u16, count: u16) -> bool {
+
+    let request = async {
+        match block_type {
+            BlockType::Co => context.read_coils(address, count).await.map(|response| response.is_ok()),
+            BlockType::Di => context.read_discrete_inputs(address, count).await.map(|response| response.is_ok()),
+            BlockType::Hr => context.read_holding_registers(address, count).await.map(|response| response.is_ok()),
+            BlockType::Ir => context.read_input_registers(address, count).await.map(|response| response.is_ok()),
+        }
+    };
+
+    matches!(time::timeout(Duration::from_millis(1000), request).await, Ok(Ok(true)))
+
+}
+
+pub async fn scan_slave(context: &mut Context, slave_id: u8) -> SlaveData {
+
+    context.set_slave(Slave(slave_id));
+
+    let mut registers = ModbusDataMap::new();
+
+    for block_type in [BlockType::Co, BlockType::Di, BlockType::Hr, BlockType::Ir] {
+
+        let mut found = Vec::new();
+        if scan_block(context, block_type, 0, SCAN_SIZE).await {
+            found.extend(0..SCAN_SIZE);
+        } else {
+            for address in 0..SCAN_SIZE {
+                if scan_block(context, block_type, address, 1).await {
+                    found.push(address);
                 }
             }
+        }
+        info!("Scan slave {} {}: {} address(es) responded", slave_id, block_type, found.len());
+
+        let value_type = match block_type {
+            BlockType::Co | BlockType::Di => ValueType::Bool,
+            BlockType::Hr | BlockType::Ir => ValueType::U16,
+        };
+        for address in found {
+            let name = format!("{}_{:04}", block_type.to_string().to_lowercase(), address);
+            registers.insert(block_type, name, ModbusData::new(address as u8, block_type, value_type, RequestFunction::Multiple));
+        }
+
+    }
+
+    SlaveData::new(slave_id, registers)
+
+}
+
+// an address range as the bytes on the wire: registers high byte first, bits packed lowest first
+pub async fn read_bytes(interface: Interface, slave_name: String, block_type: BlockType, start: u16, count: u16) -> Result<Vec<u8>, ModbusError> {
+
+    let slave_id = match interface.resolve_slave(&slave_name) {
+        Some((_, slave_id)) => slave_id,
+        None => return Err(ModbusError::SlaveNotFound(slave_name)),
+    };
+
+    let mut context = open_session(&interface).await?;
+    let _session = SessionGuard::new();
+    context.set_slave(Slave(slave_id));
+
+    let bytes = match block_type {
+        BlockType::Co | BlockType::Di => {
+            let response = match block_type {
+                BlockType::Co => context.read_coils(start, count).await,
+                _ => context.read_discrete_inputs(start, count).await,
+            };
+            let bits = response.map_err(ModbusError::from)?.map_err(ModbusError::ModbusException)?;
+            bits.chunks(8)
+                .map(|chunk| chunk.iter().enumerate().fold(0, |byte, (bit, state)| byte | ((*state as u8) << bit)))
+                .collect()
+        },
+        BlockType::Hr | BlockType::Ir => {
+            let response = read_registers(&mut context, block_type, start, count).await;
+            let words = response.map_err(ModbusError::from)?.map_err(ModbusError::ModbusException)?;
+            words.iter().flat_map(|word| word.to_be_bytes()).collect()
+        },
+    };
+
+    Ok(bytes)
+
+}
+
+fn identification_name(object_id: u8) -> String {
+
+    match object_id {
+        0x00 => String::from("vendor_name"),
+        0x01 => String::from("product_code"),
+        0x02 => String::from("revision"),
+        0x03 => String::from("vendor_url"),
+        0x04 => String::from("product_name"),
+        0x05 => String::from("model_name"),
+        0x06 => String::from("user_application_name"),
+        _ => format!("object_0x{:02X}", object_id),
+    }
+
+}
+
+pub async fn identify(interface: Interface, slave_name: String) -> Result<Value, ModbusError> {
+
+    let slave_id = match interface.resolve_slave(&slave_name) {
+        Some((_, slave_id)) => slave_id,
+        None => return Err(ModbusError::SlaveNotFound(slave_name)),
+    };
+
+    let mut context = open_session(&interface).await?;
+    let _session = SessionGuard::new();
+    context.set_slave(Slave(slave_id));
+
+    // basic objects are streamed, follow more_follows until the slave is done
+    let mut objects = serde_json::Map::new();
+    let mut object_id = 0x00;
+    loop {
+        let response = match context.read_device_identification(ReadCode::Basic, object_id).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(err)) => return Err(ModbusError::ModbusException(err)),
+            Err(err) => return Err(ModbusError::from(err)),
+        };
+        for object in &response.device_id_objects {
+            objects.insert(identification_name(object.id), Value::String(String::from_utf8_lossy(&object.value).into_owned()));
+        }
+        if !response.more_follows || response.next_object_id <= object_id {
+            break;
+        }
+        object_id = response.next_object_id;
+    }
+
+    Ok(Value::Object(objects))
+
+}
+
+const WAKEUP_TIMEOUT: Duration = Duration::from_millis(1000);
+
+async fn wake_slave(context: &mut Context, slave_id: u8, register: u16) {
+
+    // sleeping slaves drop the first frame, so the outcome is irrelevant
+    let result = time::timeout(WAKEUP_TIMEOUT, context.read_holding_registers(register, 1)).await;
+    debug!("Wake-up read of slave {} at {}: {}", slave_id, register, match result {
+        Ok(Ok(Ok(_))) => "answered",
+        Ok(_) => "failed",
+        Err(_) => "timed out",
+    });
+
+}
+
+static AUDIT_LOG: Mutex<Option<String>> = Mutex::new(None);
+
+// bound of the read before a write, so an unanswered read does not hold the write back
+const AUDIT_READ_TIMEOUT: Duration = Duration::from_millis(1000);
+
+// the audit log of interfaces that do not set their own
+pub fn set_audit_log(path: Option<String>) {
+
+    *AUDIT_LOG.lock().expect("Audit log poisoned") = path;
+
+}
+
+fn audit_log_path(interface: &Interface) -> Option<String> {
+
+    interface.audit_log().map(String::from)
+        .or_else(|| AUDIT_LOG.lock().expect("Audit log poisoned").clone())
+
+}
+
+// one line per write attempt, opened for each append so the file can be rotated underneath
+fn append_audit(path: &str, entry: &Value) {
+
+    let appended = OpenOptions::new().create(true).append(true).open(path)
+        .and_then(|mut file| writeln!(file, "{}", entry));
+    if let Err(e) = appended {
+        error!("Failed to append to audit log '{}': {}", path, e);
+    }
+
+}
+
+// the point and its shadow are read back to back and have to agree word by word before the point is decoded
+async fn read_shadowed(context: &mut Context, modbus_data: &ModbusData, shadow: Shadow, quirks: Quirks) -> Result<Value, ModbusError> {
+
+    let read_function = match modbus_data.block_type() {
+        BlockType::Hr => ModbusFunction::ReadHodingRegisters,
+        _ => ModbusFunction::ReadInputRegisters,
+    };
+    let block_type = modbus_data.block_type();
+    let access_size = modbus_data.count();
+    let read_size = access_size.max(quirks.min_read_quantity);
+    let mut reads = Vec::new();
+    for address in [modbus_data.address() as u16, shadow.address() as u16] {
+        let mut response = read_registers(context, block_type, address, read_size).await
+            .map_err(ModbusError::from)?
+            .map_err(ModbusError::ModbusException)?;
+        read_function.check_response_size(address, read_size, &response)?;
+        response.truncate(access_size as usize);
+        reads.push(response);
+    }
+    if !reads[0].iter().map(|word| shadow.formula().apply(*word)).eq(reads[1].iter().copied()) {
+        return Err(ModbusError::ModbusError(String::from("checksum validation failed")));
+    }
+
+    read_value(&reads[0], modbus_data)
+
+}
+
+// what a SET of this point would write and answer, checked and encoded without touching the bus
+pub fn simulate_write(name: &str, modbus_data: &ModbusData, value: &Option<Value>) -> Result<Value, ModbusError> {
+
+    let invailed = || ModbusError::InvailedValueInput(value.clone().unwrap_or(Value::Null));
+    let (modbus_function, access_size) = ModbusFunction::inference(modbus_data, GetOrSet::Set)
+        .ok_or(ModbusError::WriteInputValue(modbus_data.block_type()))?;
+    if modbus_data.cal_table().is_some() {
+        return Err(ModbusError::WriteCalibratedValue(String::from(name)));
+    }
+    // the bits not named are only known after a read, the named ones are answered as requested
+    if let Some(flags) = modbus_data.flags() {
+        value_to_flags(value, flags)?;
+        return value.clone().ok_or_else(invailed);
+    }
+
+    let raw = &match modbus_data.invert() {
+        true => value.as_ref().map(invert_value),
+        false => value.clone(),
+    };
+    let written = match modbus_function {
+        ModbusFunction::WriteSingleCoil => Value::Bool(raw.as_ref().and_then(Value::as_bool).ok_or_else(invailed)?),
+        ModbusFunction::WriteMultipleCoils => coils_to_value(&value_to_coils(raw, access_size)?, access_size)?,
+        _ => {
+            let access_size = modbus_data.words().map_or(access_size, |words| words.len() as u16);
+            let words = value_to_bytes(raw, modbus_data.value_type(), access_size).ok_or_else(invailed)?;
+            written_value(&words, modbus_data.value_type(), access_size)?
         },
     };
 
+    Ok(match modbus_data.invert() {
+        true => invert_value(&written),
+        false => written,
+    })
+
+}
+
+// the logical value of a point as a GET would answer it, without the read cache
+async fn read_point(context: &mut Context, modbus_data: &ModbusData, quirks: Quirks) -> Result<Value, ModbusError> {
+
+    let (read_function, read_size) = ModbusFunction::inference(modbus_data, GetOrSet::Get)
+        .ok_or(ModbusError::DataSizeNotMatch(0))?;
+    let response = match modbus_data.words() {
+        Some(words) => read_function.do_scattered_request(context, words, modbus_data, &None, quirks).await?,
+        None => read_function.do_request(context, modbus_data, read_size, &None, quirks).await?,
+    };
+    let response = match modbus_data.flags() {
+        Some(flags) => flags_to_value(&response, flags)?,
+        None => response,
+    };
+
+    Ok(match modbus_data.invert() {
+        true => invert_value(&response),
+        false => response,
+    })
+
+}
+
+const PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+
+// round trip of each of `count` reads per slave, None where no answer came back in time
+pub async fn probe(interface_name: String, interface: Interface, count: usize) -> Result<Vec<(String, Vec<Option<Duration>>)>, ModbusError> {
+
+    let (mut context, _session) = take_session(&interface_name, &interface).await?;
+
+    // a slave without points is asked for holding register 0, an exception still times the round trip
+    let fallback = ModbusData::new(0, BlockType::Hr, ValueType::U16, RequestFunction::Multiple);
+    let mut results = Vec::new();
+    for slave_name in interface.slave_names() {
+        let (slave, slave_id) = match interface.resolve_slave(&slave_name) {
+            Some(resolved) => resolved,
+            None => continue,
+        };
+        let modbus_data = [BlockType::Co, BlockType::Di, BlockType::Hr, BlockType::Ir].into_iter()
+            .find_map(|block_type| slave.registers().iter_block(block_type)
+                .map(|(_, modbus_data)| modbus_data)
+                .filter(|modbus_data| modbus_data.words().is_none())
+                .min_by_key(|modbus_data| modbus_data.address()))
+            .unwrap_or(&fallback);
+        let (modbus_function, access_size) = match ModbusFunction::inference(modbus_data, GetOrSet::Get) {
+            Some(pair) => pair,
+            None => continue,
+        };
+        context.set_slave(Slave(slave_id));
+        if let Some(register) = slave.wakeup() {
+            wake_slave(&mut context, slave_id, register).await;
+        }
+
+        let mut round_trips = Vec::new();
+        for _ in 0..count {
+            let started = Instant::now();
+            let response = time::timeout(PROBE_TIMEOUT, modbus_function.do_request(&mut context, modbus_data, access_size, &None, Quirks::of(&interface, slave))).await;
+            round_trips.push(match response {
+                Ok(Err(ModbusError::TransportError(_) | ModbusError::Timeout(_))) => None,
+                Ok(_) => Some(started.elapsed()),
+                Err(_) => {
+                    // cancelled mid-transaction, a fresh transport keeps the late answer from being taken for the next probe
+                    drop(context); // serial ports are opened exclusively, close before reopening
+                    context = open_session(&interface).await
+                        .map_err(|e| e.with_context(format!("reopening {} after a lost probe", interface_name)))?;
+                    context.set_slave(Slave(slave_id));
+                    None
+                },
+            });
+        }
+        results.push((slave_name, round_trips));
+    }
+
+    Ok(results)
+
+}
+
+pub async fn batch_request(interface_name: String, interface: Interface, request_info: RequestInfo) -> Result<Vec<ItemResult>, ModbusError> {
+
+    batch_request_items(interface_name, interface, request_info, OnError::Abort).await?
+        .into_iter()
+        .collect()
+
+}
+
+// one result per attempted item, in request order; with OnError::Abort the last one is the failure
+pub async fn batch_request_items(interface_name: String, interface: Interface, request_info: RequestInfo, on_error: OnError) -> Result<Vec<Result<ItemResult, ModbusError>>, ModbusError> {
+
+    let (mut context, _session) = take_session(&interface_name, &interface).await?;
+
     let mut results = Vec::new();
+    let mut woken = HashSet::new();
+    let mut read_values: HashMap<(u8, String), Value> = HashMap::new(); // aliases of one point are read once
+    let audit_log = audit_log_path(&interface);
 
-    for (slave_name, (value_name, value)) in &request_info {
+    macro_rules! fail_item {
+        ($error:expr) => {{
+            results.push(Err($error));
+            match on_error {
+                OnError::Abort => break,
+                OnError::Continue => continue,
+            }
+        }};
+    }
+
+    for RequestItem { slave: slave_name, name: value_name, op: get_or_set, value, options } in &request_info {
+
+        let get_or_set = *get_or_set;
+        let operation = match get_or_set {
+            GetOrSet::Get => "reading",
+            GetOrSet::Set => "writing",
+        };
+        let item_context = || format!("{} {}/{}/{}", operation, interface_name, slave_name, value_name);
         
-        let slave = match interface.slaves.get(slave_name) {
-            Some(slave) => slave, None => {
+        let (slave, slave_id) = match interface.resolve_slave(slave_name) {
+            Some(resolved) => resolved, None => {
                 warn!("SlaveNotFound: {}", slave_name);
-                return Err(ModbusError::SlaveNotFound(slave_name.to_string()));
+                fail_item!(ModbusError::SlaveNotFound(slave_name.to_string()).with_context(item_context()));
             }
         };
-        let modbus_data = match slave.find(&value_name) {
+        let modbus_data = match slave.find(value_name) {
             Some(modbus_data) => modbus_data, None => {
                 let info = format!("{} in {}", value_name, slave_name);
                 warn!("DataNotFound: {}", info);
-                return Err(ModbusError::ValueNotDefined(info));
+                fail_item!(ModbusError::ValueNotDefined(info).with_context(item_context()));
             }
         };
-        context.set_slave(Slave(slave.id()));
+        if get_or_set == GetOrSet::Set && modbus_data.cal_table().is_some() {
+            warn!("WriteCalibratedValue: {}", value_name);
+            fail_item!(ModbusError::WriteCalibratedValue(value_name.to_string()).with_context(item_context()));
+        }
+        let read_key = (slave_id, String::from(slave.canonical(value_name)));
+        if get_or_set == GetOrSet::Get {
+            if let Some(value) = read_values.get(&read_key) {
+                results.push(Ok(ItemResult {
+                    slave: slave_name.clone(),
+                    name: value_name.clone(),
+                    value: value.clone(),
+                    modbus_data,
+                }));
+                continue;
+            }
+        }
+        context.set_slave(Slave(slave_id));
+        if let Some(register) = slave.wakeup() {
+            if woken.insert(slave_id) {
+                wake_slave(&mut context, slave_id, register).await;
+            }
+        }
         
         let (modbus_function, access_size) = match ModbusFunction::inference(&modbus_data, get_or_set) {
             Some(pair) => pair, None => {
                 warn!("WriteInputValue: {}", modbus_data.block_type());
-                return Err(ModbusError::WriteInputValue(modbus_data.block_type()));
+                fail_item!(ModbusError::WriteInputValue(modbus_data.block_type()).with_context(item_context()));
             }
         };
         
-        match modbus_function.do_request(&mut context, modbus_data.address(), access_size, modbus_data.value_type(), value).await {
+        let quirks = Quirks::of(&interface, slave);
+        // read before the write so the audit line has both sides, null when the read fails or is late
+        let old_value = match (&audit_log, get_or_set) {
+            (Some(_), GetOrSet::Set) => match time::timeout(AUDIT_READ_TIMEOUT, read_point(&mut context, &modbus_data, quirks)).await {
+                Ok(old_value) => Some(old_value.unwrap_or(Value::Null)),
+                Err(_) => {
+                    // cancelled mid-transaction, a fresh transport keeps the late answer from being taken for the write's
+                    warn!("No answer to the audit read of {}/{}/{} within {:?}", interface_name, slave_name, value_name, AUDIT_READ_TIMEOUT);
+                    drop(context); // serial ports are opened exclusively, close before reopening
+                    context = open_session(&interface).await?;
+                    context.set_slave(Slave(slave_id));
+                    Some(Value::Null)
+                },
+            },
+            _ => None,
+        };
+        let request = async {
+            let value = &match modbus_data.invert() {
+                true => value.as_ref().map(invert_value),
+                false => value.clone(),
+            };
+            // flags are written by read-modify-write so bits not named in the request are kept
+            let value = &match modbus_data.flags() {
+                Some(flags) if get_or_set == GetOrSet::Set => {
+                    let (set_mask, clear_mask) = value_to_flags(value, flags)?;
+                    let (read_function, read_size) = ModbusFunction::inference(&modbus_data, GetOrSet::Get)
+                        .ok_or(ModbusError::DataSizeNotMatch(0))?;
+                    let current = match modbus_data.words() {
+                        Some(words) => read_function.do_scattered_request(&mut context, words, &modbus_data, &None, quirks).await?,
+                        None => read_function.do_request(&mut context, &modbus_data, read_size, &None, quirks).await?,
+                    };
+                    let current = current.as_u64().ok_or_else(|| ModbusError::InvailedValueInput(current.clone()))?;
+                    Some(Value::from((current | set_mask) & !clear_mask))
+                },
+                _ => value.clone(),
+            };
+            let response = match (modbus_data.words(), modbus_data.shadow()) {
+                (Some(words), _) => modbus_function.do_scattered_request(&mut context, words, &modbus_data, value, quirks).await,
+                (None, Some(shadow)) if get_or_set == GetOrSet::Get => read_shadowed(&mut context, &modbus_data, shadow, quirks).await,
+                (None, _) => modbus_function.do_request(&mut context, &modbus_data, access_size, value, quirks).await,
+            };
+            // the pulse is in logical states, raw false and back to true on an inverted coil
+            let active = !modbus_data.invert();
+            match (modbus_data.pulse_ms(), response) {
+                (Some(pulse_ms), Ok(Value::Bool(state))) if state == active && get_or_set == GetOrSet::Set => {
+                    time::sleep(Duration::from_millis(pulse_ms)).await;
+                    modbus_function.do_request(&mut context, &modbus_data, access_size, &Some(Value::Bool(!active)), quirks).await
+                        .map(|_| Value::Bool(active))
+                },
+                (_, response) => response,
+            }
+        };
+        let request = async {
+            let response = match modbus_data.flags() {
+                Some(flags) => flags_to_value(&request.await?, flags),
+                None => request.await,
+            };
+            let response = match modbus_data.cal_table() {
+                Some(cal_table) => calibrate(&response?, cal_table),
+                None => response,
+            };
+            match modbus_data.invert() {
+                true => response.map(|response| invert_value(&response)),
+                false => response,
+            }
+        };
+        // the session bounds each transaction, see TimedClient
+        let response = request.await;
+        if let Some(watchdog) = interface.watchdog() {
+            let timed_out = matches!(response, Err(ModbusError::Timeout(_)));
+            if watchdog_tripped(&interface_name, watchdog, slave_id, timed_out) {
+                context = reopen_session(&interface_name, &interface, watchdog, context).await?;
+            }
+        }
+        if let (Some(audit_log), Some(old_value)) = (&audit_log, old_value) {
+            let mut entry = json!({
+                "ts": SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64,
+                "client": options.client,
+                "path": format!("/{}/{}/{}", interface_name, slave_name, value_name),
+                "old": old_value,
+                "new": match &response {
+                    Ok(written) => written.clone(),
+                    Err(_) => value.clone().unwrap_or(Value::Null),
+                },
+                "ok": response.is_ok(),
+            });
+            if let Err(modbus_error) = &response {
+                entry["error"] = Value::from(modbus_error.to_string());
+            }
+            append_audit(audit_log, &entry);
+        }
+        match response {
             Ok(response) => {
-                if get_or_set == GetOrSet::Get {
-                    results.push((value_name.clone(), response));
+                match get_or_set {
+                    GetOrSet::Get => { read_values.insert(read_key, response.clone()); },
+                    GetOrSet::Set => { read_values.remove(&read_key); },
                 }
+                results.push(Ok(ItemResult {
+                    slave: slave_name.clone(),
+                    name: value_name.clone(),
+                    value: response,
+                    modbus_data,
+                }));
             },
             Err(modbus_error) => {
+                let modbus_error = modbus_error.with_context(item_context());
                 warn!("modbus error: {}", modbus_error);
-                return Err(modbus_error);
+                fail_item!(modbus_error);
             },
         }
         
@@ -517,6 +2045,97 @@ pub async fn batch_request(interface: Interface, request_info: Vec<(String, (Str
 
 }
 
+// failed points are returned next to the slave they belong to, only a connection failure fails the whole interface
+pub async fn read_all(interface_name: String, interface: Interface) -> Result<Vec<(String, Result<ItemResult, ModbusError>)>, ModbusError> {
+
+    // the whole interface is read by poll_priority, so critical points are not held back by the rest
+    let slave_names = interface.slave_names();
+    let mut points = Vec::new();
+    for slave_name in &slave_names {
+        let (slave, _) = match interface.resolve_slave(slave_name) {
+            Some(resolved) => resolved,
+            None => continue,
+        };
+        for block_type in [BlockType::Co, BlockType::Di, BlockType::Hr, BlockType::Ir] {
+            points.extend(slave.registers().iter_block(block_type)
+                .map(|(value_name, modbus_data)| (slave_name.as_str(), value_name, modbus_data)));
+        }
+    }
+    points.sort_by_key(|(slave_name, value_name, modbus_data)| poll_order(slave_name, value_name, modbus_data));
+    let request_info: Vec<RequestItem> = points.into_iter().map(|(slave_name, value_name, _)| RequestItem {
+        slave: String::from(slave_name),
+        name: String::from(value_name),
+        op: GetOrSet::Get,
+        value: None,
+        options: ItemOptions::default(),
+    }).collect();
+
+    // batches stay within max_batch and run one after another on the same bus
+    let batch_size = interface.max_batch().unwrap_or(request_info.len()).max(1);
+    let mut results = Vec::new();
+    for batch in request_info.chunks(batch_size) {
+        let batch_results = batch_request_items(interface_name.clone(), interface.clone(), batch.to_vec(), OnError::Continue).await?;
+        results.extend(batch.iter().map(|item| item.slave.clone()).zip(batch_results));
+    }
+
+    Ok(results)
+
+}
+
+impl ModbusError {
+
+    pub fn with_context(self, context: impl ToString) -> Self {
+
+        match self {
+            ModbusError::ModbusError(info) => ModbusError::ModbusError(format!("{}: {}", context.to_string(), info)),
+            modbus_error => ModbusError::Context(context.to_string(), Box::new(modbus_error)),
+        }
+
+    }
+
+    // the slave answered, it just refused the request
+    pub fn is_exception(&self) -> bool {
+
+        match self {
+            ModbusError::Context(_, modbus_error) => modbus_error.is_exception(),
+            ModbusError::ModbusException(_) | ModbusError::WriteException(..) => true,
+            _ => false,
+        }
+
+    }
+
+    pub fn to_http_status_code(&self) -> u16 {
+
+        match self {
+            ModbusError::Context(_, modbus_error) => modbus_error.to_http_status_code(),
+            ModbusError::ModbusError(_) | ModbusError::TransportError(_) => 503,
+            ModbusError::Timeout(_) => 504,
+            ModbusError::ModbusException(_) | ModbusError::WriteException(..) => 502,
+            ModbusError::SlaveNotFound(_) | ModbusError::ValueNotDefined(_) => 404,
+            ModbusError::WriteInputValue(_) | ModbusError::WriteCalibratedValue(_) => 405,
+            ModbusError::InvailedValueInput(_) => 400,
+            ModbusError::ResponseSizeNotMatch { .. } => 502,
+            ModbusError::DataSizeNotMatch(_) | ModbusError::DataConvertError(_) => 500,
+        }
+
+    }
+
+}
+
+impl From<tokio_modbus::Error> for ModbusError {
+
+    fn from(err: tokio_modbus::Error) -> Self {
+
+        match err {
+            tokio_modbus::Error::Transport(err) if err.kind() == ErrorKind::TimedOut => ModbusError::Timeout(err.to_string()),
+            tokio_modbus::Error::Transport(err) => ModbusError::TransportError(err.to_string()),
+            err => ModbusError::ModbusError(err.to_string()),
+        }
+
+    }
+
+}
+
 impl fmt::Display for ModbusError {
 
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -525,12 +2144,27 @@ impl fmt::Display for ModbusError {
             ModbusError::ModbusError(info) => {
                 write!(f, "ModbusError: {}", info)
             },
+            ModbusError::TransportError(info) => {
+                write!(f, "TransportError: {}", info)
+            },
+            ModbusError::Timeout(info) => {
+                write!(f, "Timeout: {}", info)
+            },
             ModbusError::ModbusException(info) => {
                 write!(f, "ModbusException: {}", info)
             },
+            ModbusError::WriteException(info, words) => {
+                let words: Vec<String> = words.iter().map(|word| format!("0x{:04X}", word)).collect();
+                write!(f, "ModbusException: {}, sent words [{}]", info, words.join(", "))
+            },
             ModbusError::DataSizeNotMatch(info) => {
                 write!(f, "DataSizeNotMatch: {}", info)
             },
+            ModbusError::ResponseSizeNotMatch { function_code, address, expected, payload } => {
+                let payload: Vec<String> = payload.iter().map(|word| format!("0x{:04X}", word)).collect();
+                write!(f, "ResponseSizeNotMatch: function 0x{:02X} at {} expected {}, received {} [{}]",
+                    function_code, address, expected, payload.len(), payload.join(", "))
+            },
             ModbusError::DataConvertError(info) => {
                 write!(f, "DataConvertError: {}", info)
             },
@@ -543,11 +2177,142 @@ impl fmt::Display for ModbusError {
             ModbusError::WriteInputValue(info) => {
                 write!(f, "WriteInputValue: {}", info)
             },
+            ModbusError::WriteCalibratedValue(info) => {
+                write!(f, "WriteCalibratedValue: {}", info)
+            },
             ModbusError::InvailedValueInput(info) => {
                 write!(f, "InvailedValueInput: {}", info)
             },
+            ModbusError::Context(context, modbus_error) => {
+                write!(f, "{}: {}", context, modbus_error)
+            },
         }
 
     }
     
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const ENDIANS: [Endian; 2] = [Endian::Big, Endian::Little];
+
+    fn name(endian: Endian) -> &'static str {
+
+        match endian {
+            Endian::Big => "big",
+            Endian::Little => "little",
+        }
+
+    }
+
+    fn boundaries(value_type: ValueType) -> Vec<Value> {
+
+        match value_type {
+            ValueType::Bool => vec![json!(false), json!(true)],
+            ValueType::U16 => vec![json!(0), json!(1), json!(u16::MAX)],
+            ValueType::I16 => vec![json!(i16::MIN), json!(-1), json!(0), json!(1), json!(i16::MAX)],
+            ValueType::U32 => vec![json!(0), json!(1), json!(u32::MAX)],
+            ValueType::I32 => vec![json!(i32::MIN), json!(-1), json!(0), json!(1), json!(i32::MAX)],
+            // exactly representable, within the i32 range the encoder accepts
+            ValueType::F32 => vec![json!(-2147483520.0), json!(-1.5), json!(0.0), json!(f32::MIN_POSITIVE as f64), json!(1.5), json!(16777216.0)],
+            ValueType::BcdU16 => vec![json!(0), json!(1), json!(9999)],
+            ValueType::BcdU32 => vec![json!(0), json!(1), json!(10000), json!(99999999)],
+            ValueType::Custom(_) => vec![],
+        }
+
+    }
+
+    const VALUE_TYPES: [ValueType; 8] = [
+        ValueType::Bool, ValueType::U16, ValueType::I16, ValueType::U32,
+        ValueType::I32, ValueType::F32, ValueType::BcdU16, ValueType::BcdU32,
+    ];
+
+    #[test]
+    fn values_round_trip_through_every_byte_and_word_order() {
+
+        for value_type in VALUE_TYPES {
+            for value in boundaries(value_type) {
+                for (byte_order, word_order) in ENDIANS.iter().flat_map(|byte_order| ENDIANS.map(|word_order| (*byte_order, word_order))) {
+                    let size = value_type.size();
+                    let encoded = value_to_bytes(&Some(value.clone()), value_type, size as u16)
+                        .unwrap_or_else(|| panic!("{} {} does not encode", value_type, value));
+                    let mut wire = slot_words(&encoded, value_type).to_vec();
+                    reorder(&mut wire, byte_order, word_order);
+                    reorder(&mut wire, byte_order, word_order);
+                    let decoded = response_to_value(&wire, value_type)
+                        .unwrap_or_else(|e| panic!("{} {} does not decode: {}", value_type, value, e));
+                    assert_eq!(decoded, value, "{} in {} byte and {} word order", value_type, name(byte_order), name(word_order));
+                }
+            }
+        }
+
+    }
+
+    #[test]
+    fn reconnect_backoff_grows_up_to_its_max() {
+
+        let policy = ReconnectPolicy::ExponentialBackoff { initial: Duration::from_millis(125), max: Duration::from_millis(600), factor: 2.0 };
+        let delays: Vec<Option<Duration>> = (1..=4).map(|attempt| policy.delay(attempt)).collect();
+        assert_eq!(delays, [125, 250, 500, 600].map(|ms| Some(Duration::from_millis(ms))));
+        assert_eq!(policy.delay(u32::MAX), Some(Duration::from_millis(600)));
+        assert_eq!(ReconnectPolicy::FixedDelay(Duration::from_millis(5)).delay(9), Some(Duration::from_millis(5)));
+        assert_eq!(ReconnectPolicy::Immediate.delay(1), Some(Duration::ZERO));
+        assert_eq!(ReconnectPolicy::NoReconnect.delay(1), None);
+
+    }
+
+    #[test]
+    fn signed_registers_decode_negative() {
+
+        assert_eq!(response_to_value(&[0x8000], ValueType::I16).ok(), Some(json!(i16::MIN)));
+        assert_eq!(response_to_value(&[0xFFFF], ValueType::I16).ok(), Some(json!(-1)));
+        assert_eq!(response_to_value(&[0x8000, 0x0000], ValueType::I32).ok(), Some(json!(i32::MIN)));
+        assert_eq!(response_to_value(&[0xFFFF, 0xFFFF], ValueType::I32).ok(), Some(json!(-1)));
+        assert_eq!(response_to_value(&[0xFFFF, 0xFFFF], ValueType::U32).ok(), Some(json!(u32::MAX)));
+
+    }
+
+    #[test]
+    fn little_endian_wire_order() {
+
+        let mut words = vec![0x1234, 0x5678];
+        reorder(&mut words, Endian::Little, Endian::Big);
+        assert_eq!(words, vec![0x3412, 0x7856]);
+
+        let mut words = vec![0x1234, 0x5678];
+        reorder(&mut words, Endian::Big, Endian::Little);
+        assert_eq!(words, vec![0x5678, 0x1234]);
+
+        let mut words = vec![0x1234, 0x5678];
+        reorder(&mut words, Endian::Little, Endian::Little);
+        assert_eq!(words, vec![0x7856, 0x3412]);
+
+    }
+
+    #[test]
+    fn integers_encode_as_f32() {
+
+        assert_eq!(value_to_bytes(&Some(json!(5)), ValueType::F32, 2), Some(vec![0x40A0, 0x0000]));
+        assert_eq!(value_to_bytes(&Some(json!(-5)), ValueType::F32, 2), Some(vec![0xC0A0, 0x0000]));
+        assert_eq!(value_to_bytes(&Some(json!(5)), ValueType::F32, 2), value_to_bytes(&Some(json!(5.0)), ValueType::F32, 2));
+
+    }
+
+    #[test]
+    fn values_beyond_the_boundaries_do_not_encode() {
+
+        for (value_type, value) in [
+            (ValueType::U16, json!(-1)), (ValueType::U16, json!(u16::MAX as u32 + 1)),
+            (ValueType::I16, json!(i16::MIN as i32 - 1)), (ValueType::I16, json!(i16::MAX as i32 + 1)),
+            (ValueType::U32, json!(-1)), (ValueType::U32, json!(u32::MAX as u64 + 1)),
+            (ValueType::I32, json!(i32::MIN as i64 - 1)), (ValueType::I32, json!(i32::MAX as i64 + 1)),
+            (ValueType::BcdU16, json!(10000)), (ValueType::BcdU32, json!(100000000)),
+        ] {
+            assert!(value_to_bytes(&Some(value.clone()), value_type, value_type.size() as u16).is_none(), "{} {}", value_type, value);
+        }
+
+    }
+
+}
@@ -0,0 +1,549 @@
+use core::fmt;
+use std::collections::BTreeMap;
+use serde::{de::{self, DeserializeOwned}, Deserialize, Deserializer, Serialize};
+use serde_json::{self, json, Map, Value};
+
+use crate::interface::BlockType;
+use crate::modbus::OnError;
+
+
+// every request is a single {"METHOD": body} object
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Method {
+    Test,
+    Get,
+    Set,
+    DumpConfig,
+    Aggregate,
+    Snapshot,
+    Identify,
+    Hexdump,
+    List,
+    Stats,
+    Reload,
+}
+
+impl Method {
+
+    pub fn parse(name: &str) -> Option<Self> {
+
+        match name.to_uppercase().as_str() {
+            "TEST" => Some(Method::Test),
+            "GET" => Some(Method::Get),
+            "SET" => Some(Method::Set),
+            "DUMP_CONFIG" => Some(Method::DumpConfig),
+            "AGGREGATE" => Some(Method::Aggregate),
+            "SNAPSHOT" => Some(Method::Snapshot),
+            "IDENTIFY" => Some(Method::Identify),
+            "HEXDUMP" => Some(Method::Hexdump),
+            "LIST" => Some(Method::List),
+            "STATS" => Some(Method::Stats),
+            "RELOAD" => Some(Method::Reload),
+            _ => None,
+        }
+
+    }
+
+    pub fn as_str(&self) -> &'static str {
+
+        match self {
+            Method::Test => "TEST",
+            Method::Get => "GET",
+            Method::Set => "SET",
+            Method::DumpConfig => "DUMP_CONFIG",
+            Method::Aggregate => "AGGREGATE",
+            Method::Snapshot => "SNAPSHOT",
+            Method::Identify => "IDENTIFY",
+            Method::Hexdump => "HEXDUMP",
+            Method::List => "LIST",
+            Method::Stats => "STATS",
+            Method::Reload => "RELOAD",
+        }
+
+    }
+
+    pub fn request(&self, body: Value) -> Value {
+
+        json!({ self.as_str(): body })
+
+    }
+
+}
+
+// externally tagged, so each variant reads and writes as {"METHOD": body}
+#[derive(Serialize, Deserialize)]
+pub enum Request {
+    #[serde(rename = "TEST")]
+    Test(TestBody),
+    #[serde(rename = "GET")]
+    Get(GetBody),
+    #[serde(rename = "SET")]
+    Set(SetBody),
+    #[serde(rename = "DUMP_CONFIG")]
+    DumpConfig(DumpConfigBody),
+    #[serde(rename = "AGGREGATE")]
+    Aggregate(AggregateBody),
+    #[serde(rename = "SNAPSHOT")]
+    Snapshot(()),
+    #[serde(rename = "IDENTIFY")]
+    Identify(IdentifyBody),
+    #[serde(rename = "HEXDUMP")]
+    Hexdump(HexdumpBody),
+    #[serde(rename = "LIST")]
+    List(()),
+    #[serde(rename = "STATS")]
+    Stats(()),
+    #[serde(rename = "RELOAD")]
+    Reload(()), // load the device files again, see diff_configs
+}
+
+pub enum RequestError {
+    Malformed(String), // not JSON, or not a single {"METHOD": body} object
+    UnknownMethod { name: String, body: Value },
+    InvalidBody { method: Method, body: Value, reason: String },
+}
+
+impl Request {
+
+    pub fn parse(message: &str) -> Result<(Request, Value), RequestError> {
+
+        let request: Value = serde_json::from_str(message)
+            .map_err(|e| RequestError::Malformed(e.to_string()))?;
+        let (name, body) = match request {
+            Value::Object(object) if object.len() == 1 => object.into_iter().next()
+                .ok_or_else(|| RequestError::Malformed(String::from("empty request")))?,
+            _ => return Err(RequestError::Malformed(String::from("expected a single {\"METHOD\": body} object"))),
+        };
+        // method names are matched case-insensitively, the bodies are not
+        let method = match Method::parse(&name) {
+            Some(method) => method,
+            None => return Err(RequestError::UnknownMethod { name, body }),
+        };
+        // the error already names the method, the reason starts at the field within the body
+        let request = serde_path_to_error::deserialize(method.request(body.clone()))
+            .map_err(|e| {
+                let path = e.path().to_string();
+                let field = path.strip_prefix(method.as_str()).unwrap_or(&path).trim_start_matches('.');
+                RequestError::InvalidBody {
+                    method,
+                    body: body.clone(),
+                    reason: match field {
+                        "" => e.inner().to_string(),
+                        field => format!("{}: {}", field, e.inner()),
+                    },
+                }
+            })?;
+
+        Ok((request, body))
+
+    }
+
+    pub fn method(&self) -> Method {
+
+        match self {
+            Request::Test(_) => Method::Test,
+            Request::Get(_) => Method::Get,
+            Request::Set(_) => Method::Set,
+            Request::DumpConfig(_) => Method::DumpConfig,
+            Request::Aggregate(_) => Method::Aggregate,
+            Request::Snapshot(_) => Method::Snapshot,
+            Request::Identify(_) => Method::Identify,
+            Request::Hexdump(_) => Method::Hexdump,
+            Request::List(_) => Method::List,
+            Request::Stats(_) => Method::Stats,
+            Request::Reload(_) => Method::Reload,
+        }
+
+    }
+
+}
+
+// bodies that take several shapes pick one by the JSON type, so a bad field is reported
+// as such instead of as "did not match any variant"
+fn from_value<T: DeserializeOwned, E: de::Error>(value: Value) -> Result<T, E> {
+
+    serde_path_to_error::deserialize(value).map_err(|e| match e.path().to_string().as_str() {
+        "." => E::custom(e.into_inner()),
+        path => E::custom(format!("{}: {}", path, e.inner())),
+    })
+
+}
+
+// TEST body: either the key to echo or the options object
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum TestBody {
+    Key(String),
+    Options(TestOptions),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TestOptions {
+    pub key: String,
+    #[serde(default)]
+    pub probe: bool, // time a few reads to every slave
+    #[serde(default)]
+    pub count: Option<usize>, // reads per slave when probing
+}
+
+impl<'de> Deserialize<'de> for TestBody {
+
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+
+        match Value::deserialize(deserializer)? {
+            value @ Value::String(_) => from_value(value).map(TestBody::Key),
+            value => from_value(value).map(TestBody::Options),
+        }
+
+    }
+
+}
+
+// GET body: either ["path", ...] or the options object
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum GetBody {
+    Paths(Vec<String>),
+    Options(GetOptions),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetOptions {
+    pub paths: Vec<String>,
+    #[serde(default)]
+    pub etag: Option<String>, // etag of a previous response, answered with "unchanged" when still current
+    #[serde(default)]
+    pub with_units: bool, // points with a unit are answered as {"value": value, "unit": unit}
+    #[serde(default)]
+    pub describe: bool, // every point is answered as {"value", "block", "address", "type"}, with "unit" when it has one
+}
+
+impl<'de> Deserialize<'de> for GetBody {
+
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+
+        match Value::deserialize(deserializer)? {
+            value @ Value::Array(_) => from_value(value).map(GetBody::Paths),
+            value => from_value(value).map(GetBody::Options),
+        }
+
+    }
+
+}
+
+// SET body: either {"path": value, ...} or, when "values" is present, the options object
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum SetBody {
+    Values(Map<String, Value>),
+    Options(SetOptions),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SetOptions {
+    pub values: Map<String, Value>,
+    #[serde(default)]
+    pub on_error: OnError,
+    #[serde(default)]
+    pub coerce_strings: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>, // a repeated key is answered from the first response without writing again
+}
+
+impl<'de> Deserialize<'de> for SetBody {
+
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+
+        match Value::deserialize(deserializer)? {
+            value if value.get("values").is_some() => from_value(value).map(SetBody::Options),
+            value => from_value(value).map(SetBody::Values),
+        }
+
+    }
+
+}
+
+// DUMP_CONFIG body: either "device" or the options object
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum DumpConfigBody {
+    Device(String),
+    Options(DumpConfigOptions),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DumpConfigOptions {
+    pub device: String,
+    #[serde(default)]
+    pub layout: bool,
+    #[serde(default)]
+    pub format: DumpFormat,
+}
+
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DumpFormat {
+    #[default]
+    Yaml,
+    Table,
+    Dotenv,
+}
+
+impl<'de> Deserialize<'de> for DumpConfigBody {
+
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+
+        match Value::deserialize(deserializer)? {
+            value @ Value::Object(_) => from_value(value).map(DumpConfigBody::Options),
+            value => from_value(value).map(DumpConfigBody::Device),
+        }
+
+    }
+
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AggregateBody {
+    pub interface: String,
+    pub point: String,
+    pub op: AggregateOp,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AggregateOp {
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct IdentifyBody {
+    pub interface: String,
+    pub slave: String,
+}
+
+// raw read of an address range, bypassing the configured points
+#[derive(Serialize, Deserialize)]
+pub struct HexdumpBody {
+    pub interface: String,
+    pub slave: String,
+    pub block: BlockType,
+    pub start: u16,
+    pub count: u16, // registers for hr/ir, bits for co/di
+}
+
+// error responses that only name the error and echo what was wrong with it
+#[derive(Serialize, Deserialize)]
+pub struct ErrorBody {
+    #[serde(rename = "ERROR")]
+    pub error: String,
+    #[serde(rename = "DETAILS")]
+    pub details: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>, // which field of the request was rejected, and why
+}
+
+impl ErrorBody {
+
+    pub fn new(error: &str, details: String) -> Self {
+
+        ErrorBody {
+            error: String::from(error),
+            details,
+            reason: None,
+        }
+
+    }
+
+}
+
+impl From<RequestError> for ErrorBody {
+
+    fn from(request_error: RequestError) -> Self {
+
+        match request_error {
+            RequestError::Malformed(reason) => ErrorBody {
+                reason: Some(reason),
+                ..ErrorBody::new("INVAILD REQUEST", String::new())
+            },
+            RequestError::UnknownMethod { name, body } => ErrorBody {
+                reason: Some(format!("unknown method '{}'", name)),
+                ..ErrorBody::new("INVAILED METHOD", body.to_string())
+            },
+            RequestError::InvalidBody { method, body, reason } => ErrorBody {
+                reason: Some(reason),
+                ..ErrorBody::new(&format!("INVAILED {}", method.as_str()), body.to_string())
+            },
+        }
+
+    }
+
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TestResponse {
+    #[serde(rename = "TEST")]
+    pub key: String,
+    pub connections: Map<String, Value>, // device -> {"connect": mode, "connected": state}
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub probe: Option<Map<String, Value>>, // device -> slave -> round trip times
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub errors: Map<String, Value>, // devices that could not be probed
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetResponse {
+    #[serde(rename = "GET")]
+    pub values: Value, // null when the etag still matches
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub unchanged: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SetResponse {
+    #[serde(rename = "SET")]
+    pub written: Map<String, Value>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub coerced: Vec<Value>,
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub failed: Map<String, Value>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped: Vec<Value>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub simulated: bool, // some device of the request has writes: simulate, its values never reached the bus
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DumpConfigResponse {
+    #[serde(rename = "DUMP_CONFIG")]
+    pub config: Value, // the config itself, or the table or dotenv text
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AggregateResponse {
+    #[serde(rename = "AGGREGATE")]
+    pub result: AggregateResult,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AggregateResult {
+    pub op: AggregateOp,
+    pub value: Option<f64>, // null when no slave answered
+    pub count: usize,
+    pub skipped: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SnapshotResponse {
+    #[serde(rename = "SNAPSHOT")]
+    pub values: Map<String, Value>,
+    pub status: Map<String, Value>,
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub errors: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct IdentifyResponse {
+    #[serde(rename = "IDENTIFY")]
+    pub objects: Value,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct HexdumpResponse {
+    #[serde(rename = "HEXDUMP")]
+    pub dump: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ListResponse {
+    #[serde(rename = "LIST")]
+    pub devices: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct StatsResponse {
+    #[serde(rename = "STATS")]
+    pub stats: Stats,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReloadResponse {
+    #[serde(rename = "RELOAD")]
+    pub devices: Value, // device names by "added", "removed", "changed" and "unchanged"
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub changes: Map<String, Value>, // what changed inside each changed device
+}
+
+// answer to STATS, the same numbers the periodic stats line logs
+#[derive(Serialize, Deserialize)]
+pub struct Stats {
+    pub interval_s: u64,
+    pub rps: f64,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    pub error_rate: f64, // percent of requests answered with an error
+    pub sessions: usize,
+    pub recoveries: usize, // watchdog reopens over all devices
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub device_recoveries: BTreeMap<String, usize>, // watchdog reopens per device, only devices that needed one
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub rtt_ms: BTreeMap<String, f64>, // mean probe round trip per /device/slave
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub writes: BTreeMap<String, String>, // write mode per device, only filled in for STATS requests
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum Response {
+    Test(TestResponse),
+    Get(GetResponse),
+    Set(SetResponse),
+    DumpConfig(DumpConfigResponse),
+    Aggregate(AggregateResponse),
+    Snapshot(SnapshotResponse),
+    Identify(IdentifyResponse),
+    Hexdump(HexdumpResponse),
+    List(ListResponse),
+    Stats(StatsResponse),
+    Reload(ReloadResponse),
+    Error(ErrorBody),
+}
+
+fn is_false(value: &bool) -> bool {
+
+    !*value
+
+}
+
+// error responses carry the error name under "ERROR"
+pub fn response_error(response: &Value) -> Option<&str> {
+
+    response.get("ERROR")?.as_str()
+
+}
+
+impl fmt::Display for RequestError {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        match self {
+            RequestError::Malformed(info) => {
+                write!(f, "Malformed: {}", info)
+            },
+            RequestError::UnknownMethod { name, .. } => {
+                write!(f, "UnknownMethod: '{}'", name)
+            },
+            RequestError::InvalidBody { method, reason, .. } => {
+                write!(f, "InvalidBody: {}: {}", method.as_str(), reason)
+            },
+        }
+
+    }
+
+}
@@ -1,16 +1,48 @@
 use log::*;
-use std::collections::HashMap;
-use tokio::task;
-use zmq::{REP, Context, Socket, Message};
+use std::{cell::Cell, collections::{BTreeMap, HashMap, VecDeque}, fs, hash::{DefaultHasher, Hash, Hasher}, os::unix::fs::PermissionsExt, path::Path, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+use tokio::{signal::unix::{signal, SignalKind}, task, time};
+use zmq::{PUSH, REP, Context, Socket, Message};
 use serde_json::{self, json, Value, Map};
 use futures::future;
+use tokio_modbus::ExceptionCode;
 
-use crate::interface::Interface;
-use crate::modbus;
+use crate::display;
+use crate::interface::{closest_names, diff_configs, BlockType, ConfigDiff, ConnectMode, DeviceSource, Interface, ModbusData, ValueType, WriteMode};
+use crate::modbus::{self, GetOrSet, ItemOptions, ModbusError, OnError, RequestInfo, RequestItem};
+use crate::protocol::{AggregateBody, AggregateOp, AggregateResponse, AggregateResult, DumpConfigBody, DumpConfigOptions, DumpConfigResponse, DumpFormat, ErrorBody,
+    GetBody, GetOptions, GetResponse, HexdumpBody, HexdumpResponse, IdentifyBody, IdentifyResponse, ListResponse, ReloadResponse, Request, Response, SetBody, SetOptions, SetResponse,
+    SnapshotResponse, Stats, StatsResponse, TestBody, TestOptions, TestResponse};
+
+pub type BatchPlan<'a> = Vec<(&'a String, &'a RequestInfo)>;
+
+const STATS_INTERVAL_S: u64 = 60; // window answered by STATS when the periodic summary is off
+const PROBE_COUNT: usize = 5; // reads per slave for TEST with probe
+const PROBE_COUNT_MAX: usize = 100;
+const REBIND_ATTEMPTS: u32 = 5;
+const REBIND_BACKOFF: Duration = Duration::from_millis(200);
+
+// keys are kept sorted by serde_json, so equal values always serialize, and hash, the same
+fn response_etag(values: &Map<String, Value>) -> String {
+
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(values).unwrap_or_default().hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+
+}
 
 
 pub struct TaskPlan {
-    todo_list: HashMap<String, Vec<(String, (String, Option<Value>))>>,
+    todo_list: HashMap<String, RequestInfo>,
+    dedup: bool,
+    max_items: usize, // 0 means unlimited
+    max_interface_items: usize,
+    client: Option<String>, // copied into every item for the audit log
+}
+
+impl Default for TaskPlan {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TaskPlan {
@@ -19,8 +51,67 @@ impl TaskPlan {
         
         TaskPlan {
             todo_list: HashMap::new(),
+            dedup: false,
+            max_items: 0,
+            max_interface_items: 0,
+            client: None,
+        }
+
+    }
+
+    pub fn with_dedup(dedup: bool) -> Self {
+
+        TaskPlan {
+            dedup,
+            ..TaskPlan::new()
+        }
+
+    }
+
+    pub fn with_limits(mut self, max_items: usize, max_interface_items: usize) -> Self {
+
+        self.max_items = max_items;
+        self.max_interface_items = max_interface_items;
+
+        self
+
+    }
+
+    pub fn with_client(mut self, client: Option<String>) -> Self {
+
+        self.client = client;
+
+        self
+
+    }
+
+    pub fn limit_error<'a>(&self, paths: impl Iterator<Item = &'a str>) -> Option<Value> {
+
+        // counts only, so oversized requests are rejected before anything is cloned
+        let mut count = 0;
+        let mut interface_counts: HashMap<&str, usize> = HashMap::new();
+        for path in paths {
+            count += 1;
+            if let Some(interface_name) = path.split('/').nth(1) {
+                *interface_counts.entry(interface_name).or_insert(0) += 1;
+            }
+        }
+
+        if self.max_items > 0 && count > self.max_items {
+            return Some(json!({"ERROR": "QUEUE_FULL", "limit": self.max_items, "count": count}));
+        }
+        if self.max_interface_items > 0 {
+            let mut interface_counts: Vec<(&str, usize)> = interface_counts.into_iter().collect();
+            interface_counts.sort();
+            for (interface_name, count) in interface_counts {
+                if count > self.max_interface_items {
+                    return Some(json!({"ERROR": "QUEUE_FULL", "device": interface_name, "limit": self.max_interface_items, "count": count}));
+                }
+            }
         }
 
+        None
+
     }
 
     pub fn push(&mut self, path: &str, value: Option<Value>) {
@@ -34,20 +125,84 @@ impl TaskPlan {
                 return;
             }
 
+            let item = RequestItem {
+                slave: path_vec[2].to_string(),
+                name: path_vec[3].to_string(),
+                op: if value.is_some() { GetOrSet::Set } else { GetOrSet::Get },
+                value,
+                options: ItemOptions { client: self.client.clone() },
+            };
+
             if self.todo_list.contains_key(path_vec[1]) {
-                match self.todo_list.get_mut(path_vec[1]) {
+                let vec = match self.todo_list.get_mut(path_vec[1]) {
                     Some(vec) => vec, None => { return; }
-                }.push((path_vec[2].to_string(), (path_vec[3].to_string(), value)));
+                };
+                if self.dedup && item.op == GetOrSet::Get && vec.iter().any(|queued| {
+                    queued.op == GetOrSet::Get && queued.slave == item.slave && queued.name == item.name
+                }) {
+                    debug!("Duplicated path skipped: {}", path);
+                    return;
+                }
+                vec.push(item);
             } else {
-                let new_vec = vec![{(path_vec[2].to_string(), (path_vec[3].to_string(), value))}];
-                self.todo_list.insert(path_vec[1].to_string(), new_vec);
+                self.todo_list.insert(path_vec[1].to_string(), vec![item]);
             }
     
     }
 
-    pub fn plan(&self) -> Vec<(&String, &Vec<(String, (String, Option<Value>))>)> {
+    pub fn device_names(&self) -> impl Iterator<Item = &str> {
+
+        self.todo_list.keys().map(|interface_name| interface_name.as_str())
+
+    }
+
+    // legacy clients send every value as a string, turn those into the json type of the datapoint
+    pub fn coerce_strings(&mut self, device_list: &HashMap<String, Interface>) -> Vec<(String, String)> {
+
+        let mut failures = Vec::new();
+
+        for (interface_name, request_info) in self.todo_list.iter_mut() {
+            let interface = match device_list.get(interface_name) {
+                Some(interface) => interface,
+                None => continue, // reported as DEVICE_NOT_FOUND
+            };
+            request_info.retain_mut(|item| {
+                let modbus_data = match interface.resolve_slave(&item.slave).and_then(|(slave, _)| slave.find(&item.name)) {
+                    Some(modbus_data) => modbus_data,
+                    None => return true, // reported as UNKNOWN_DATAPOINT
+                };
+                let coerced = match &item.value {
+                    Some(Value::String(text)) => coerce_string(text, &modbus_data),
+                    Some(Value::Array(values)) => values.iter()
+                        .map(|value| match value {
+                            Value::String(text) => coerce_string(text, &modbus_data),
+                            _ => Ok(value.clone()),
+                        })
+                        .collect::<Result<Vec<Value>, String>>()
+                        .map(Value::Array),
+                    _ => return true,
+                };
+                match coerced {
+                    Ok(value) => {
+                        item.value = Some(value);
+                        true
+                    },
+                    Err(error) => {
+                        failures.push((format!("/{}/{}/{}", interface_name, item.slave, item.name), error));
+                        false
+                    },
+                }
+            });
+        }
+
+        failures.sort();
+        failures
+
+    }
+
+    pub fn plan(&self) -> BatchPlan<'_> {
 
-        let mut task_plan:Vec<(&String, &Vec<(String, (String, Option<Value>))>)> = Vec::new();
+        let mut task_plan:BatchPlan<'_> = Vec::new();
 
         for (interface_name, request_info) in &self.todo_list {
             task_plan.push((interface_name, request_info));
@@ -60,20 +215,497 @@ impl TaskPlan {
 }
 
 
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum KeyCase {
+    #[default]
+    Original, // GET, ERROR, DUMP_CONFIG
+    Snake, // get, error, dump_config
+    Camel, // get, error, dumpConfig
+}
+
+impl KeyCase {
+
+    pub fn parse(name: &str) -> Option<Self> {
+
+        match name.to_lowercase().as_str() {
+            "original" => Some(KeyCase::Original),
+            "snake" => Some(KeyCase::Snake),
+            "camel" => Some(KeyCase::Camel),
+            _ => None,
+        }
+
+    }
+
+    fn rename(self, key: &str) -> Option<String> {
+
+        // only the uppercase metadata keys are renamed, point paths and the like stay verbatim
+        if !key.chars().all(|c| c.is_ascii_uppercase() || c == '_') {
+            return None;
+        }
+        let snake = key.to_lowercase();
+        match self {
+            KeyCase::Original => None,
+            KeyCase::Snake => Some(snake),
+            KeyCase::Camel => Some(snake.split('_').enumerate().map(|(index, word)| match index {
+                0 => word.to_string(),
+                _ => word[..1].to_uppercase() + &word[1..],
+            }).collect()),
+        }
+
+    }
+
+    fn apply(self, response: Value) -> Value {
+
+        match response {
+            Value::Object(object) if self != KeyCase::Original => Value::Object(object.into_iter()
+                .map(|(key, value)| (self.rename(&key).unwrap_or(key), value))
+                .collect()),
+            response => response,
+        }
+
+    }
+
+}
+
+// what the REP socket does after a response could not be sent, it can not receive again until it has
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum SendFailure {
+    #[default]
+    Reset, // close and rebind the socket, the peer that missed its response has to resend
+    Exit, // stop serving and leave the restart to a supervisor
+}
+
+impl SendFailure {
+
+    pub fn parse(name: &str) -> Option<Self> {
+
+        match name.to_lowercase().as_str() {
+            "reset" => Some(SendFailure::Reset),
+            "exit" => Some(SendFailure::Exit),
+            _ => None,
+        }
+
+    }
+
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum LogFormat {
+    #[default]
+    Text, // Stats: rps=.. mean_ms=..
+    Json, // one {"event": "stats", ..} object per line, for log shippers
+}
+
+impl LogFormat {
+
+    pub fn parse(name: &str) -> Option<Self> {
+
+        match name.to_lowercase().as_str() {
+            "text" => Some(LogFormat::Text),
+            "json" => Some(LogFormat::Json),
+            _ => None,
+        }
+
+    }
+
+}
+
+#[derive(Clone, Default)]
+pub struct ServerOptions {
+    pub dedup: bool, // read identical GET paths only once per batch
+    pub serve_stale: bool, // answer failed GETs from the last-value store
+    pub stale_ttl_s: u64, // stored values older than this are dropped, 0 keeps them
+    pub stats_interval_s: u64, // 0 disables the periodic stats summary
+    pub log_format: LogFormat, // how the periodic stats summary is logged
+    pub max_request_items: usize, // paths per GET/SET, 0 means unlimited
+    pub max_interface_items: usize, // paths per device within one GET/SET
+    pub key_case: KeyCase, // casing of the top level response keys
+    pub max_message_size: usize, // bytes per request, 0 means unlimited
+    pub pretty: bool, // indent responses for humans reading them
+    pub monitor_address: Option<String>, // PUSH endpoint for per-device GET/SET telemetry
+    pub on_send_failure: SendFailure,
+    pub idempotency_keys: usize, // SET responses kept for replay, 0 disables idempotency_key
+    pub read_only: bool, // every interface rejects writes, whatever its own writes setting
+    pub idempotency_ttl_s: u64,
+    pub zmq_poll_interval_ms: u64, // pause between non-blocking receives while no request is pending
+    pub device_sources: Vec<DeviceSource>, // device files loaded again by RELOAD and SIGHUP
+}
+
+struct RequestStats {
+    interval: Duration, // older entries are dropped as new ones come in
+    window: VecDeque<(Instant, Duration, bool)>, // (finished at, latency, failed)
+    probes: VecDeque<(Instant, String, Duration)>, // (answered at, /device/slave, round trip)
+}
+
+impl RequestStats {
+
+    fn new(interval: Duration) -> Self {
+
+        RequestStats {
+            interval,
+            window: VecDeque::new(),
+            probes: VecDeque::new(),
+        }
+
+    }
+
+    fn record(&mut self, latency: Duration, failed: bool) {
+
+        self.window.push_back((Instant::now(), latency, failed));
+        self.prune();
+
+    }
+
+    fn record_probe(&mut self, path: String, round_trip: Duration) {
+
+        self.probes.push_back((Instant::now(), path, round_trip));
+        self.prune();
+
+    }
+
+    fn prune(&mut self) {
+
+        while let Some((finished_at, _, _)) = self.window.front() {
+            if finished_at.elapsed() <= self.interval {
+                break;
+            }
+            self.window.pop_front();
+        }
+        while let Some((answered_at, _, _)) = self.probes.front() {
+            if answered_at.elapsed() <= self.interval {
+                break;
+            }
+            self.probes.pop_front();
+        }
+
+    }
+
+    fn summary(&mut self) -> Stats {
+
+        self.prune();
+        let interval = self.interval;
+
+        let mut latencies: Vec<Duration> = self.window.iter().map(|(_, latency, _)| *latency).collect();
+        latencies.sort();
+        let count = latencies.len();
+        let errors = self.window.iter().filter(|(_, _, failed)| *failed).count();
+        let percentile = |p: usize| match count {
+            0 => 0.0,
+            _ => latencies[(count * p / 100).min(count - 1)].as_secs_f64() * 1000.0,
+        };
+        let mean = match count {
+            0 => 0.0,
+            _ => latencies.iter().sum::<Duration>().as_secs_f64() * 1000.0 / count as f64,
+        };
+        let recoveries = modbus::watchdog_recoveries();
+
+        Stats {
+            interval_s: interval.as_secs(),
+            rps: count as f64 / interval.as_secs_f64(),
+            mean_ms: mean,
+            p50_ms: percentile(50),
+            p95_ms: percentile(95),
+            p99_ms: percentile(99),
+            error_rate: match count { 0 => 0.0, _ => errors as f64 * 100.0 / count as f64 },
+            sessions: modbus::active_sessions(),
+            recoveries: recoveries.values().sum(),
+            device_recoveries: recoveries,
+            rtt_ms: self.probes.iter()
+                .fold(BTreeMap::new(), |mut totals: BTreeMap<String, (Duration, u32)>, (_, path, round_trip)| {
+                    let total = totals.entry(path.clone()).or_default();
+                    *total = (total.0 + *round_trip, total.1 + 1);
+                    totals
+                })
+                .into_iter()
+                .map(|(path, (total, count))| (path, total.as_secs_f64() * 1000.0 / count as f64))
+                .collect(),
+            writes: BTreeMap::new(),
+        }
+
+    }
+
+    fn report_line(&mut self, log_format: LogFormat) -> String {
+
+        let stats = self.summary();
+        match log_format {
+            LogFormat::Text => format!("Stats: rps={:.2} mean_ms={:.1} p50_ms={:.1} p95_ms={:.1} p99_ms={:.1} error_rate={:.1}% sessions={} recoveries={}",
+                stats.rps, stats.mean_ms, stats.p50_ms, stats.p95_ms, stats.p99_ms, stats.error_rate, stats.sessions, stats.recoveries,
+            ),
+            LogFormat::Json => {
+                let mut event = json!({"event": "stats"});
+                if let (Some(event), Ok(Value::Object(stats))) = (event.as_object_mut(), serde_json::to_value(stats)) {
+                    event.extend(stats);
+                }
+                event.to_string()
+            },
+        }
+
+    }
+
+    fn report(&mut self, log_format: LogFormat) {
+
+        info!("{}", self.report_line(log_format));
+
+    }
+
+}
+
+fn unit_range_error(interface_name: &str, interface: &Interface, slave_name: &str) -> Option<Value> {
+
+    let (base_name, unit) = slave_name.rsplit_once('_')?;
+    let (first, last) = interface.slaves.get(base_name)?.id_range()?;
+
+    Some(json!({
+        "ERROR": "UNIT_OUT_OF_RANGE",
+        "device": interface_name,
+        "slave": slave_name,
+        "unit": unit,
+        "range": format!("{}-{}", first, last),
+    }))
+
+}
+
+// every unknown device at once, with the closest configured names for each
+fn device_not_found_error<'a>(device_names: impl Iterator<Item = &'a str>, device_list: &HashMap<String, Interface>) -> Option<Value> {
+
+    let mut missing_devices: Vec<&str> = device_names
+        .filter(|interface_name| !device_list.contains_key(*interface_name))
+        .collect();
+    if missing_devices.is_empty() {
+        return None;
+    }
+    missing_devices.sort();
+    missing_devices.dedup();
+    let suggestions: Map<String, Value> = missing_devices.iter()
+        .map(|interface_name| (interface_name.to_string(), json!(closest_names(interface_name, device_list.keys().map(String::as_str)))))
+        .collect();
+
+    Some(json!({"ERROR": "DEVICE_NOT_FOUND", "devices": missing_devices, "suggestions": suggestions}))
+
+}
+
+fn unknown_path_error(plan: &BatchPlan<'_>, device_list: &HashMap<String, Interface>) -> Option<Value> {
+
+    // devices first, no device may be touched when one is unknown
+    if let Some(error) = device_not_found_error(plan.iter().map(|(interface_name, _)| interface_name.as_str()), device_list) {
+        return Some(error);
+    }
+
+    for (interface_name, request_info) in plan {
+        let interface = &device_list[*interface_name];
+        for RequestItem { slave: slave_name, name: value_name, .. } in request_info.iter() {
+            let slave = match interface.resolve_slave(slave_name) {
+                Some((slave, _)) => slave,
+                None => return match unit_range_error(interface_name, interface, slave_name) {
+                    Some(error) => Some(error),
+                    None => Some(json!({
+                        "ERROR": "UNKNOWN_SLAVE",
+                        "device": interface_name,
+                        "slave": slave_name,
+                        "suggestions": closest_names(slave_name, interface.slaves.keys().map(String::as_str)),
+                    })),
+                },
+            };
+            if slave.find(value_name).is_none() {
+                return Some(json!({
+                    "ERROR": "UNKNOWN_DATAPOINT",
+                    "device": interface_name,
+                    "slave": slave_name,
+                    "datapoint": value_name,
+                    "suggestions": slave.suggest(value_name),
+                }));
+            }
+        }
+    }
+
+    None
+
+}
+
+fn batch_size_error(plan: &BatchPlan<'_>, device_list: &HashMap<String, Interface>) -> Option<Value> {
+
+    for (interface_name, request_info) in plan {
+        let max_batch = match device_list.get(*interface_name).and_then(Interface::max_batch) {
+            Some(max_batch) => max_batch,
+            None => continue,
+        };
+        if request_info.len() > max_batch {
+            return Some(json!({
+                "ERROR": "BATCH_TOO_LARGE",
+                "device": interface_name,
+                "limit": max_batch,
+                "count": request_info.len(),
+            }));
+        }
+    }
+
+    None
+
+}
+
+// every di/ir path of a SET, reported together before anything is written
+fn read_only_error(plan: &BatchPlan<'_>, device_list: &HashMap<String, Interface>) -> Option<Value> {
+
+    let mut registers = Vec::new();
+    for (interface_name, request_info) in plan {
+        let interface = match device_list.get(*interface_name) {
+            Some(interface) => interface,
+            None => continue,
+        };
+        for RequestItem { slave: slave_name, name: value_name, .. } in request_info.iter() {
+            let block_type = interface.resolve_slave(slave_name)
+                .and_then(|(slave, _)| slave.find(value_name))
+                .map(|modbus_data| modbus_data.block_type());
+            if matches!(block_type, Some(BlockType::Di | BlockType::Ir)) {
+                registers.push(format!("/{}/{}/{}", interface_name, slave_name, value_name));
+            }
+        }
+    }
+    registers.sort();
+
+    match registers.is_empty() {
+        true => None,
+        false => Some(json!({"ERROR": "WRITE_TO_READ_ONLY_REGISTER", "registers": registers})),
+    }
+
+}
+
+fn write_mode(interface: &Interface, read_only: bool) -> WriteMode {
+
+    match read_only {
+        true => WriteMode::Reject,
+        false => interface.writes(),
+    }
+
+}
+
+// devices of a SET that do not take writes, nothing is written when there is any
+fn write_disabled_error(plan: &BatchPlan<'_>, device_list: &HashMap<String, Interface>, read_only: bool) -> Option<Value> {
+
+    let mut devices: Vec<&str> = plan.iter()
+        .filter(|(interface_name, _)| device_list.get(*interface_name)
+            .is_some_and(|interface| write_mode(interface, read_only) == WriteMode::Reject))
+        .map(|(interface_name, _)| interface_name.as_str())
+        .collect();
+    devices.sort();
+
+    match devices.is_empty() {
+        true => None,
+        false => Some(json!({"ERROR": "WRITE_DISABLED", "devices": devices})),
+    }
+
+}
+
+fn coerce_string(text: &str, modbus_data: &ModbusData) -> Result<Value, String> {
+
+    let text = text.trim();
+    let parse_error = |expected: &str| format!("ParseError: '{}' is not {}", text, expected);
+    let is_bool = matches!(modbus_data.block_type(), BlockType::Co | BlockType::Di)
+        || modbus_data.value_type() == ValueType::Bool;
+
+    if is_bool {
+        return match text.to_lowercase().as_str() {
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            _ => Err(parse_error("a bool")),
+        };
+    }
+    match modbus_data.value_type() {
+        ValueType::U16 | ValueType::U32 | ValueType::BcdU16 | ValueType::BcdU32 => text.parse::<u64>()
+            .map(Value::from)
+            .map_err(|_| parse_error("an unsigned integer")),
+        ValueType::I16 | ValueType::I32 => text.parse::<i64>()
+            .map(Value::from)
+            .map_err(|_| parse_error("an integer")),
+        ValueType::F32 => text.parse::<f64>().ok()
+            .filter(|num| num.is_finite())
+            .map(Value::from)
+            .ok_or_else(|| parse_error("a number")),
+        // the encoder decides what it accepts, JSON literals are passed on as they are
+        ValueType::Custom(_) => Ok(serde_json::from_str(text).unwrap_or_else(|_| Value::String(String::from(text)))),
+        ValueType::Bool => unreachable!(),
+    }
+
+}
+
+// what with_units and describe add next to the value of a point
+fn point_fields(modbus_data: &ModbusData, with_units: bool, describe: bool) -> Map<String, Value> {
+
+    let mut fields = Map::new();
+    if describe {
+        fields.insert(String::from("block"), Value::String(modbus_data.block_type().to_string().to_lowercase()));
+        fields.insert(String::from("address"), Value::from(modbus_data.address()));
+        fields.insert(String::from("type"), Value::String(modbus_data.value_type().to_string().to_lowercase()));
+    }
+    if let Some(unit) = modbus_data.unit().filter(|_| with_units || describe) {
+        fields.insert(String::from("unit"), Value::String(String::from(unit)));
+    }
+
+    fields
+
+}
+
+fn with_fields(value: Value, fields: Map<String, Value>) -> Value {
+
+    match fields.is_empty() {
+        true => value,
+        false => {
+            let mut object = Map::from_iter([(String::from("value"), value)]);
+            object.extend(fields);
+            Value::Object(object)
+        },
+    }
+
+}
+
+fn is_coerced(requested: &Value, written: &Value) -> bool {
+
+    match (requested, written) {
+        // flags may be written partially, only the named ones are echoed back as requested
+        (Value::Object(requested), Value::Object(written)) => requested.iter()
+            .any(|(name, state)| written.get(name) != Some(state)),
+        _ => match (requested.as_f64(), written.as_f64()) {
+            (Some(requested), Some(written)) => requested != written,
+            _ => requested != written,
+        },
+    }
+
+}
+
 pub struct Server {
+    context: Context,
+    address: String,
     socket: Socket,
     message: Message,
+    peer: Option<String>, // address of the client that sent the message, where the transport knows it
+    options: ServerOptions,
+    send_failed: Cell<bool>,
+    responses_dropped: Cell<u64>,
+    request_failed: Cell<bool>,
+    stats: Arc<Mutex<RequestStats>>,
+    last_values: HashMap<String, (Value, Instant)>,
+    replies: VecDeque<(String, String, Vec<String>, Instant, Value)>, // (idempotency key, etag of the values, devices written, answered at, response), least recent first
+    monitor: Option<Socket>,
+    reload_requested: Arc<AtomicBool>, // set by SIGHUP, the reload happens while no request is pending
 }
 
 macro_rules! send_response {
-    ($socket:expr, $message:expr) => {{
-        let __response = $message.to_string();
-        match $socket.send(__response.as_str(), 0) {
+    ($server:expr, $message:expr) => {{
+        // serialized straight into the buffer zmq takes ownership of, without an intermediate String
+        let __response = $server.options.key_case.apply($message);
+        let __response = match $server.options.pretty {
+            true => serde_json::to_vec_pretty(&__response),
+            false => serde_json::to_vec(&__response),
+        }.expect("Failed to serialize response");
+        let __size = __response.len();
+        match $server.socket.send(__response, 0) {
             Ok(_) => {
-                info!("Response sent: {}", __response.len());
+                info!("Response sent: {}", __size);
             }
             Err(e) => {
                 error!("Error when send response: {}", e);
+                $server.send_failed.set(true);
+                $server.responses_dropped.set($server.responses_dropped.get() + 1);
             }
         }
     }};
@@ -81,60 +713,343 @@ macro_rules! send_response {
 
 impl Server {
 
-    pub fn new(address: &str) -> Self {
+    pub fn new(address: &str, options: ServerOptions) -> Self {
 
         let context = Context::new();
-        let server = Server {
+        let monitor = options.monitor_address.as_ref().map(|monitor_address| {
+            let monitor = context.socket(PUSH)
+                .expect("Failed to create monitor socket");
+            monitor.set_linger(0)
+                .expect("Failed to set monitor socket linger");
+            monitor.bind(monitor_address)
+                .unwrap_or_else(|_| panic!("Failed to bind monitor socket to '{}'", monitor_address));
+            info!("Monitor telemetry pushed to '{}'", monitor_address);
+            monitor
+        });
+        let stats_interval = Duration::from_secs(match options.stats_interval_s {
+            0 => STATS_INTERVAL_S,
+            stats_interval_s => stats_interval_s,
+        });
+        let mut server = Server {
             socket: context.socket(REP)
                 .expect("Failed to create socket"),
+            context,
+            address: String::from(address),
             message: Message::new(),
+            peer: None,
+            options,
+            send_failed: Cell::new(false),
+            responses_dropped: Cell::new(0),
+            request_failed: Cell::new(false),
+            stats: Arc::new(Mutex::new(RequestStats::new(stats_interval))),
+            last_values: HashMap::new(),
+            replies: VecDeque::new(),
+            monitor,
+            reload_requested: Arc::new(AtomicBool::new(false)),
         };
 
+        let transport = address.split("://").next().unwrap_or(address);
+        if let Some(ipc_path) = address.strip_prefix("ipc://") {
+            if let Some(parent) = Path::new(ipc_path).parent() {
+                fs::create_dir_all(parent)
+                    .unwrap_or_else(|_| panic!("Failed to create directory '{}'", parent.display()));
+            }
+        }
+
         server.socket.bind(address)
-            .expect(format!("Failed to bind socket to '{}'", address).as_str());
+            .unwrap_or_else(|_| panic!("Failed to bind socket to '{}'", address));
+        server.address = server.endpoint();
+
+        if let Some(ipc_path) = server.ipc_path() {
+            if let Err(e) = fs::set_permissions(ipc_path, fs::Permissions::from_mode(0o600)) {
+                warn!("Failed to set permissions of '{}': {}", ipc_path, e);
+            }
+        }
+        info!("Listening on '{}' ({} transport)", server.address, transport);
         
         server
 
     }
 
+    fn ipc_path(&self) -> Option<&str> {
+
+        self.address.strip_prefix("ipc://")
+
+    }
+
+    pub fn responses_dropped(&self) -> u64 {
+
+        self.responses_dropped.get()
+
+    }
+
+    async fn reset_socket(&mut self) {
+
+        warn!("Resetting socket after failed response, {} dropped so far", self.responses_dropped.get());
+
+        if let Err(e) = self.socket.set_linger(0) {
+            warn!("Failed to set linger on old socket: {}", e);
+        }
+        if let Err(e) = self.socket.unbind(&self.address) {
+            warn!("Failed to unbind old socket from '{}': {}", self.address, e);
+        }
+
+        let socket = self.context.socket(REP)
+            .expect("Failed to create socket");
+        // the unbind completes in the background, so the endpoint may still be taken for a moment
+        let mut attempt = 1;
+        while let Err(e) = socket.bind(&self.address) {
+            if attempt == REBIND_ATTEMPTS {
+                panic!("Failed to bind socket to '{}': {}", self.address, e);
+            }
+            warn!("Rebinding '{}' failed (attempt {}/{}): {}", self.address, attempt, REBIND_ATTEMPTS, e);
+            time::sleep(REBIND_BACKOFF).await;
+            attempt += 1;
+        }
+        self.socket = socket;
+        self.send_failed.set(false);
+
+    }
+
+    fn send_error_value(&self, error: Value) {
+
+        self.request_failed.set(true);
+        send_response!(self, error);
+
+    }
+
     pub fn send_error(&self, error: &str, details: String) {
 
-        send_response!(self.socket, json!({"ERROR": error, "DETAILS": details}));
+        self.respond(Response::Error(ErrorBody::new(error, details)));
         
     }
 
-    pub async fn handle_test(&self, body: &Value, device_list: &HashMap<String, Interface>) -> Option<()> {
+    fn respond(&self, response: Response) {
 
-        let key = String::from(body.as_str()?);
-        if device_list.contains_key(&key) {
-            send_response!(self.socket, json!({"TEST": key}));
-        } else {
-            send_response!(self.socket, json!({"TEST": key}));
+        if let Response::Error(_) = response {
+            self.request_failed.set(true);
         }
+        let response = serde_json::to_value(response).expect("Failed to serialize response");
+        send_response!(self, response);
+
+    }
+
+    // the stored response of an earlier SET with this key, moved to the most recent end
+    fn replay(&mut self, idempotency_key: &str) -> Option<(String, Value)> {
+
+        let ttl = Duration::from_secs(self.options.idempotency_ttl_s);
+        self.replies.retain(|(_, _, _, answered_at, _)| answered_at.elapsed() <= ttl);
+        let index = self.replies.iter().position(|(key, ..)| key == idempotency_key)?;
+        let (key, etag, device_names, _, response) = self.replies.remove(index)?;
+        self.replies.push_back((key, etag.clone(), device_names, Instant::now(), response.clone()));
+
+        Some((etag, response))
+
+    }
+
+    // answers a SET and keeps the response for replays of the same key
+    fn respond_set(&mut self, idempotency_key: Option<(String, String, Vec<String>)>, response: Response) {
+
+        if let Some((key, etag, device_names)) = idempotency_key {
+            let stored = serde_json::to_value(&response).expect("Failed to serialize response");
+            if self.replies.len() >= self.options.idempotency_keys {
+                self.replies.pop_front();
+            }
+            self.replies.push_back((key, etag, device_names, Instant::now(), stored));
+        }
+        self.respond(response);
+
+    }
+
+    pub async fn handle_test(&self, body: TestBody, device_list: &HashMap<String, Interface>) -> Option<()> {
+
+        let (key, probe_count) = match body {
+            TestBody::Key(key) => (key, None),
+            TestBody::Options(TestOptions { key, probe: false, .. }) => (key, None),
+            TestBody::Options(TestOptions { key, probe: true, count }) => match count.unwrap_or(PROBE_COUNT) {
+                count @ 1..=PROBE_COUNT_MAX => (key, Some(count)),
+                _ => return None,
+            },
+        };
+
+        let (probe, errors) = match probe_count {
+            Some(count) => {
+                let (probe, errors) = self.probe(count, device_list).await;
+                (Some(probe), errors)
+            },
+            None => (None, Map::new()),
+        };
+        // probing opens sessions, so the connection state is read afterwards
+        let connections: Map<String, Value> = device_list.iter()
+            .map(|(interface_name, interface)| (interface_name.clone(), json!({
+                "connect": interface.connect().to_string().to_lowercase(),
+                "connected": modbus::connection_state(interface_name),
+            })))
+            .collect();
+        self.respond(Response::Test(TestResponse { key, connections, probe, errors }));
+
+        Some(())
+
+    }
+
+    async fn probe(&self, count: usize, device_list: &HashMap<String, Interface>) -> (Map<String, Value>, Map<String, Value>) {
+
+        let mut interface_names: Vec<&String> = device_list.keys().collect();
+        interface_names.sort();
+        info!("Probing {} interface(s) with {} read(s) per slave", interface_names.len(), count);
+
+        let tasks = interface_names.iter().map(|interface_name| task::spawn(
+            modbus::probe((*interface_name).clone(), device_list[*interface_name].clone(), count)
+        ));
+        let mut probe = Map::new();
+        let mut errors = Map::new();
+        let results = future::join_all(tasks).await;
+        for (interface_name, results) in interface_names.into_iter().zip(results) {
+            match results {
+                Ok(Ok(results)) => {
+                    let mut slaves = Map::new();
+                    for (slave_name, round_trips) in results {
+                        let answered: Vec<Duration> = round_trips.iter().flatten().copied().collect();
+                        for round_trip in &answered {
+                            self.stats.lock().unwrap().record_probe(format!("/{}/{}", interface_name, slave_name), *round_trip);
+                        }
+                        let ms = |round_trip: Duration| round_trip.as_secs_f64() * 1000.0;
+                        slaves.insert(slave_name, json!({
+                            "sent": round_trips.len(),
+                            "lost": round_trips.len() - answered.len(),
+                            "min_ms": answered.iter().min().copied().map(ms),
+                            "avg_ms": match answered.len() {
+                                0 => None,
+                                len => Some(ms(answered.iter().sum::<Duration>()) / len as f64),
+                            },
+                            "max_ms": answered.iter().max().copied().map(ms),
+                        }));
+                    }
+                    probe.insert(interface_name.clone(), Value::Object(slaves));
+                },
+                Ok(Err(modbus_error)) => {
+                    warn!("Probe skips '{}': {}", interface_name, modbus_error);
+                    errors.insert(interface_name.clone(), Value::String(format!("{}", modbus_error)));
+                },
+                Err(_) => {
+                    panic!("Task execute error");
+                }
+            }
+        }
+
+        (probe, errors)
+
+    }
+
+    pub async fn handle_dump_config(&self, body: DumpConfigBody, device_list: &HashMap<String, Interface>) -> Option<()> {
+
+        let DumpConfigOptions { device: device_name, layout, format } = match body {
+            DumpConfigBody::Device(device) => DumpConfigOptions { device, layout: false, format: DumpFormat::Yaml },
+            DumpConfigBody::Options(options) => options,
+        };
+        let interface = device_list.get(&device_name)?;
+        let config = match format {
+            DumpFormat::Yaml => serde_json::to_value(interface.to_yaml()).ok()?,
+            DumpFormat::Table => Value::String(display::format_register_table(interface)),
+            DumpFormat::Dotenv => Value::String(interface.to_dotenv(&device_name)),
+        };
+        if !layout || format != DumpFormat::Yaml {
+            self.respond(Response::DumpConfig(DumpConfigResponse { config }));
+            return Some(());
+        }
+        let mut config = config;
+
+        let mut layouts = Map::new();
+        for (slave_name, slave_data) in &interface.slaves {
+            let mut blocks = Map::new();
+            for (block_name, block) in [("co", BlockType::Co), ("di", BlockType::Di), ("hr", BlockType::Hr), ("ir", BlockType::Ir)] {
+                let spans: Vec<Value> = slave_data.layout(block).into_iter()
+                    .map(|(start, len, name)| json!({"start": start, "len": len, "name": name}))
+                    .collect();
+                if !spans.is_empty() {
+                    blocks.insert(String::from(block_name), Value::Array(spans));
+                }
+            }
+            layouts.insert(slave_name.clone(), Value::Object(blocks));
+        }
+        config.as_object_mut()?.insert(String::from("layout"), Value::Object(layouts));
+        self.respond(Response::DumpConfig(DumpConfigResponse { config }));
 
         Some(())
 
     }
 
-    pub async fn handle_get(&mut self, body: &Value, device_list: &HashMap<String, Interface>) -> Option<()> {
+    fn stale_values(&self, interface_name: &str, interface: &Interface, request_info: &RequestInfo, with_units: bool, describe: bool) -> Option<Vec<(String, Value)>> {
+
+        let mut stale_values = Vec::new();
+        for RequestItem { slave: slave_name, name: value_name, .. } in request_info {
+            let path = format!("/{}/{}/{}", interface_name, slave_name, value_name);
+            let (value, read_at) = self.last_values.get(&path)?;
+            let mut stale_value = json!({
+                "value": value,
+                "stale": true,
+                "age_ms": read_at.elapsed().as_millis() as u64,
+            });
+            // nothing was resolved by a read, the points are looked up in the config
+            let modbus_data = interface.resolve_slave(slave_name).and_then(|(slave, _)| slave.find(value_name));
+            if let (Some(modbus_data), Value::Object(stale_value)) = (modbus_data, &mut stale_value) {
+                stale_value.extend(point_fields(&modbus_data, with_units, describe));
+            }
+            stale_values.push((value_name.clone(), stale_value));
+        }
+
+        Some(stale_values)
+
+    }
+
+    pub async fn handle_get(&mut self, body: GetBody, device_list: &HashMap<String, Interface>) -> Option<()> {
+
+        let mut planner = TaskPlan::with_dedup(self.options.dedup)
+            .with_limits(self.options.max_request_items, self.options.max_interface_items);
+        let (paths, etag, with_units, describe) = match body {
+            GetBody::Paths(paths) => (paths, None, false, false),
+            GetBody::Options(GetOptions { paths, etag, with_units, describe }) => (paths, Some(etag), with_units, describe),
+        };
+        if let Some(error) = planner.limit_error(paths.iter().map(String::as_str)) {
+            self.send_error_value(error);
+            return Some(());
+        }
+        for path in &paths {
+            planner.push(path, None);
+        }
+
+        if let Some(error) = device_not_found_error(planner.device_names(), device_list) {
+            self.send_error_value(error);
+            return Some(());
+        }
 
-        let mut planner = TaskPlan::new();
-        for path in body.as_array()? {
-            planner.push(path.as_str()?, None);
+        let plan: BatchPlan<'_> = planner.plan();
+        if let Some(error) = unknown_path_error(&plan, device_list) {
+            self.send_error_value(error);
+            return Some(());
+        }
+        if let Some(error) = batch_size_error(&plan, device_list) {
+            self.send_error_value(error);
+            return Some(());
         }
-        let plan: Vec<(&String, &Vec<(String, (String, Option<Value>))>)> = planner.plan();
 
         let mut results_table = Map::new();
+        // the store holds every path ever read, expired values go before new ones are added
+        if self.options.serve_stale && self.options.stale_ttl_s > 0 {
+            let ttl = Duration::from_secs(self.options.stale_ttl_s);
+            self.last_values.retain(|_, (_, read_at)| read_at.elapsed() <= ttl);
+        }
 
         for (interface_name, request_info) in plan {
 
             info!("Batch read from '{}': {}", interface_name, request_info.len());
             
+            let started = Instant::now();
             let mut tasks = Vec::new();
             
             if device_list.contains_key(interface_name) {
                 let handle = task::spawn(
-                    modbus::batch_request(device_list.get(interface_name)?.clone(), request_info.clone(), modbus::GetOrSet::Get)
+                    modbus::batch_request(interface_name.clone(), device_list.get(interface_name)?.clone(), request_info.clone())
                 );
                 tasks.push(handle);
             } else {
@@ -146,12 +1061,30 @@ impl Server {
                 match results {
                     Ok(results) => match results {
                         Ok(results) => {
-                            for (key, value) in results {
-                                results_table.insert(key, value);
+                            self.notify_monitor("GET", interface_name, request_info.len(), started.elapsed(), 0);
+                            for result in results {
+                                if self.options.serve_stale {
+                                    let path = format!("/{}/{}/{}", interface_name, result.slave, result.name);
+                                    self.last_values.insert(path, (result.value.clone(), Instant::now()));
+                                }
+                                let fields = point_fields(&result.modbus_data, with_units, describe);
+                                results_table.insert(result.name, with_fields(result.value, fields));
                             }
                         },
                         Err(modbus_error) => {
-                            self.send_error("MODBUS ERROR", format!("{}", modbus_error));
+                            self.notify_monitor("GET", interface_name, request_info.len(), started.elapsed(), request_info.len());
+                            match self.stale_values(interface_name, &device_list[interface_name], request_info, with_units, describe) {
+                                Some(stale_values) if self.options.serve_stale => {
+                                    warn!("Serving stale values for '{}': {}", interface_name, modbus_error);
+                                    for (key, value) in stale_values {
+                                        results_table.insert(key, value);
+                                    }
+                                },
+                                _ => {
+                                    self.send_error("MODBUS ERROR", format!("{}", modbus_error));
+                                    return Some(());
+                                }
+                            }
                         }
                     },
                     Err(_) => {
@@ -161,33 +1094,138 @@ impl Server {
             }
 
         }
-        
-        let mut wrapper = Map::new();
-        wrapper.insert("GET".to_string(), Value::Object(results_table));
 
-        send_response!(self.socket, Value::Object(wrapper).to_string());
+        if let Some(previous_etag) = etag {
+            let current_etag = response_etag(&results_table);
+            let unchanged = previous_etag.as_deref() == Some(current_etag.as_str());
+            self.respond(Response::Get(GetResponse {
+                values: match unchanged {
+                    true => Value::Null,
+                    false => Value::Object(results_table),
+                },
+                etag: Some(current_etag),
+                unchanged,
+            }));
+            return Some(());
+        }
+
+        self.respond(Response::Get(GetResponse { values: Value::Object(results_table), etag: None, unchanged: false }));
 
         Some(())
 
     }
 
-    pub async fn handle_set(&self, body: &Value, device_list: &HashMap<String, Interface>) -> Option<()> {
+    pub async fn handle_set(&mut self, body: SetBody, device_list: &HashMap<String, Interface>) -> Option<()> {
 
-        let mut planner = TaskPlan::new();
-        for (path, value) in body.as_object()? {
+        let mut planner = TaskPlan::new()
+            .with_limits(self.options.max_request_items, self.options.max_interface_items)
+            .with_client(self.peer.clone());
+        let SetOptions { values: pairs, on_error, coerce_strings, idempotency_key } = match body {
+            SetBody::Values(values) => SetOptions { values, on_error: OnError::Continue, coerce_strings: false, idempotency_key: None },
+            SetBody::Options(options) => options,
+        };
+        // a retried SET is answered as the first time, a key reused for other values is refused
+        let idempotency_key = match idempotency_key {
+            Some(key) if self.options.idempotency_keys > 0 => {
+                let etag = response_etag(&pairs);
+                match self.replay(&key) {
+                    Some((stored_etag, response)) if stored_etag == etag => {
+                        info!("SET replayed for idempotency key '{}'", key);
+                        send_response!(self, response);
+                        return Some(());
+                    },
+                    Some(_) => {
+                        self.send_error_value(json!({"ERROR": "IDEMPOTENCY_KEY_REUSED", "idempotency_key": key}));
+                        return Some(());
+                    },
+                    None => Some((key, etag)),
+                }
+            },
+            _ => None,
+        };
+        if let Some(error) = planner.limit_error(pairs.keys().map(String::as_str)) {
+            self.send_error_value(error);
+            return Some(());
+        }
+        for (path, value) in &pairs {
             planner.push(path, Some(value.clone()));
         }
-        let plan: Vec<(&String, &Vec<(String, (String, Option<Value>))>)> = planner.plan();
+        let parse_failures = match coerce_strings {
+            true => planner.coerce_strings(device_list),
+            false => Vec::new(),
+        };
+        let plan: BatchPlan<'_> = planner.plan();
+        if let Some(error) = unknown_path_error(&plan, device_list) {
+            self.send_error_value(error);
+            return Some(());
+        }
+        if let Some(error) = read_only_error(&plan, device_list) {
+            self.send_error_value(error);
+            return Some(());
+        }
+        if let Some(error) = write_disabled_error(&plan, device_list, self.options.read_only) {
+            self.send_error_value(error);
+            return Some(());
+        }
+        if let Some(error) = batch_size_error(&plan, device_list) {
+            self.send_error_value(error);
+            return Some(());
+        }
+        // a reload of any of these devices drops the stored response
+        let idempotency_key = idempotency_key.map(|(key, etag)| {
+            (key, etag, plan.iter().map(|(interface_name, _)| (*interface_name).clone()).collect::<Vec<_>>())
+        });
+
+        let mut written_table = Map::new();
+        let mut coerced_list = Vec::new();
+        let mut failed_table: Map<String, Value> = parse_failures.into_iter()
+            .map(|(path, error)| (path, Value::String(error)))
+            .collect();
+        let mut skipped_list = Vec::new();
+        let mut simulated = false;
 
         for (interface_name, request_info) in plan {
 
+            if on_error == OnError::Abort && !failed_table.is_empty() {
+                skipped_list.extend(request_info.iter()
+                    .map(|item| Value::String(format!("/{}/{}/{}", interface_name, item.slave, item.name))));
+                continue;
+            }
+
+            let interface = device_list.get(interface_name)?;
+            if write_mode(interface, self.options.read_only) == WriteMode::Simulate {
+                info!("Simulated write to '{}': {}", interface_name, request_info.len());
+                simulated = true;
+                for item in request_info {
+                    let path = format!("/{}/{}/{}", interface_name, item.slave, item.name);
+                    // paths were resolved by unknown_path_error already
+                    let modbus_data = interface.resolve_slave(&item.slave)
+                        .and_then(|(slave, _)| slave.find(&item.name))?;
+                    match modbus::simulate_write(&item.name, &modbus_data, &item.value) {
+                        Ok(written) => {
+                            if let Some(requested) = &item.value {
+                                if is_coerced(requested, &written) {
+                                    coerced_list.push(Value::String(path.clone()));
+                                }
+                            }
+                            written_table.insert(path, written);
+                        },
+                        Err(modbus_error) => {
+                            failed_table.insert(path, Value::String(format!("{}", modbus_error)));
+                        },
+                    }
+                }
+                continue;
+            }
+
             info!("Batch write to '{}': {}", interface_name, request_info.len());
             
+            let started = Instant::now();
             let mut tasks = Vec::new();
             
             if device_list.contains_key(interface_name) {
                 let handle = task::spawn(
-                    modbus::batch_request(device_list.get(interface_name)?.clone(), request_info.clone(), modbus::GetOrSet::Set)
+                    modbus::batch_request_items(interface_name.clone(), device_list.get(interface_name)?.clone(), request_info.clone(), on_error)
                 );
                 tasks.push(handle);
             } else {
@@ -198,9 +1236,34 @@ impl Server {
                 
                 match results {
                     Ok(results) => match results {
-                        Ok(_) => {},
+                        Ok(results) => {
+                            let errors = results.iter().filter(|result| result.is_err()).count();
+                            self.notify_monitor("SET", interface_name, request_info.len(), started.elapsed(), errors);
+                            let attempted = results.len();
+                            for (item, result) in request_info.iter().zip(results) {
+                                let path = format!("/{}/{}/{}", interface_name, item.slave, item.name);
+                                let result = match result {
+                                    Ok(result) => result,
+                                    Err(modbus_error) => {
+                                        failed_table.insert(path, Value::String(format!("{}", modbus_error)));
+                                        continue;
+                                    },
+                                };
+                                if let Some(requested) = &item.value {
+                                    if is_coerced(requested, &result.value) {
+                                        coerced_list.push(Value::String(path.clone()));
+                                    }
+                                }
+                                written_table.insert(path, result.value);
+                            }
+                            skipped_list.extend(request_info[attempted..].iter()
+                                .map(|item| Value::String(format!("/{}/{}/{}", interface_name, item.slave, item.name))));
+                        },
                         Err(modbus_error) => {
-                            self.send_error("MODBUS ERROR", format!("{}", modbus_error));
+                            self.notify_monitor("SET", interface_name, request_info.len(), started.elapsed(), request_info.len());
+                            // devices earlier in the plan may have been written already
+                            self.respond_set(idempotency_key, Response::Error(ErrorBody::new("MODBUS ERROR", format!("{}", modbus_error))));
+                            return Some(());
                         }
                     },
                     Err(_) => {
@@ -211,77 +1274,564 @@ impl Server {
 
         }
 
-        send_response!(self.socket, "{\"SET\":null}");
+        if !failed_table.is_empty() {
+            warn!("Batch write failed for {} path(s), {} skipped", failed_table.len(), skipped_list.len());
+        }
+        self.respond_set(idempotency_key, Response::Set(SetResponse {
+            written: written_table,
+            coerced: coerced_list,
+            failed: failed_table,
+            skipped: skipped_list,
+            simulated,
+        }));
 
         Some(())
 
     }
 
-    async fn handle_message(&mut self, device_list: &HashMap<String, Interface>) -> Option<()> {
-            
-        let string = self.message.as_str()?;
-    
-        let result: Value = match serde_json::from_str(string) {
-            Ok(result) => Some(result),
-            Err(_) => None
-        }?;
-    
-        let object = result.as_object()?;
-    
-        if object.len() != 1 {
-            return None;
+    pub async fn handle_identify(&self, body: IdentifyBody, device_list: &HashMap<String, Interface>) -> Option<()> {
+
+        let (interface_name, slave_name) = (body.interface.as_str(), body.slave.as_str());
+        let interface = device_list.get(interface_name)?;
+        if interface.resolve_slave(slave_name).is_none() {
+            self.send_error_value(unit_range_error(interface_name, interface, slave_name).unwrap_or_else(|| json!({
+                "ERROR": "UNKNOWN_SLAVE",
+                "device": interface_name,
+                "slave": slave_name,
+                "suggestions": closest_names(slave_name, interface.slaves.keys().map(String::as_str)),
+            })));
+            return Some(());
         }
-    
-        for (method, body) in object {
-            
-            match method.to_uppercase().as_str() {
-                "TEST" => match self.handle_test(body, device_list).await {
-                    Some(_) => {}, None => {
-                        self.send_error("INVAILED TEST", format!("{}", body));
-                    }
+
+        info!("Read device identification from '{}/{}'", interface_name, slave_name);
+        let handle = task::spawn(modbus::identify(interface.clone(), String::from(slave_name)));
+        match handle.await {
+            Ok(Ok(objects)) => {
+                self.respond(Response::Identify(IdentifyResponse { objects }));
+            },
+            Ok(Err(ModbusError::ModbusException(ExceptionCode::IllegalFunction))) => {
+                self.send_error("UNSUPPORTED", format!("'{}/{}' does not support read device identification", interface_name, slave_name));
+            },
+            Ok(Err(modbus_error)) => {
+                self.send_error("MODBUS ERROR", format!("{}", modbus_error));
+            },
+            Err(_) => {
+                panic!("Task execute error");
+            }
+        }
+
+        Some(())
+
+    }
+
+    pub async fn handle_hexdump(&self, body: HexdumpBody, device_list: &HashMap<String, Interface>) -> Option<()> {
+
+        let (interface_name, slave_name) = (body.interface.as_str(), body.slave.as_str());
+        let interface = device_list.get(interface_name)?;
+        if interface.resolve_slave(slave_name).is_none() {
+            self.send_error_value(unit_range_error(interface_name, interface, slave_name).unwrap_or_else(|| json!({
+                "ERROR": "UNKNOWN_SLAVE",
+                "device": interface_name,
+                "slave": slave_name,
+                "suggestions": closest_names(slave_name, interface.slaves.keys().map(String::as_str)),
+            })));
+            return Some(());
+        }
+        // the most a single read may ask for
+        let max_count = match body.block {
+            BlockType::Co | BlockType::Di => 2000,
+            BlockType::Hr | BlockType::Ir => 125,
+        };
+        if body.count == 0 || body.count > max_count {
+            self.send_error("INVAILED HEXDUMP", format!("count must be 1-{} for {}", max_count, body.block));
+            return Some(());
+        }
+
+        info!("Hex dump of '{}/{}' {} {}+{}", interface_name, slave_name, body.block, body.start, body.count);
+        let handle = task::spawn(modbus::read_bytes(interface.clone(), String::from(slave_name), body.block, body.start, body.count));
+        match handle.await {
+            Ok(Ok(bytes)) => {
+                self.respond(Response::Hexdump(HexdumpResponse { dump: display::format_hexdump(&bytes) }));
+            },
+            Ok(Err(modbus_error)) => {
+                self.send_error("MODBUS ERROR", format!("{}", modbus_error));
+            },
+            Err(_) => {
+                panic!("Task execute error");
+            }
+        }
+
+        Some(())
+
+    }
+
+    pub async fn handle_aggregate(&self, body: AggregateBody, device_list: &HashMap<String, Interface>) -> Option<()> {
+
+        let (interface_name, point, op) = (body.interface.as_str(), body.point.as_str(), body.op);
+        let interface = device_list.get(interface_name)?;
+
+        let slave_names: Vec<String> = interface.slave_names().into_iter()
+            .filter(|slave_name| matches!(interface.resolve_slave(slave_name), Some((slave_data, _)) if slave_data.find(point).is_some()))
+            .collect();
+
+        // one batch per slave so a failing slave is skipped instead of failing the rest
+        let mut values = Vec::new();
+        let mut skipped = Vec::new();
+        for slave_name in slave_names {
+            let item = RequestItem {
+                slave: slave_name.clone(),
+                name: String::from(point),
+                op: GetOrSet::Get,
+                value: None,
+                options: ItemOptions::default(),
+            };
+            let handle = task::spawn(modbus::batch_request(String::from(interface_name), interface.clone(), vec![item]));
+            match handle.await {
+                Ok(Ok(results)) => match results.first().and_then(|result| result.value.as_f64()) {
+                    Some(value) if value.is_finite() => values.push(value),
+                    _ => skipped.push(slave_name.clone()),
                 },
-                "GET" => match self.handle_get(body, device_list).await {
-                    Some(_) => {}, None => {
-                        self.send_error("INVAILED GET", format!("{}", body));
-                    }
+                Ok(Err(modbus_error)) => {
+                    warn!("Aggregate skips '{}': {}", slave_name, modbus_error);
+                    skipped.push(slave_name.clone());
                 },
-                "SET" => match self.handle_set(body, device_list).await {
-                    Some(_) => {}, None => {
-                        self.send_error("INVAILED SET", format!("{}", body));
+                Err(_) => {
+                    panic!("Task execute error");
+                }
+            }
+        }
+
+        let value = match (op, values.is_empty()) {
+            (_, true) => None,
+            (AggregateOp::Sum, _) => Some(values.iter().sum::<f64>()),
+            (AggregateOp::Avg, _) => Some(values.iter().sum::<f64>() / values.len() as f64),
+            (AggregateOp::Min, _) => values.iter().copied().reduce(f64::min),
+            (AggregateOp::Max, _) => values.iter().copied().reduce(f64::max),
+        };
+        self.respond(Response::Aggregate(AggregateResponse { result: AggregateResult {
+            op,
+            value,
+            count: values.len(),
+            skipped,
+        }}));
+
+        Some(())
+
+    }
+
+    pub async fn handle_snapshot(&self, device_list: &HashMap<String, Interface>) -> Option<()> {
+
+        let mut interface_names: Vec<&String> = device_list.keys().collect();
+        interface_names.sort();
+        info!("Snapshot of {} interface(s)", interface_names.len());
+
+        // interfaces are read concurrently, a failing one is reported without holding back the rest
+        let tasks = interface_names.iter().map(|interface_name| task::spawn(
+            modbus::read_all((*interface_name).clone(), device_list[*interface_name].clone())
+        ));
+        let mut snapshot = Map::new();
+        let mut status = Map::new();
+        let mut errors = Map::new();
+        let results = future::join_all(tasks).await;
+        for (interface_name, results) in interface_names.into_iter().zip(results) {
+            match results {
+                Ok(Ok(results)) => {
+                    // (points read, points failed, slave answered)
+                    let mut counts: BTreeMap<String, (usize, usize, bool)> = device_list[interface_name].slave_names()
+                        .into_iter().map(|slave_name| (slave_name, (0, 0, false))).collect();
+                    let mut slaves = Map::new();
+                    for (slave_name, result) in results {
+                        let count = counts.entry(slave_name.clone()).or_insert((0, 0, false));
+                        match result {
+                            Ok(result) => {
+                                count.0 += 1;
+                                count.2 = true;
+                                if let Value::Object(values) = slaves.entry(result.slave).or_insert_with(|| json!({})) {
+                                    values.insert(result.name, result.value);
+                                }
+                            },
+                            Err(modbus_error) => {
+                                warn!("Snapshot of '{}' on '{}' failed a point: {}", slave_name, interface_name, modbus_error);
+                                count.1 += 1;
+                                count.2 |= modbus_error.is_exception();
+                                slaves.entry(slave_name).or_insert_with(|| json!({}));
+                            },
+                        }
                     }
+                    // a slave without points was never asked, so its reachability is unknown
+                    let slave_status = counts.into_iter().map(|(slave_name, (read, failed, answered))| (slave_name, json!({
+                        "reachable": if read + failed == 0 { Value::Null } else { Value::Bool(answered) },
+                        "points_read": read,
+                        "points_failed": failed,
+                    }))).collect();
+                    snapshot.insert(interface_name.clone(), Value::Object(slaves));
+                    status.insert(interface_name.clone(), Value::Object(slave_status));
+                },
+                Ok(Err(modbus_error)) => {
+                    warn!("Snapshot skips '{}': {}", interface_name, modbus_error);
+                    errors.insert(interface_name.clone(), Value::String(format!("{}", modbus_error)));
                 },
-                _ => {
-                    self.send_error("INVAILED METHOD", format!("{}", body));
+                Err(_) => {
+                    panic!("Task execute error");
                 }
             }
+        }
 
-            break;
-    
+        self.respond(Response::Snapshot(SnapshotResponse { values: snapshot, status, errors }));
+
+        Some(())
+
+    }
+
+    pub fn handle_list(&self, device_list: &HashMap<String, Interface>) -> Option<()> {
+
+        // device -> slave -> point names, straight from the loaded config
+        let devices: Map<String, Value> = device_list.iter()
+            .map(|(interface_name, interface)| (interface_name.clone(), interface.slaves.iter()
+                .map(|(slave_name, slave_data)| {
+                    let mut names: Vec<&str> = slave_data.registers().names().collect();
+                    names.sort();
+                    (slave_name.clone(), json!(names))
+                })
+                .collect()))
+            .collect();
+        self.respond(Response::List(ListResponse { devices }));
+
+        Some(())
+
+    }
+
+    // every file is loaded before anything is replaced, so a broken file keeps the running config;
+    // only removed and changed devices lose their session, unchanged ones keep it
+    async fn reload(&mut self, device_list: &mut HashMap<String, Interface>) -> Result<ConfigDiff, String> {
+
+        if self.options.device_sources.is_empty() {
+            return Err(String::from("no device files to load again"));
         }
-    
+        let mut reloaded = HashMap::new();
+        for source in &self.options.device_sources {
+            let interface = source.load()
+                .map_err(|e| format!("{}: {}", source.name, e))?;
+            reloaded.insert(source.name.clone(), interface);
+        }
+
+        let diff = diff_configs(device_list, &reloaded);
+        for device_name in diff.removed.iter().chain(&diff.changed) {
+            modbus::close_session(device_name);
+            let prefix = format!("/{}/", device_name);
+            self.last_values.retain(|path, _| !path.starts_with(&prefix));
+            self.replies.retain(|(_, _, device_names, _, _)| !device_names.contains(device_name));
+        }
+        for device_name in diff.added.iter().chain(&diff.changed) {
+            let interface = &reloaded[device_name];
+            if interface.connect() == ConnectMode::Eager {
+                if let Err(e) = modbus::connect_eager(device_name, interface).await {
+                    error!("Failed to connect to '{}', will retry on first request: {}", device_name, e);
+                }
+            }
+        }
+        info!("Config reloaded: added {:?}, removed {:?}, changed {:?}, {} unchanged",
+            diff.added, diff.removed, diff.changed, diff.unchanged.len());
+        *device_list = reloaded;
+
+        Ok(diff)
+
+    }
+
+    pub async fn handle_reload(&mut self, device_list: &mut HashMap<String, Interface>) -> Option<()> {
+
+        match self.reload(device_list).await {
+            Ok(diff) => {
+                let changes = diff.devices.iter()
+                    .map(|(device_name, device_diff)| (device_name.clone(), json!(device_diff)))
+                    .collect();
+                self.respond(Response::Reload(ReloadResponse {
+                    devices: json!({
+                        "added": diff.added,
+                        "removed": diff.removed,
+                        "changed": diff.changed,
+                        "unchanged": diff.unchanged,
+                    }),
+                    changes,
+                }));
+            },
+            Err(reason) => {
+                error!("Reload failed, keeping the running config: {}", reason);
+                self.send_error("RELOAD_FAILED", reason);
+            },
+        }
+
         Some(())
-        
+
+    }
+
+    pub fn handle_stats(&self, device_list: &HashMap<String, Interface>) -> Option<()> {
+
+        let mut stats = self.stats.lock().unwrap().summary();
+        stats.writes = device_list.iter()
+            .map(|(interface_name, interface)| (interface_name.clone(), write_mode(interface, self.options.read_only).to_string().to_lowercase()))
+            .collect();
+        self.respond(Response::Stats(StatsResponse { stats }));
+
+        Some(())
+
+    }
+
+    async fn handle_message(&mut self, device_list: &mut HashMap<String, Interface>) -> Option<()> {
+            
+        let string = self.message.as_str()?;
+        let (request, body) = match Request::parse(string) {
+            Ok(parsed) => parsed,
+            Err(request_error) => {
+                warn!("Request rejected: {}", request_error);
+                self.respond(Response::Error(ErrorBody::from(request_error)));
+                return Some(());
+            }
+        };
+
+        let method = request.method();
+        let handled = match request {
+            Request::Test(body) => self.handle_test(body, device_list).await,
+            Request::Get(body) => self.handle_get(body, device_list).await,
+            Request::Set(body) => self.handle_set(body, device_list).await,
+            Request::DumpConfig(body) => self.handle_dump_config(body, device_list).await,
+            Request::Aggregate(body) => self.handle_aggregate(body, device_list).await,
+            Request::Snapshot(()) => self.handle_snapshot(device_list).await,
+            Request::Identify(body) => self.handle_identify(body, device_list).await,
+            Request::Hexdump(body) => self.handle_hexdump(body, device_list).await,
+            Request::List(()) => self.handle_list(device_list),
+            Request::Stats(()) => self.handle_stats(device_list),
+            Request::Reload(()) => self.handle_reload(device_list).await,
+        };
+        // the body was well formed but named something that does not exist
+        if handled.is_none() {
+            self.send_error(&format!("INVAILED {}", method.as_str()), body.to_string());
+        }
+
+        Some(())
+
+    }
+
+    pub fn endpoint(&self) -> String {
+
+        match self.socket.get_last_endpoint() {
+            Ok(Ok(endpoint)) => endpoint,
+            _ => self.address.clone(),
+        }
+
+    }
+
+    fn notify_monitor(&self, method: &str, device: &str, count: usize, latency: Duration, errors: usize) {
+
+        let monitor = match &self.monitor {
+            Some(monitor) => monitor,
+            None => return,
+        };
+        let ts = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        let frame = json!({
+            "ts": ts,
+            "method": method,
+            "device": device,
+            "count": count,
+            "latency_ms": latency.as_millis() as u64,
+            "errors": errors,
+        });
+        // a slow or absent monitor must never hold up the request loop
+        if let Err(e) = monitor.send(frame.to_string().as_bytes(), zmq::DONTWAIT) {
+            debug!("Monitor frame dropped: {}", e);
+        }
+
     }
 
-    pub async fn forever(&mut self, device_list: &HashMap<String, Interface>) {
+    // polls instead of blocking in recv, so the runtime thread stays free for the other tasks while idle;
+    // Ok(false) when a reload was requested before any message came in
+    async fn receive(&mut self) -> Result<bool, zmq::Error> {
 
+        let interval = Duration::from_millis(self.options.zmq_poll_interval_ms.max(1));
         loop {
+            match self.socket.recv(&mut self.message, zmq::DONTWAIT) {
+                Err(zmq::Error::EAGAIN) if self.reload_requested.swap(false, Ordering::Relaxed) => return Ok(false),
+                Err(zmq::Error::EAGAIN) => time::sleep(interval).await,
+                result => return result.map(|_| true),
+            }
+        }
+
+    }
 
-            self.socket.recv(&mut self.message, 0)
-                .expect("Failed to receive message");
+    // what SIGHUP sets, for callers that trigger a reload in another way
+    pub fn reload_handle(&self) -> Arc<AtomicBool> {
+
+        self.reload_requested.clone()
+
+    }
+
+    // false once the server should stop, see SendFailure
+    pub async fn serve_one(&mut self, device_list: &mut HashMap<String, Interface>) -> bool {
+
+        match self.receive().await {
+            Ok(true) => {},
+            Ok(false) => {
+                if let Err(reason) = self.reload(device_list).await {
+                    error!("Reload failed, keeping the running config: {}", reason);
+                }
+                return true;
+            },
+            // a socket that still owes a response refuses to receive, start over with a fresh one
+            Err(e) => {
+                error!("Error when receive request: {}", e);
+                self.send_failed.set(true);
+                return self.recover().await;
+            },
+        }
 
-            info!("Request received: {}", self.message.len());
+        info!("Request received: {}", self.message.len());
+        self.peer = self.message.gets("Peer-Address").map(String::from);
 
+        let received_at = Instant::now();
+        self.request_failed.set(false);
+        let (size, max_size) = (self.message.len(), self.options.max_message_size);
+        if max_size > 0 && size > max_size {
+            warn!("Request of {} bytes discarded, limit is {}", size, max_size);
+            self.send_error_value(json!({"ERROR": "MESSAGE_TOO_LARGE", "limit_bytes": max_size, "received_bytes": size}));
+        } else {
+            if max_size > 0 && size * 10 >= max_size * 9 {
+                warn!("Request of {} bytes is close to the {} byte limit", size, max_size);
+            }
             match self.handle_message(device_list).await {
                 Some(_) => {},
                 None => {
-                    self.send_error("INVAILD REQUEST", format!(""));
-                    continue;
+                    self.send_error("INVAILD REQUEST", String::new());
                 }
             };
+        }
+        self.stats.lock().unwrap().record(received_at.elapsed(), self.request_failed.get());
+
+        match self.send_failed.get() {
+            true => self.recover().await,
+            false => true,
+        }
+
+    }
+
+    async fn recover(&mut self) -> bool {
 
+        match self.options.on_send_failure {
+            SendFailure::Reset => {
+                self.reset_socket().await;
+                true
+            },
+            SendFailure::Exit => {
+                error!("Stopping after failed response, {} dropped", self.responses_dropped.get());
+                false
+            },
         }
 
     }
 
-}
\ No newline at end of file
+    pub async fn forever(&mut self, device_list: &mut HashMap<String, Interface>) {
+
+        let reload_requested = self.reload_requested.clone();
+        match signal(SignalKind::hangup()) {
+            Ok(mut hangup) => {
+                tokio::spawn(async move {
+                    while hangup.recv().await.is_some() {
+                        info!("SIGHUP received, reloading the device files");
+                        reload_requested.store(true, Ordering::Relaxed);
+                    }
+                });
+            },
+            Err(e) => warn!("SIGHUP will not reload, failed to install the handler: {}", e),
+        }
+
+        if self.options.stats_interval_s > 0 {
+            let stats = self.stats.clone();
+            let period = Duration::from_secs(self.options.stats_interval_s);
+            let log_format = self.options.log_format;
+            tokio::spawn(async move {
+                let mut interval = time::interval(period);
+                interval.tick().await;
+                loop {
+                    interval.tick().await;
+                    stats.lock().unwrap().report(log_format);
+                }
+            });
+        }
+
+        while self.serve_one(device_list).await {}
+
+    }
+
+}
+
+impl Drop for Server {
+
+    fn drop(&mut self) {
+
+        if let Some(ipc_path) = self.ipc_path() {
+            if let Err(e) = fs::remove_file(ipc_path) {
+                warn!("Failed to remove '{}': {}", ipc_path, e);
+            }
+        }
+
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn stats_are_pruned_as_they_are_recorded() {
+
+        let mut stats = RequestStats::new(Duration::from_millis(50));
+        stats.record(Duration::from_millis(1), false);
+        stats.record_probe(String::from("/plant/meter"), Duration::from_millis(1));
+        std::thread::sleep(Duration::from_millis(60));
+        stats.record(Duration::from_millis(2), false);
+        stats.record_probe(String::from("/plant/meter"), Duration::from_millis(2));
+        assert_eq!((stats.window.len(), stats.probes.len()), (1, 1));
+
+    }
+
+    #[test]
+    fn stats_are_logged_in_the_chosen_format() {
+
+        let mut stats = RequestStats::new(Duration::from_secs(10));
+        stats.record(Duration::from_millis(4), false);
+        stats.record(Duration::from_millis(8), true);
+
+        let line = stats.report_line(LogFormat::Text);
+        assert!(line.starts_with("Stats: rps=0.20 mean_ms=6.0 "), "{}", line);
+        assert!(line.contains(" error_rate=50.0% "), "{}", line);
+
+        let line = stats.report_line(LogFormat::Json);
+        let event: Value = serde_json::from_str(&line).unwrap_or_else(|e| panic!("{}: {}", e, line));
+        assert_eq!(event["event"], "stats", "{}", line);
+        assert_eq!((event["mean_ms"].as_f64(), event["error_rate"].as_f64()), (Some(6.0), Some(50.0)), "{}", line);
+        assert_eq!(event["interval_s"], 10, "{}", line);
+
+    }
+
+    #[tokio::test]
+    async fn next_request_is_served_after_a_failed_send() {
+
+        let mut server = Server::new("tcp://127.0.0.1:*", ServerOptions::default());
+        // a REP socket that owes no response refuses to send, as a wedged one does
+        send_response!(server, json!({"LIST": {}}));
+        assert_eq!(server.responses_dropped(), 1);
+        assert!(server.recover().await, "the server stopped");
+
+        let context = zmq::Context::new();
+        let client = context.socket(zmq::REQ).unwrap();
+        client.set_rcvtimeo(10000).unwrap();
+        client.connect(&server.endpoint()).unwrap();
+        client.send(json!({"LIST": null}).to_string().as_str(), 0).unwrap();
+        assert!(server.serve_one(&mut HashMap::new()).await, "the server stopped");
+        assert_eq!(client.recv_string(0).unwrap().unwrap(), json!({"LIST": {}}).to_string());
+        assert_eq!(server.responses_dropped(), 1);
+
+    }
+
+}
@@ -0,0 +1,124 @@
+use std::fs;
+use serde_json::{json, Map, Value};
+
+use crate::interface::{BlockType, Interface};
+
+
+fn error_entry(message: String, location: Option<serde_yaml::Location>) -> Value {
+
+    let mut entry = Map::new();
+    entry.insert(String::from("message"), Value::from(message));
+    if let Some(location) = location {
+        entry.insert(String::from("line"), Value::from(location.line()));
+        entry.insert(String::from("column"), Value::from(location.column()));
+    }
+
+    Value::Object(entry)
+
+}
+
+fn device_summary(interface: &Interface) -> Value {
+
+    let mut slave_list: Vec<_> = interface.slaves.iter().collect();
+    slave_list.sort_by_key(|(slave_name, _)| slave_name.as_str());
+
+    let mut slaves = Vec::new();
+    let mut total = 0;
+    for (slave_name, slave_data) in slave_list {
+        let mut points = Map::new();
+        for block_type in [BlockType::Co, BlockType::Di, BlockType::Hr, BlockType::Ir] {
+            let count = slave_data.registers().iter_block(block_type).count();
+            points.insert(block_type.to_string().to_lowercase(), Value::from(count));
+        }
+        total += slave_data.registers().total_count();
+        slaves.push(json!({ "name": slave_name, "id": slave_data.id(), "points": points }));
+    }
+
+    json!({
+        "protocol": interface.to_yaml()["protocol"].as_str(),
+        "address": interface.address(),
+        "slaves": slaves,
+        "points": total,
+    })
+
+}
+
+// parse status, device summary, errors and warnings of one device file
+pub fn validate_file(path: &str) -> Value {
+
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    let mut parsed = false;
+    let mut device = Value::Null;
+    match fs::read(path) {
+        Ok(data) => match serde_yaml::from_slice::<serde_yaml::Value>(&data) {
+            // the loader only keeps the message, the location comes from the parser itself
+            Err(e) => errors.push(error_entry(e.to_string(), e.location())),
+            Ok(_) => {
+                parsed = true;
+                match Interface::from_bytes(&data, "yaml") {
+                    Ok(interface) => {
+                        let mut slave_list: Vec<_> = interface.slaves.iter().collect();
+                        slave_list.sort_by_key(|(slave_name, _)| slave_name.as_str());
+                        for (slave_name, slave_data) in slave_list {
+                            for warning in slave_data.validate() {
+                                warnings.push(json!({ "slave": slave_name, "message": warning.to_string() }));
+                            }
+                        }
+                        device = device_summary(&interface);
+                    },
+                    Err(e) => errors.push(error_entry(e.to_string(), None)),
+                }
+            },
+        },
+        Err(e) => errors.push(error_entry(format!("Could not open file: {}", e), None)),
+    }
+
+    json!({
+        "file": path,
+        "parsed": parsed,
+        "valid": errors.is_empty(),
+        "device": device,
+        "errors": errors,
+        "warnings": warnings,
+    })
+
+}
+
+// the whole dry run as one document, valid only when no file has an error
+pub fn validate_files(paths: &[String]) -> Value {
+
+    let files: Vec<Value> = paths.iter().map(|path| validate_file(path)).collect();
+    let valid = files.iter().all(|file| file["valid"] == true);
+
+    json!({ "valid": valid, "files": files })
+
+}
+
+// the same report as log-like lines, for a person at a terminal
+pub fn format_report(report: &Value) -> String {
+
+    let mut lines = Vec::new();
+    for file in report["files"].as_array().into_iter().flatten() {
+        let path = file["file"].as_str().unwrap_or_default();
+        match file["valid"] == true {
+            true => lines.push(format!("{}: ok, {} slave(s), {} point(s)",
+                path,
+                file["device"]["slaves"].as_array().map_or(0, Vec::len),
+                file["device"]["points"],
+            )),
+            false => for error in file["errors"].as_array().into_iter().flatten() {
+                lines.push(format!("{}: error: {}", path, error["message"].as_str().unwrap_or_default()));
+            },
+        }
+        for warning in file["warnings"].as_array().into_iter().flatten() {
+            lines.push(format!("{}: warning: '{}': {}", path,
+                warning["slave"].as_str().unwrap_or_default(),
+                warning["message"].as_str().unwrap_or_default(),
+            ));
+        }
+    }
+
+    lines.join("\n")
+
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use std::{fs, path::{Path, PathBuf}, time::{Duration, Instant}};
+use serde_json::{json, Value};
+
+use zero_modbus::modbus::{self, ItemOptions};
+use common::{set, MockDevice, MockQuirks};
+
+
+fn audit_path(name: &str) -> PathBuf {
+
+    let path = std::env::temp_dir().join(format!("zero_modbus_audit_{}_{}.jsonl", name, std::process::id()));
+    let _ = fs::remove_file(&path);
+
+    path
+
+}
+
+fn audit_lines(path: &Path) -> Vec<Value> {
+
+    fs::read_to_string(path).unwrap_or_default().lines()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("'{}' is not JSON: {}", line, e)))
+        .collect()
+
+}
+
+fn slaves(path: &Path) -> String {
+
+    format!("
+audit_log: {}
+slaves:
+- meter:
+    id: 1
+    hr:
+    - setpoint: {{addr: 3, type: u16}}
+", path.display())
+
+}
+
+#[tokio::test]
+async fn set_appends_an_audit_line() {
+
+    let device = MockDevice::start().await;
+    device.set_holding_registers(3, &[10]);
+    let path = audit_path("set");
+
+    let mut item = set("meter", "setpoint", json!(42));
+    item.options = ItemOptions { client: Some(String::from("tcp://10.0.0.7:5555")) };
+    modbus::batch_request(String::from("plant"), device.interface(&slaves(&path)), vec![item]).await
+        .unwrap_or_else(|e| panic!("the write failed: {}", e));
+
+    let lines = audit_lines(&path);
+    assert_eq!(lines.len(), 1, "{:?}", lines);
+    let line = &lines[0];
+    assert!(line["ts"].as_u64().is_some_and(|ts| ts > 0), "{}", line);
+    let mut fields = line.as_object().unwrap().clone();
+    fields.remove("ts");
+    assert_eq!(Value::Object(fields), json!({
+        "client": "tcp://10.0.0.7:5555",
+        "path": "/plant/meter/setpoint",
+        "old": 10,
+        "new": 42,
+        "ok": true,
+    }));
+    fs::remove_file(&path).unwrap();
+
+}
+
+#[tokio::test]
+async fn unanswered_audit_read_does_not_hold_the_write_back() {
+
+    let device = MockDevice::start().await;
+    device.set_holding_registers(3, &[10]);
+    device.set_quirks(MockQuirks { stall: Some(Duration::from_millis(1500)), ..MockQuirks::default() });
+    let path = audit_path("stalled");
+
+    let started = Instant::now();
+    let results = modbus::batch_request(String::from("plant"), device.interface(&slaves(&path)), vec![set("meter", "setpoint", json!(42))]).await
+        .unwrap_or_else(|e| panic!("the write failed: {}", e));
+    assert_eq!(results[0].value, json!(42));
+    // the audit read is given up after its bound, only the write is waited for
+    assert!(started.elapsed() < Duration::from_millis(2900), "{:?}", started.elapsed());
+    assert_eq!(device.holding_registers(3, 1), vec![42]);
+    // the late answer to the read went to the dropped connection
+    assert_eq!(device.connections(), 2);
+
+    let lines = audit_lines(&path);
+    assert_eq!(lines.len(), 1, "{:?}", lines);
+    assert_eq!(lines[0]["old"], Value::Null, "{}", lines[0]);
+    assert_eq!(lines[0]["new"], 42, "{}", lines[0]);
+    fs::remove_file(&path).unwrap();
+
+}
@@ -0,0 +1,533 @@
+// shared by the integration tests, not every test file uses every helper
+#![allow(dead_code)]
+
+use std::{borrow::Cow, collections::HashMap, fs, future::Future, net::SocketAddr, pin::Pin, sync::{Arc, Mutex}, time::{Duration, Instant}};
+use serde_json::Value;
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpListener};
+use tokio_modbus::{ExceptionCode, Request, Response, SlaveRequest};
+use tokio_modbus::prelude::{ConformityLevel, DeviceIdObject, ReadCode, ReadDeviceIdentificationResponse};
+use tokio_serial::{SerialPort, SerialStream};
+use tokio_modbus::server::tcp::{accept_tcp_connection, Server as ModbusServer};
+
+use zero_modbus::interface::Interface;
+use zero_modbus::modbus::{GetOrSet, ItemOptions, RequestItem};
+use zero_modbus::server::{Server, ServerOptions};
+
+
+pub const BANK_SIZE: usize = 200; // addresses at or above answer IllegalDataAddress
+pub const IDENTIFICATION_OBJECTS_PER_ANSWER: usize = 2; // more_follows is set past this, tcp only
+
+// device misbehaviour the tests switch on, all off by default
+#[derive(Clone, Default)]
+pub struct MockQuirks {
+    pub stall: Option<Duration>, // delay before every answer
+    pub mute: bool, // requests are swallowed without an answer, rtu only
+    pub absent_slaves: Vec<u8>, // slave ids that never answer, rtu only
+    pub read_delta: i16, // registers added to (or removed from) every register read answer
+    pub coil_echo: Option<u16>, // raw value echoed for FC05 instead of 0xFF00/0x0000
+    pub min_read_quantity: u16, // register reads below this answer IllegalDataValue
+    pub reject_writes: bool, // register writes answer IllegalDataValue and change nothing
+}
+
+pub struct Bank {
+    pub coils: Vec<bool>,
+    pub discrete_inputs: Vec<bool>,
+    pub holding_registers: Vec<u16>,
+    pub input_registers: Vec<u16>,
+    pub quirks: MockQuirks,
+    pub requests: Vec<(u8, String)>, // (slave id, request) in arrival order
+    pub arrivals: Vec<Instant>, // when each of the requests came in
+    pub identification: Vec<(u8, String)>, // basic FC43 objects, none answers IllegalFunction
+    pub connections: usize, // tcp connections accepted
+}
+
+fn span(address: u16, quantity: usize) -> Result<std::ops::Range<usize>, ExceptionCode> {
+
+    let start = address as usize;
+    match start + quantity <= BANK_SIZE {
+        true => Ok(start..start + quantity),
+        false => Err(ExceptionCode::IllegalDataAddress),
+    }
+
+}
+
+impl Bank {
+
+    fn answer(&mut self, slave: u8, request: Request<'static>) -> Result<Response, ExceptionCode> {
+
+        self.requests.push((slave, format!("{:?}", request)));
+        self.arrivals.push(Instant::now());
+        let quirks = self.quirks.clone();
+        let fit = |mut words: Vec<u16>| {
+            match quirks.read_delta {
+                delta if delta < 0 => words.truncate(words.len().saturating_sub(delta.unsigned_abs() as usize)),
+                delta => words.extend(std::iter::repeat_n(0, delta as usize)),
+            }
+            words
+        };
+        match request {
+            Request::ReadCoils(address, quantity) => {
+                Ok(Response::ReadCoils(self.coils[span(address, quantity as usize)?].to_vec()))
+            },
+            Request::ReadDiscreteInputs(address, quantity) => {
+                Ok(Response::ReadDiscreteInputs(self.discrete_inputs[span(address, quantity as usize)?].to_vec()))
+            },
+            Request::ReadHoldingRegisters(_, quantity) | Request::ReadInputRegisters(_, quantity) if quantity < quirks.min_read_quantity => {
+                Err(ExceptionCode::IllegalDataValue)
+            },
+            Request::WriteSingleRegister(..) | Request::WriteMultipleRegisters(..) if quirks.reject_writes => {
+                Err(ExceptionCode::IllegalDataValue)
+            },
+            Request::ReadHoldingRegisters(address, quantity) => {
+                Ok(Response::ReadHoldingRegisters(fit(self.holding_registers[span(address, quantity as usize)?].to_vec())))
+            },
+            Request::ReadInputRegisters(address, quantity) => {
+                Ok(Response::ReadInputRegisters(fit(self.input_registers[span(address, quantity as usize)?].to_vec())))
+            },
+            Request::WriteSingleCoil(address, coil) => {
+                self.coils[span(address, 1)?.start] = coil;
+                match quirks.coil_echo {
+                    // a custom response is encoded as is, the codec would only write 0xFF00 or 0x0000
+                    Some(echo) => Ok(Response::Custom(0x05, [address.to_be_bytes(), echo.to_be_bytes()].concat().into())),
+                    None => Ok(Response::WriteSingleCoil(address, coil)),
+                }
+            },
+            Request::WriteMultipleCoils(address, coils) => {
+                let range = span(address, coils.len())?;
+                self.coils[range].copy_from_slice(&coils);
+                Ok(Response::WriteMultipleCoils(address, coils.len() as u16))
+            },
+            Request::WriteSingleRegister(address, word) => {
+                self.holding_registers[span(address, 1)?.start] = word;
+                Ok(Response::WriteSingleRegister(address, word))
+            },
+            Request::WriteMultipleRegisters(address, words) => {
+                let range = span(address, words.len())?;
+                self.holding_registers[range].copy_from_slice(&words);
+                Ok(Response::WriteMultipleRegisters(address, words.len() as u16))
+            },
+            Request::ReadDeviceIdentification(ReadCode::Basic, object_id) if !self.identification.is_empty() => {
+                let objects: Vec<&(u8, String)> = self.identification.iter().filter(|(id, _)| *id >= object_id).collect();
+                let (answered, rest) = objects.split_at(objects.len().min(IDENTIFICATION_OBJECTS_PER_ANSWER));
+                Ok(Response::ReadDeviceIdentification(ReadDeviceIdentificationResponse {
+                    read_code: ReadCode::Basic,
+                    conformity_level: ConformityLevel::BasicIdentificationStreamOnly,
+                    more_follows: !rest.is_empty(),
+                    next_object_id: rest.first().map_or(0, |(id, _)| *id),
+                    device_id_objects: answered.iter()
+                        .map(|(id, value)| DeviceIdObject { id: *id, value: value.clone().into_bytes().into() })
+                        .collect(),
+                }))
+            },
+            _ => Err(ExceptionCode::IllegalFunction),
+        }
+
+    }
+
+}
+
+#[derive(Clone)]
+struct BankService {
+    bank: Arc<Mutex<Bank>>,
+}
+
+impl tokio_modbus::server::Service for BankService {
+
+    type Request = SlaveRequest<'static>;
+    type Response = Response;
+    type Exception = ExceptionCode;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, ExceptionCode>> + Send>>;
+
+    fn call(&self, request: Self::Request) -> Self::Future {
+
+        let (answer, stall) = {
+            let mut bank = self.bank.lock().unwrap();
+            let stall = bank.quirks.stall;
+            (bank.answer(request.slave, request.request), stall)
+        };
+        Box::pin(async move {
+            if let Some(stall) = stall {
+                tokio::time::sleep(stall).await;
+            }
+            answer
+        })
+
+    }
+
+}
+
+// a modbus tcp device on an ephemeral port, backed by an in-memory register bank
+pub struct MockDevice {
+    pub addr: SocketAddr,
+    pub bank: Arc<Mutex<Bank>>,
+}
+
+impl MockDevice {
+
+    pub async fn start() -> MockDevice {
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let bank = Arc::new(Mutex::new(Bank {
+            coils: vec![false; BANK_SIZE],
+            discrete_inputs: vec![false; BANK_SIZE],
+            holding_registers: vec![0; BANK_SIZE],
+            input_registers: vec![0; BANK_SIZE],
+            quirks: MockQuirks::default(),
+            requests: Vec::new(),
+            arrivals: Vec::new(),
+            identification: Vec::new(),
+            connections: 0,
+        }));
+        let service = BankService { bank: bank.clone() };
+        tokio::spawn(async move {
+            let on_connected = move |stream, socket_addr| {
+                let service = service.clone();
+                service.bank.lock().unwrap().connections += 1;
+                async move { accept_tcp_connection(stream, socket_addr, move |_| Ok(Some(service.clone()))) }
+            };
+            let _ = ModbusServer::new(listener).serve(&on_connected, |_| {}).await;
+        });
+
+        MockDevice { addr, bank }
+
+    }
+
+    pub fn set_quirks(&self, quirks: MockQuirks) {
+
+        self.bank.lock().unwrap().quirks = quirks;
+
+    }
+
+    pub fn holding_registers(&self, address: usize, count: usize) -> Vec<u16> {
+
+        self.bank.lock().unwrap().holding_registers[address..address + count].to_vec()
+
+    }
+
+    pub fn set_holding_registers(&self, address: usize, words: &[u16]) {
+
+        self.bank.lock().unwrap().holding_registers[address..address + words.len()].copy_from_slice(words);
+
+    }
+
+    pub fn set_input_registers(&self, address: usize, words: &[u16]) {
+
+        self.bank.lock().unwrap().input_registers[address..address + words.len()].copy_from_slice(words);
+
+    }
+
+    pub fn coil(&self, address: usize) -> bool {
+
+        self.bank.lock().unwrap().coils[address]
+
+    }
+
+    pub fn set_discrete_input(&self, address: usize, state: bool) {
+
+        self.bank.lock().unwrap().discrete_inputs[address] = state;
+
+    }
+
+    pub fn requests(&self) -> Vec<(u8, String)> {
+
+        self.bank.lock().unwrap().requests.clone()
+
+    }
+
+    pub fn set_identification(&self, objects: &[(u8, &str)]) {
+
+        self.bank.lock().unwrap().identification = objects.iter().map(|(id, value)| (*id, value.to_string())).collect();
+
+    }
+
+    pub fn arrivals(&self) -> Vec<Instant> {
+
+        self.bank.lock().unwrap().arrivals.clone()
+
+    }
+
+    pub fn connections(&self) -> usize {
+
+        self.bank.lock().unwrap().connections
+
+    }
+
+    // the interface yaml with its connection pointed at this device
+    pub fn yaml(&self, slaves: &str) -> String {
+
+        format!("protocol: tcp\naddress: 127.0.0.1\ntcp_port: {}\n{}", self.addr.port(), slaves)
+
+    }
+
+    pub fn interface(&self, slaves: &str) -> Interface {
+
+        Interface::from_bytes(self.yaml(slaves).as_bytes(), "yaml")
+            .unwrap_or_else(|e| panic!("Fixture does not load: {}", e))
+
+    }
+
+    // a fixture file from tests/fixtures, its tcp_port replaced by the one of this device
+    pub fn fixture(&self, name: &str) -> Interface {
+
+        let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+        let text = fs::read_to_string(&path).unwrap_or_else(|e| panic!("Failed to read '{}': {}", path, e));
+        let text: String = text.lines()
+            .map(|line| match line.starts_with("tcp_port:") {
+                true => format!("tcp_port: {}\n", self.addr.port()),
+                false => format!("{}\n", line),
+            })
+            .collect();
+        Interface::from_bytes(text.as_bytes(), "yaml")
+            .unwrap_or_else(|e| panic!("Fixture '{}' does not load: {}", name, e))
+
+    }
+
+}
+
+fn crc16(frame: &[u8]) -> u16 {
+
+    let mut crc: u16 = 0xFFFF;
+    for byte in frame {
+        crc ^= *byte as u16;
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xA001,
+                _ => crc >> 1,
+            };
+        }
+    }
+
+    crc
+
+}
+
+// length of the request frame at the start of the buffer, None until it can be told
+fn rtu_frame_size(buffer: &[u8]) -> Option<usize> {
+
+    match *buffer.get(1)? {
+        0x01..=0x06 => Some(8),
+        0x0F | 0x10 => Some(9 + *buffer.get(6)? as usize),
+        _ => Some(buffer.len()), // unknown function, dropped whole
+    }
+
+}
+
+fn rtu_request(pdu: &[u8]) -> Option<Request<'static>> {
+
+    let word = |index: usize| u16::from_be_bytes([pdu[index], pdu[index + 1]]);
+    Some(match pdu[0] {
+        0x01 => Request::ReadCoils(word(1), word(3)),
+        0x02 => Request::ReadDiscreteInputs(word(1), word(3)),
+        0x03 => Request::ReadHoldingRegisters(word(1), word(3)),
+        0x04 => Request::ReadInputRegisters(word(1), word(3)),
+        0x05 => Request::WriteSingleCoil(word(1), word(3) == 0xFF00),
+        0x06 => Request::WriteSingleRegister(word(1), word(3)),
+        0x0F => Request::WriteMultipleCoils(word(1), Cow::Owned((0..word(3) as usize).map(|bit| pdu[6 + bit / 8] & (1 << (bit % 8)) != 0).collect())),
+        0x10 => Request::WriteMultipleRegisters(word(1), Cow::Owned((0..word(3) as usize).map(|index| word(6 + index * 2)).collect())),
+        _ => return None,
+    })
+
+}
+
+fn rtu_response(function: u8, answer: Result<Response, ExceptionCode>) -> Vec<u8> {
+
+    let bits = |bits: Vec<bool>| {
+        let mut bytes = vec![0u8; bits.len().div_ceil(8)];
+        for (index, bit) in bits.iter().enumerate() {
+            bytes[index / 8] |= (*bit as u8) << (index % 8);
+        }
+        bytes
+    };
+    let mut pdu = vec![function];
+    match answer {
+        Ok(Response::ReadCoils(coils) | Response::ReadDiscreteInputs(coils)) => {
+            let bytes = bits(coils);
+            pdu.push(bytes.len() as u8);
+            pdu.extend(bytes);
+        },
+        Ok(Response::ReadHoldingRegisters(words) | Response::ReadInputRegisters(words)) => {
+            pdu.push((words.len() * 2) as u8);
+            pdu.extend(words.iter().flat_map(|word| word.to_be_bytes()));
+        },
+        Ok(Response::WriteSingleCoil(address, coil)) => {
+            pdu.extend(address.to_be_bytes());
+            pdu.extend(if coil { [0xFF, 0x00] } else { [0x00, 0x00] });
+        },
+        Ok(Response::Custom(_, data)) => pdu.extend(data),
+        Ok(Response::WriteSingleRegister(address, word) | Response::WriteMultipleCoils(address, word) | Response::WriteMultipleRegisters(address, word)) => {
+            pdu.extend(address.to_be_bytes());
+            pdu.extend(word.to_be_bytes());
+        },
+        Ok(_) | Err(_) => {
+            let code = answer.err().unwrap_or(ExceptionCode::IllegalFunction);
+            pdu = vec![function | 0x80, u8::from(code)];
+        },
+    }
+
+    pdu
+
+}
+
+// a modbus rtu device on the far end of a pty, the interface opens the pty by path like a serial port
+pub struct MockRtuDevice {
+    pub path: String,
+    pub bank: Arc<Mutex<Bank>>,
+}
+
+impl MockRtuDevice {
+
+    pub async fn start() -> MockRtuDevice {
+
+        let (mut master, slave) = SerialStream::pair().expect("Failed to open a pty pair");
+        let path = slave.name().expect("pty without a name");
+        drop(slave); // the device under test opens it like any serial port
+        let bank = Arc::new(Mutex::new(Bank {
+            coils: vec![false; BANK_SIZE],
+            discrete_inputs: vec![false; BANK_SIZE],
+            holding_registers: vec![0; BANK_SIZE],
+            input_registers: vec![0; BANK_SIZE],
+            quirks: MockQuirks::default(),
+            requests: Vec::new(),
+            arrivals: Vec::new(),
+            identification: Vec::new(),
+            connections: 0,
+        }));
+        let device_bank = bank.clone();
+        tokio::spawn(async move {
+            let mut buffer = Vec::new();
+            let mut chunk = [0u8; 256];
+            loop {
+                match master.read(&mut chunk).await {
+                    Ok(size) if size > 0 => buffer.extend_from_slice(&chunk[..size]),
+                    // nobody holds the pty open while a session is reopened
+                    _ => {
+                        buffer.clear();
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                        continue;
+                    },
+                }
+                while let Some(size) = rtu_frame_size(&buffer).filter(|size| *size <= buffer.len()) {
+                    let frame: Vec<u8> = buffer.drain(..size).collect();
+                    if frame.len() < 4 || crc16(&frame[..size - 2]).to_le_bytes() != frame[size - 2..] {
+                        continue;
+                    }
+                    let (slave, pdu) = (frame[0], &frame[1..size - 2]);
+                    let request = match rtu_request(pdu) {
+                        Some(request) => request,
+                        None => continue,
+                    };
+                    let (answer, quirks) = {
+                        let mut bank = device_bank.lock().unwrap();
+                        (bank.answer(slave, request), bank.quirks.clone())
+                    };
+                    if quirks.mute || quirks.absent_slaves.contains(&slave) {
+                        continue;
+                    }
+                    if let Some(stall) = quirks.stall {
+                        tokio::time::sleep(stall).await;
+                    }
+                    let mut response = vec![slave];
+                    response.extend(rtu_response(pdu[0], answer));
+                    response.extend(crc16(&response).to_le_bytes());
+                    let _ = master.write_all(&response).await;
+                }
+            }
+        });
+
+        MockRtuDevice { path, bank }
+
+    }
+
+    pub fn set_quirks(&self, quirks: MockQuirks) {
+
+        self.bank.lock().unwrap().quirks = quirks;
+
+    }
+
+    pub fn set_holding_registers(&self, address: usize, words: &[u16]) {
+
+        self.bank.lock().unwrap().holding_registers[address..address + words.len()].copy_from_slice(words);
+
+    }
+
+    pub fn requests(&self) -> Vec<(u8, String)> {
+
+        self.bank.lock().unwrap().requests.clone()
+
+    }
+
+    // the interface yaml with its serial port pointed at this device
+    pub fn interface(&self, settings: &str) -> Interface {
+
+        let yaml = format!("protocol: rtu\naddress: {}\nbaudrate: 9600\n{}", self.path, settings);
+        Interface::from_bytes(yaml.as_bytes(), "yaml")
+            .unwrap_or_else(|e| panic!("Fixture does not load: {}", e))
+
+    }
+
+}
+
+pub fn get(slave: &str, name: &str) -> RequestItem {
+
+    RequestItem {
+        slave: String::from(slave),
+        name: String::from(name),
+        op: GetOrSet::Get,
+        value: None,
+        options: ItemOptions::default(),
+    }
+
+}
+
+pub fn set(slave: &str, name: &str, value: Value) -> RequestItem {
+
+    RequestItem {
+        slave: String::from(slave),
+        name: String::from(name),
+        op: GetOrSet::Set,
+        value: Some(value),
+        options: ItemOptions::default(),
+    }
+
+}
+
+// the full server behind an in-process REQ socket, one request served per call
+pub struct Gateway {
+    pub server: Server,
+    pub devices: HashMap<String, Interface>,
+    _context: zmq::Context,
+    client: zmq::Socket,
+}
+
+impl Gateway {
+
+    pub fn new(devices: HashMap<String, Interface>, options: ServerOptions) -> Gateway {
+
+        let server = Server::new("tcp://127.0.0.1:*", options);
+        let context = zmq::Context::new();
+        let client = context.socket(zmq::REQ).unwrap();
+        client.set_rcvtimeo(10000).unwrap();
+        client.connect(&server.endpoint()).unwrap();
+
+        Gateway { server, devices, _context: context, client }
+
+    }
+
+    pub async fn request(&mut self, request: Value) -> Value {
+
+        let response = self.request_text(request).await;
+        serde_json::from_str(&response).unwrap_or_else(|e| panic!("Response is no JSON ({}): {}", e, response))
+
+    }
+
+    // the response as sent, for checks on its layout
+    pub async fn request_text(&mut self, request: Value) -> String {
+
+        self.client.send(request.to_string().as_str(), 0).unwrap();
+        assert!(self.server.serve_one(&mut self.devices).await, "server stopped");
+        self.client.recv_string(0).unwrap().unwrap()
+
+    }
+
+}
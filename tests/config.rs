@@ -0,0 +1,472 @@
+use serde_yaml::Value;
+
+use zero_modbus::interface::{diff_device, BlockType, ConfigError, ConfigWarning, Interface, ModbusProtocol, RequestFunction, ValueType};
+
+
+fn load(slaves: &str) -> Result<Interface, ConfigError> {
+
+    let yaml = format!("protocol: tcp\naddress: 127.0.0.1\ntcp_port: 502\n{}", slaves);
+    Interface::from_bytes(yaml.as_bytes(), "yaml")
+
+}
+
+#[test]
+fn point_names_collide_after_trimming() {
+
+    let error = load("
+slaves:
+- meter:
+    id: 1
+    hr:
+    - Power: {addr: 0, type: u16}
+    ir:
+    - 'Power ': {addr: 1, type: u16}
+").err().map(|e| e.to_string()).expect("the duplicate was loaded");
+    assert!(error.contains("'Power' is defined more than once"), "{}", error);
+
+}
+
+#[test]
+fn point_names_are_trimmed() {
+
+    let interface = load("
+slaves:
+- meter:
+    id: 1
+    hr:
+    - ' Power ': {addr: 0, type: u16}
+").unwrap_or_else(|e| panic!("{}", e));
+    assert!(interface.resolve_slave("meter").unwrap().0.find("Power").is_some());
+
+}
+
+#[test]
+fn embedded_config_loads_the_same_from_every_format() {
+
+    let load_embedded = |data: &[u8], format_hint: &str| Interface::from_bytes(data, format_hint)
+        .unwrap_or_else(|e| panic!("embedded {} does not load: {}", format_hint, e))
+        .to_yaml();
+    let yaml = load_embedded(include_bytes!("fixtures/embedded.yaml"), "yaml");
+    assert_eq!(load_embedded(include_bytes!("fixtures/embedded.json"), "json"), yaml);
+    assert_eq!(load_embedded(include_bytes!("fixtures/embedded.toml"), "TOML"), yaml);
+
+    let error = Interface::from_bytes(include_bytes!("fixtures/embedded.yaml"), "ini").err().map(|e| e.to_string()).expect("an unknown format loaded");
+    assert!(error.contains("ini"), "{}", error);
+
+}
+
+#[test]
+fn merged_template_points_are_loaded_per_slave() {
+
+    let interface = fixture("template_interface.yaml");
+    assert_eq!(interface.slave_names(), vec!["tcp_slave_1", "tcp_slave_2"]);
+    for (slave_name, unit) in [("tcp_slave_1", 0), ("tcp_slave_2", 1)] {
+        let (slave, slave_id) = interface.resolve_slave(slave_name).unwrap_or_else(|| panic!("no slave '{}'", slave_name));
+        assert_eq!(slave_id, unit, "{}", slave_name);
+        for (name, block_type, address, value_type) in [
+            ("status_value", BlockType::Co, 0, ValueType::Bool),
+            ("holding_value", BlockType::Hr, 2, ValueType::I16),
+            ("rdonly_value", BlockType::Ir, 3, ValueType::F32),
+        ] {
+            let modbus_data = slave.find(name).unwrap_or_else(|| panic!("'{}' of {} was not merged", name, slave_name));
+            assert!(modbus_data.block_type() == block_type && modbus_data.value_type() == value_type, "{}/{}", slave_name, name);
+            assert_eq!(modbus_data.address(), address, "{}/{}", slave_name, name);
+        }
+    }
+    // keys next to the merge key are kept
+    assert!(interface.resolve_slave("tcp_slave_1").unwrap().0.find("input_status").is_none());
+    assert!(interface.resolve_slave("tcp_slave_2").unwrap().0.find("input_status").is_some());
+
+}
+
+fn fixture(name: &str) -> Interface {
+
+    let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+    let text = std::fs::read(&path).unwrap_or_else(|e| panic!("Failed to read '{}': {}", path, e));
+    Interface::from_bytes(&text, "yaml").unwrap_or_else(|e| panic!("Fixture '{}' does not load: {}", name, e))
+
+}
+
+// the dump of a loaded interface loads back into the same interface
+fn assert_round_trip(name: &str) -> Value {
+
+    let dumped = fixture(name).to_yaml();
+    let reloaded = Interface::from_bytes(serde_yaml::to_string(&dumped).unwrap().as_bytes(), "yaml")
+        .unwrap_or_else(|e| panic!("Dump of '{}' does not load: {}", name, e));
+    assert_eq!(reloaded.to_yaml(), dumped, "{}", name);
+
+    dumped
+
+}
+
+#[test]
+fn every_interface_option_round_trips() {
+
+    let dumped = assert_round_trip("all_options_rtu.yaml");
+    for key in [
+        "flow_control", "interframe_delay_ms", "watchdog", "connect", "writes", "lenient_coil_echo",
+        "lenient_width", "strict", "max_batch", "auto_save", "audit_log", "byte_order", "word_order",
+    ] {
+        assert!(dumped.get(key).is_some(), "'{}' is not dumped", key);
+    }
+    assert_eq!(dumped["watchdog"]["window_s"], Value::from(30));
+
+    let dumped = assert_round_trip("all_options_tcp.yaml");
+    assert_eq!(dumped["connect_timeout_ms"], Value::from(250));
+
+}
+
+#[test]
+fn every_slave_and_point_option_round_trips() {
+
+    let dumped = assert_round_trip("all_options_rtu.yaml");
+    let slaves = dumped["slaves"].as_sequence().unwrap();
+    let boiler = &slaves[0]["boiler"];
+    assert_eq!((&boiler["wakeup"], &boiler["wakeup_register"], &boiler["min_read_quantity"]),
+        (&Value::from(true), &Value::from(9), &Value::from(2)));
+    assert_eq!(slaves[1]["heaters"]["id_range"], Value::from("10-12"));
+
+    let point = |block: &str, name: &str| boiler[block].as_sequence().unwrap().iter()
+        .find_map(|point| point.get(name).cloned())
+        .unwrap_or_else(|| panic!("'{}' is not dumped", name));
+    for (block, name, key) in [
+        ("co", "burner", "pulse_ms"), ("co", "burner", "invert"), ("co", "pumps", "count"),
+        ("di", "flame", "active_low"),
+        ("hr", "setpoint", "func"), ("hr", "setpoint", "description"), ("hr", "setpoint", "unit"),
+        ("hr", "setpoint", "poll_priority"), ("hr", "setpoint", "alias"),
+        ("hr", "total", "words"), ("hr", "total", "byte_order"),
+        ("hr", "status", "flags"), ("hr", "status_view", "view"),
+        ("hr", "checked", "shadow_register"), ("hr", "checked", "shadow_formula"), ("hr", "checked", "word_order"),
+        ("hr", "level", "cal_table"),
+    ] {
+        assert!(point(block, name).get(key).is_some(), "'{}' of '{}' is not dumped", key, name);
+    }
+
+}
+
+#[test]
+fn point_key_order_does_not_matter() {
+
+    let ordered = load("
+slaves:
+- meter:
+    id: 1
+    hr:
+    - level: {addr: 10, type: f32}
+").unwrap_or_else(|e| panic!("{}", e));
+    let shuffled = load("
+slaves:
+- meter:
+    id: 1
+    hr:
+    - level: {type: f32, addr: 10}
+").unwrap_or_else(|e| panic!("{}", e));
+    assert_eq!(shuffled.to_yaml(), ordered.to_yaml());
+
+}
+
+#[test]
+fn compact_example_matches_block_example() {
+
+    let example = |name: &str| {
+        let path = format!("{}/examples/{}", env!("CARGO_MANIFEST_DIR"), name);
+        let text = std::fs::read(&path).unwrap_or_else(|e| panic!("Failed to read '{}': {}", path, e));
+        Interface::from_bytes(&text, "yaml").unwrap_or_else(|e| panic!("Example '{}' does not load: {}", name, e))
+    };
+    assert_eq!(example("example_compact_interface.yaml").to_yaml(), example("example_tcp_interface.yaml").to_yaml());
+
+}
+
+#[test]
+fn func_single_is_checked_against_the_point_width() {
+
+    let widths = [("bool", 1), ("u16", 1), ("i16", 1), ("bcd16", 1), ("u32", 2), ("i32", 2), ("f32", 2), ("bcd32", 2)];
+    for (func, requested) in [("single", RequestFunction::Single), ("multiple", RequestFunction::Multiple)] {
+        for (value_type, width) in widths {
+            for strict in [false, true] {
+                let loaded = load(&format!("
+strict: {}
+slaves:
+- meter:
+    id: 1
+    hr:
+    - level: {{addr: 0, type: {}, func: {}}}
+", strict, value_type, func));
+                let case = format!("func {} on {} with strict {}", func, value_type, strict);
+                match (requested, width, strict) {
+                    (RequestFunction::Single, 2, true) => {
+                        let error = loaded.err().map(|e| e.to_string()).expect(&case);
+                        assert!(error.contains("'level' uses func single"), "{}: {}", case, error);
+                    },
+                    _ => {
+                        let interface = loaded.unwrap_or_else(|e| panic!("{}: {}", case, e));
+                        let point = interface.resolve_slave("meter").unwrap().0.find("level").unwrap();
+                        // a multi-register point is upgraded to a multiple write
+                        let expected = if width == 2 { RequestFunction::Multiple } else { requested };
+                        assert!(point.requestfunction() == expected, "{}", case);
+                    },
+                }
+            }
+        }
+    }
+
+}
+
+#[test]
+fn func_single_is_kept_on_scattered_points() {
+
+    for strict in [false, true] {
+        let interface = load(&format!("
+strict: {}
+slaves:
+- meter:
+    id: 1
+    hr:
+    - total: {{type: u32, words: [4, 2], func: single}}
+", strict)).unwrap_or_else(|e| panic!("{}", e));
+        let point = interface.resolve_slave("meter").unwrap().0.find("total").unwrap();
+        assert!(point.requestfunction() == RequestFunction::Single, "strict {}", strict);
+    }
+
+}
+
+// each interface level line of the fixture dropped in turn, back to its default
+#[test]
+fn interface_settings_are_diffed() {
+
+    let path = format!("{}/tests/fixtures/all_options_rtu.yaml", env!("CARGO_MANIFEST_DIR"));
+    let text = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("Failed to read '{}': {}", path, e));
+    let old = fixture("all_options_rtu.yaml");
+    for (key, reconnect) in [
+        ("flow_control", true),
+        ("interframe_delay_ms", true),
+        ("lenient_coil_echo", true),
+        ("watchdog", false),
+        ("connect", false),
+        ("writes", false),
+        ("lenient_width", false),
+        ("strict", false),
+        ("max_batch", false),
+        ("auto_save", false),
+        ("audit_log", false),
+        ("byte_order", false),
+        ("word_order", false),
+    ] {
+        let edited: String = text.lines()
+            .filter(|line| !line.starts_with(&format!("{}:", key)))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        let new = Interface::from_bytes(edited.as_bytes(), "yaml").unwrap_or_else(|e| panic!("{}: {}", key, e));
+        let diff = diff_device(&old, &new);
+        let (expected_reconnect, expected_settings) = if reconnect { (vec![key], vec![]) } else { (vec![], vec![key]) };
+        assert_eq!((diff.reconnect, diff.settings), (expected_reconnect, expected_settings), "{}", key);
+        assert!(diff.added.is_empty() && diff.removed.is_empty(), "{}", key);
+        // the points that inherit the order change along with it
+        assert!(key.ends_with("_order") || diff.modified.is_empty(), "{}", key);
+    }
+
+}
+
+#[test]
+fn connect_timeout_is_diffed() {
+
+    let old = load("slaves: []").unwrap_or_else(|e| panic!("{}", e));
+    let new = load("connect_timeout_ms: 500\nslaves: []").unwrap_or_else(|e| panic!("{}", e));
+    let diff = diff_device(&old, &new);
+    assert_eq!(diff.settings, vec!["connect_timeout_ms"]);
+    assert!(diff_device(&old, &old).is_empty());
+
+}
+
+#[test]
+fn points_and_slaves_are_diffed() {
+
+    let old = load("
+slaves:
+- meter:
+    id: 1
+    hr:
+    - level: {addr: 0, type: u16}
+    - total: {addr: 1, type: u32}
+").unwrap_or_else(|e| panic!("{}", e));
+    let new = load("
+slaves:
+- meter:
+    id: 2
+    hr:
+    - level: {addr: 4, type: u16}
+    - power: {addr: 1, type: f32}
+").unwrap_or_else(|e| panic!("{}", e));
+    let diff = diff_device(&old, &new);
+    assert_eq!(diff.added, vec!["meter/power"]);
+    assert_eq!(diff.removed, vec!["meter/total"]);
+    assert_eq!(diff.modified.get("meter"), Some(&vec!["id"]));
+    assert_eq!(diff.modified.get("meter/level"), Some(&vec!["addr"]));
+    assert!(diff.reconnect.is_empty() && diff.settings.is_empty());
+
+}
+
+#[test]
+fn update_slave_refuses_another_unit() {
+
+    let parametric = |id_range: &str| load(&format!("
+slaves:
+- heater:
+    id_range: {}
+    hr:
+    - level: {{addr: 0, type: u16}}
+", id_range)).unwrap_or_else(|e| panic!("{}", e));
+
+    let mut interface = parametric("1-8");
+    let slave = parametric("1-4").slaves["heater"].clone();
+    let error = interface.update_slave("heater", slave).err().map(|e| e.to_string()).expect("the range was changed");
+    assert_eq!(error, "SlaveIdChanged: 'heater' from 1-8 to 1-4");
+
+    let slave = load("slaves:\n- heater:\n    id: 1\n").unwrap_or_else(|e| panic!("{}", e)).slaves["heater"].clone();
+    let error = interface.update_slave("heater", slave).err().map(|e| e.to_string()).expect("the range was dropped");
+    assert_eq!(error, "SlaveIdChanged: 'heater' from 1-8 to 1");
+
+    let slave = parametric("1-8").slaves["heater"].clone();
+    assert!(interface.update_slave("heater", slave).is_ok());
+
+}
+
+#[test]
+fn uri_is_parsed_into_the_connection() {
+
+    for (uri, protocol, address, config) in [
+        ("modbus+tcp://192.168.1.100:1502", ModbusProtocol::Tcp, "192.168.1.100", 1502),
+        ("modbus+tcp://192.168.1.100", ModbusProtocol::Tcp, "192.168.1.100", 502),
+        ("modbus+tcp://[::1]:1502", ModbusProtocol::Tcp, "::1", 1502),
+        ("modbus+tcp://[fe80::1]", ModbusProtocol::Tcp, "fe80::1", 502),
+        ("modbus+rtu:///dev/ttyUSB0?baudrate=9600&parity=none", ModbusProtocol::Rtu, "/dev/ttyUSB0", 9600),
+    ] {
+        let interface = Interface::from_uri(uri).unwrap_or_else(|e| panic!("{}: {}", uri, e));
+        assert!(interface.modbusprotocol() == protocol, "{}", uri);
+        assert_eq!((interface.address().as_str(), interface.config()), (address, config), "{}", uri);
+    }
+
+}
+
+#[test]
+fn malformed_uri_names_the_part() {
+
+    for (uri, expected) in [
+        ("modbus+rtu_over_tcp://gateway.local:10502", "Invaild value of 'scheme': 'modbus+rtu_over_tcp'"),
+        ("modbus+tcp://::1:502", "Invaild value of 'address': '::1:502'"),
+        ("modbus+tcp://[::1", "Invaild value of 'address': '[::1'"),
+        ("modbus+tcp://[::1]502", "Invaild value of 'address': '[::1]502'"),
+        ("modbus+tcp://[::1]:port", "Invaild value of 'tcp_port': 'port'"),
+        ("modbus+rtu:///dev/ttyUSB0?baudrate=9600&parity=even", "Invaild value of 'parity': 'even'"),
+    ] {
+        let error = Interface::from_uri(uri).err().map(|e| e.to_string()).unwrap_or_else(|| panic!("{} was loaded", uri));
+        assert!(error.contains(expected), "{}: {}", uri, error);
+    }
+
+}
+
+#[test]
+fn registers_are_listed_by_priority() {
+
+    let interface = load("
+slaves:
+- meter:
+    id: 1
+    hr:
+    - energy: {addr: 0, type: u32}
+    - level: {addr: 4, type: u16, poll_priority: 10}
+    co:
+    - estop: {addr: 7, poll_priority: 255}
+    - lamp: {addr: 2}
+").unwrap_or_else(|e| panic!("{}", e));
+    let names: Vec<&str> = interface.registers_by_priority().into_iter().map(|(_, name, _)| name).collect();
+    // equal priorities go by block, then address
+    assert_eq!(names, vec!["estop", "level", "lamp", "energy"]);
+
+}
+
+#[test]
+fn prometheus_metadata_lists_described_points() {
+
+    let interface = load("
+slaves:
+- meter:
+    id: 1
+    hr:
+    - flow.rate: {addr: 0, type: u16, description: Flow in l/min}
+    - level: {addr: 1, type: u16}
+    co:
+    - pump-1: {addr: 0, description: \"Pump\\\\one\"}
+- spare:
+    id: 2
+    hr:
+    - flow.rate: {addr: 0, type: u16, description: Spare flow}
+").unwrap_or_else(|e| panic!("{}", e));
+    // names are sanitized, undescribed points are left out, a name on two slaves is listed once
+    assert_eq!(interface.export_prometheus_metadata(), [
+        "# HELP modbus_register_value_flow_rate Flow in l/min",
+        "# TYPE modbus_register_value_flow_rate gauge",
+        "# HELP modbus_register_value_pump_1 Pump\\\\one",
+        "# TYPE modbus_register_value_pump_1 gauge",
+    ].join("\n"));
+
+}
+
+#[test]
+fn dotenv_keys_are_upper_case_paths() {
+
+    let interface = load("
+slaves:
+- main-meter:
+    id: 1
+    hr:
+    - flow.rate: {addr: 0, type: u16, unit: l/min}
+    co:
+    - pump: {addr: 3, alias: [main pump, p1]}
+").unwrap_or_else(|e| panic!("{}", e));
+    // names are upper cased with every other character replaced, lists are joined with commas
+    assert_eq!(interface.to_dotenv("my-plant"), [
+        "MY_PLANT_PROTOCOL=tcp",
+        "MY_PLANT_ADDRESS=127.0.0.1",
+        "MY_PLANT_TCP_PORT=502",
+        "MY_PLANT_SLAVES_MAIN_METER_ID=1",
+        "MY_PLANT_SLAVES_MAIN_METER_CO_PUMP_ADDR=3",
+        "MY_PLANT_SLAVES_MAIN_METER_CO_PUMP_TYPE=bool",
+        "MY_PLANT_SLAVES_MAIN_METER_CO_PUMP_FUNC=multiple",
+        "MY_PLANT_SLAVES_MAIN_METER_CO_PUMP_ALIAS=main pump,p1",
+        "MY_PLANT_SLAVES_MAIN_METER_HR_FLOW_RATE_ADDR=0",
+        "MY_PLANT_SLAVES_MAIN_METER_HR_FLOW_RATE_TYPE=u16",
+        "MY_PLANT_SLAVES_MAIN_METER_HR_FLOW_RATE_FUNC=multiple",
+        "MY_PLANT_SLAVES_MAIN_METER_HR_FLOW_RATE_UNIT=l/min",
+        "",
+    ].join("\n"));
+
+}
+
+#[test]
+fn bit_blocks_of_other_types_warn_or_fail_when_strict() {
+
+    let slaves = "
+slaves:
+- meter:
+    id: 1
+    co:
+    - relay: {addr: 0}
+    - speed: {addr: 1, type: u16}
+    di:
+    - level: {addr: 0, type: f32}
+    hr:
+    - setpoint: {addr: 0, type: f32}
+";
+    let interface = load(slaves).unwrap_or_else(|e| panic!("{}", e));
+    let warnings = interface.resolve_slave("meter").unwrap().0.validate();
+    let expected = [
+        ConfigWarning::TypeMismatch { name: String::from("speed"), block_type: BlockType::Co, value_type: ValueType::U16 },
+        ConfigWarning::TypeMismatch { name: String::from("level"), block_type: BlockType::Di, value_type: ValueType::F32 },
+    ];
+    assert!(warnings == expected, "{}", warnings.iter().map(|warning| warning.to_string()).collect::<Vec<_>>().join(", "));
+
+    let error = load(&format!("strict: true\n{}", slaves)).err().expect("the mismatch was loaded");
+    assert!(matches!(&error, ConfigError::TypeMismatch { name, block_type: BlockType::Co, value_type: ValueType::U16 } if name == "speed"), "{}", error);
+
+}
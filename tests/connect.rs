@@ -0,0 +1,89 @@
+mod common;
+
+use std::time::{Duration, Instant};
+use tokio::net::{TcpSocket, TcpStream};
+use serde_json::json;
+
+use zero_modbus::interface::Interface;
+use zero_modbus::modbus;
+use common::{get, MockDevice};
+
+
+const SLAVES: &str = "
+connect: eager
+slaves:
+- meter:
+    id: 1
+    hr:
+    - level: {addr: 0, type: u16}
+";
+
+#[tokio::test]
+async fn eager_session_is_used_by_the_first_request() {
+
+    let device = MockDevice::start().await;
+    device.set_holding_registers(0, &[7]);
+    let interface = device.interface(SLAVES);
+
+    modbus::connect_eager("eager", &interface).await.unwrap_or_else(|e| panic!("no connection: {}", e));
+    assert_eq!(modbus::connection_state("eager"), Some(true));
+    let results = modbus::batch_request(String::from("eager"), interface.clone(), vec![get("meter", "level")]).await
+        .unwrap_or_else(|e| panic!("request failed: {}", e));
+    assert_eq!(results[0].value, json!(7));
+    assert_eq!(device.connections(), 1, "the first request connected again");
+
+    // later requests open their own session as before
+    modbus::batch_request(String::from("eager"), interface, vec![get("meter", "level")]).await
+        .unwrap_or_else(|e| panic!("request failed: {}", e));
+    assert_eq!(device.connections(), 2);
+
+}
+
+#[tokio::test]
+async fn ipv6_uri_connects() {
+
+    let listener = match tokio::net::TcpListener::bind("[::1]:0").await {
+        Ok(listener) => listener,
+        Err(e) => return eprintln!("no IPv6 loopback, skipped: {}", e),
+    };
+    let port = listener.local_addr().unwrap().port();
+    let interface = Interface::from_uri(&format!("modbus+tcp://[::1]:{}", port))
+        .unwrap_or_else(|e| panic!("{}", e));
+    modbus::open_session(&interface).await.unwrap_or_else(|e| panic!("no connection: {}", e));
+
+}
+
+#[tokio::test]
+async fn host_name_uri_is_resolved_on_connect() {
+
+    let device = MockDevice::start().await;
+    let interface = Interface::from_uri(&format!("modbus+tcp://localhost:{}", device.addr.port()))
+        .unwrap_or_else(|e| panic!("{}", e));
+    modbus::open_session(&interface).await.unwrap_or_else(|e| panic!("no connection: {}", e));
+
+    let interface = Interface::from_uri("modbus+tcp://gateway.invalid:502?connect_timeout_ms=1000")
+        .unwrap_or_else(|e| panic!("{}", e));
+    let error = modbus::open_session(&interface).await.err().expect("an unknown host connected");
+    assert!(error.to_string().contains("Failed to resolve \"gateway.invalid\""), "{}", error);
+
+}
+
+#[tokio::test]
+async fn dropped_connect_fails_within_the_connect_timeout() {
+
+    // a listener whose accept queue is full drops further SYNs, as a filtered or unroutable host does;
+    // a real unroutable address is not used, sandboxes and proxies may answer for it
+    let socket = TcpSocket::new_v4().unwrap();
+    socket.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let listener = socket.listen(0).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let _queued = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+
+    let interface = Interface::from_uri(&format!("modbus+tcp://127.0.0.1:{}?connect_timeout_ms=200", port))
+        .unwrap_or_else(|e| panic!("{}", e));
+    let started = Instant::now();
+    let error = modbus::open_session(&interface).await.err().expect("a full accept queue took the connection");
+    assert!(started.elapsed() < Duration::from_millis(1000), "{:?}", started.elapsed());
+    assert!(error.to_string().contains("no answer within 200ms"), "{}", error);
+
+}
@@ -0,0 +1,83 @@
+use zero_modbus::interface::{DeviceSource, Interface, ModbusData};
+
+
+fn csv_path() -> String {
+
+    format!("{}/tests/fixtures/register_map.csv", env!("CARGO_MANIFEST_DIR"))
+
+}
+
+fn connection(settings: &str) -> Interface {
+
+    let yaml = format!("protocol: tcp\naddress: 10.0.0.5\ntcp_port: 1502\n{}slaves: []\n", settings);
+    Interface::from_bytes(yaml.as_bytes(), "yaml").unwrap_or_else(|e| panic!("Connection does not load: {}", e))
+
+}
+
+// block, address and type of a point in one comparable line
+fn layout(modbus_data: &ModbusData) -> String {
+
+    format!("{} {} {}", modbus_data.block_type(), modbus_data.address(), modbus_data.value_type())
+
+}
+
+#[test]
+fn csv_points_are_loaded_onto_the_connection() {
+
+    let interface = Interface::from_csv(&csv_path(), &connection("max_batch: 8\n"))
+        .unwrap_or_else(|e| panic!("Register map does not load: {}", e));
+
+    assert_eq!((interface.address().as_str(), interface.config()), ("10.0.0.5", 1502));
+    assert_eq!(interface.max_batch(), Some(8));
+    let mut slave_names = interface.slave_names();
+    slave_names.sort();
+    assert_eq!(slave_names, vec!["slave_1", "slave_2"]);
+
+    let (slave, slave_id) = interface.resolve_slave("slave_1").unwrap();
+    assert_eq!(slave_id, 1);
+    let setpoint = slave.find("setpoint").unwrap();
+    assert_eq!(layout(&setpoint), "Hr 0 U16");
+    assert_eq!((setpoint.description(), setpoint.unit()), (Some("Target level"), Some("%")));
+    assert_eq!(layout(&slave.find("energy").unwrap()), "Hr 2 U32");
+    assert_eq!(layout(&slave.find("pump").unwrap()), "Co 0 Bool");
+
+    let (slave, slave_id) = interface.resolve_slave("slave_2").unwrap();
+    assert_eq!(slave_id, 2);
+    assert_eq!(layout(&slave.find("temperature").unwrap()), "Ir 5 I16");
+
+}
+
+#[test]
+fn csv_register_map_refuses_auto_save() {
+
+    let error = Interface::from_csv(&csv_path(), &connection("auto_save: true\n"))
+        .err().map(|e| e.to_string()).expect("auto_save was accepted");
+    assert!(error.contains("auto_save"), "{}", error);
+
+}
+
+#[test]
+fn csv_rows_are_checked() {
+
+    let path = std::env::temp_dir().join(format!("zero_modbus_bad_map_{}.csv", std::process::id()));
+    std::fs::write(&path, "slave_id,block_type,name,address,type\n1,xr,level,0,u16\n").unwrap();
+    let error = Interface::from_csv(path.to_str().unwrap(), &connection(""))
+        .err().map(|e| e.to_string()).expect("an unknown block type was accepted");
+    std::fs::remove_file(&path).unwrap();
+    assert!(error.contains("line 2") && error.contains("xr"), "{}", error);
+
+}
+
+#[test]
+fn uri_device_takes_its_points_from_the_register_map() {
+
+    let source = DeviceSource {
+        name: String::from("plant"),
+        path: String::from("modbus+tcp://10.0.0.5:1502"),
+        csv_map: Some(csv_path()),
+    };
+    let interface = source.load().unwrap_or_else(|e| panic!("Uri device does not load: {}", e));
+    assert_eq!((interface.address().as_str(), interface.config()), ("10.0.0.5", 1502));
+    assert_eq!(interface.slave_names().len(), 2);
+
+}
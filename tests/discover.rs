@@ -0,0 +1,99 @@
+mod common;
+
+use std::{sync::{atomic::{AtomicUsize, Ordering}, Arc}, time::{Duration, Instant}};
+use tokio::net::TcpListener;
+use tokio_modbus::{Request, Response};
+
+use zero_modbus::interface::{BlockType, Interface};
+use zero_modbus::modbus::{ModbusSession, ReconnectPolicy};
+use common::MockDevice;
+
+
+#[tokio::test]
+async fn scan_keeps_the_connection_of_the_session() {
+
+    let device = MockDevice::start().await;
+    let connection = device.interface("connect_timeout_ms: 500\nslaves: []");
+    let mut session = ModbusSession::open(&connection).await.unwrap_or_else(|e| panic!("no connection: {}", e));
+
+    let discovered = Interface::from_modbus_scan(&mut session, 7).await;
+    assert_eq!((discovered.address(), discovered.config()), (connection.address(), connection.config()));
+    assert_eq!(discovered.connect_timeout(), connection.connect_timeout());
+    assert_eq!(discovered.slave_names(), vec!["slave_7"]);
+    assert_eq!(device.requests()[0].0, 7);
+
+    // the mock answers every address, so each block is found whole by its first read
+    let (slave, slave_id) = discovered.resolve_slave("slave_7").unwrap();
+    assert_eq!(slave_id, 7);
+    for block in [BlockType::Co, BlockType::Di, BlockType::Hr, BlockType::Ir] {
+        assert_eq!(slave.registers().iter_block(block).count(), 125, "{}", block);
+    }
+    assert!(slave.find("hr_0124").is_some());
+
+    // what the cli writes loads back
+    let reloaded = Interface::from_bytes(discovered.to_yaml_string().as_bytes(), "yaml")
+        .unwrap_or_else(|e| panic!("Discovered config does not load: {}", e));
+    assert!(reloaded == discovered);
+
+}
+
+// a device that takes every connection and closes it again without answering
+async fn hanging_up_device() -> (Interface, Arc<AtomicUsize>) {
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let accepted = Arc::new(AtomicUsize::new(0));
+    let counter = accepted.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            counter.fetch_add(1, Ordering::SeqCst);
+            drop(stream);
+        }
+    });
+    let connection = Interface::from_bytes(format!("protocol: tcp\naddress: 127.0.0.1\ntcp_port: {}\nslaves: []", port).as_bytes(), "yaml")
+        .unwrap_or_else(|e| panic!("{}", e));
+
+    (connection, accepted)
+
+}
+
+#[tokio::test]
+async fn lost_connection_is_not_reopened_without_a_policy() {
+
+    let (connection, accepted) = hanging_up_device().await;
+    let mut session = ModbusSession::open(&connection).await.unwrap_or_else(|e| panic!("no connection: {}", e))
+        .with_reconnect_policy(ReconnectPolicy::NoReconnect);
+
+    let error = session.call(1, Request::ReadHoldingRegisters(0, 1)).await.err().map(|e| e.to_string()).expect("a closed connection answered");
+    assert!(error.starts_with("TransportError"), "{}", error);
+    assert_eq!(accepted.load(Ordering::SeqCst), 1);
+
+}
+
+#[tokio::test]
+async fn lost_connection_is_reopened_after_the_policy_delay() {
+
+    let (connection, accepted) = hanging_up_device().await;
+    let mut session = ModbusSession::open(&connection).await.unwrap_or_else(|e| panic!("no connection: {}", e))
+        .with_reconnect_policy(ReconnectPolicy::FixedDelay(Duration::from_millis(100)));
+
+    // the request is sent once more on the new connection, which is closed as well
+    let started = Instant::now();
+    assert!(session.call(1, Request::ReadHoldingRegisters(0, 1)).await.is_err());
+    assert!(started.elapsed() >= Duration::from_millis(100), "reconnected after {:?}", started.elapsed());
+    assert_eq!(accepted.load(Ordering::SeqCst), 2);
+
+}
+
+#[tokio::test]
+async fn session_call_reads_from_the_device() {
+
+    let device = MockDevice::start().await;
+    device.set_holding_registers(4, &[42]);
+    let mut session = ModbusSession::open(&device.interface("slaves: []")).await.unwrap_or_else(|e| panic!("no connection: {}", e));
+
+    let response = session.call(3, Request::ReadHoldingRegisters(4, 1)).await.unwrap_or_else(|e| panic!("{}", e));
+    assert!(response == Response::ReadHoldingRegisters(vec![42]));
+    assert_eq!(device.requests(), vec![(3, String::from("ReadHoldingRegisters(4, 1)"))]);
+
+}
@@ -0,0 +1,38 @@
+mod common;
+
+use std::collections::HashMap;
+use serde_json::json;
+
+use zero_modbus::display::format_hexdump;
+use zero_modbus::server::ServerOptions;
+use common::{Gateway, MockDevice};
+
+
+#[test]
+fn hexdump_lines() {
+
+    let bytes: Vec<u8> = (0x30..0x30 + 18).collect();
+    assert_eq!(format_hexdump(&bytes), [
+        "00000000  30 31 32 33 34 35 36 37  38 39 3a 3b 3c 3d 3e 3f  |0123456789:;<=>?|",
+        "00000010  40 41                                             |@A|",
+        "00000012",
+    ].join("\n"));
+
+}
+
+#[tokio::test]
+async fn hexdump_of_four_registers() {
+
+    let device = MockDevice::start().await;
+    device.set_holding_registers(16, &[0x4142, 0x4344, 0x0001, 0x7E20]);
+    let devices = HashMap::from([(String::from("plant"), device.fixture("roundtrip.yaml"))]);
+    let mut gateway = Gateway::new(devices, ServerOptions::default());
+
+    let response = gateway.request(json!({"HEXDUMP": {"interface": "plant", "slave": "meter", "block": "hr", "start": 16, "count": 4}})).await;
+    assert_eq!(response["HEXDUMP"], [
+        "00000000  41 42 43 44 00 01 7e 20                           |ABCD..~ |",
+        "00000008",
+    ].join("\n"), "{}", response);
+    assert_eq!(device.requests(), vec![(1, String::from("ReadHoldingRegisters(16, 4)"))]);
+
+}
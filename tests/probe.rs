@@ -0,0 +1,32 @@
+mod common;
+
+use std::{collections::HashMap, time::Duration};
+use serde_json::json;
+
+use zero_modbus::server::ServerOptions;
+use common::{Gateway, MockDevice, MockQuirks};
+
+
+const SLAVES: &str = "
+slaves:
+- meter:
+    id: 1
+    hr:
+    - level: {addr: 0, type: u16}
+";
+
+#[tokio::test]
+async fn late_answer_is_not_taken_for_the_next_probe() {
+
+    let device = MockDevice::start().await;
+    let devices = HashMap::from([(String::from("probed"), device.interface(SLAVES))]);
+    let mut gateway = Gateway::new(devices, ServerOptions::default());
+
+    // every answer comes after the probe gave up on it
+    device.set_quirks(MockQuirks { stall: Some(Duration::from_millis(700)), ..MockQuirks::default() });
+    let response = gateway.request(json!({"TEST": {"key": "ping", "probe": true, "count": 3}})).await;
+    let meter = &response["probe"]["probed"]["meter"];
+    assert_eq!((&meter["sent"], &meter["lost"]), (&json!(3), &json!(3)), "{}", response);
+    assert_eq!(device.connections(), 4, "each lost probe reopens the session");
+
+}
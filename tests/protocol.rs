@@ -0,0 +1,114 @@
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+
+use zero_modbus::protocol::{
+    AggregateResponse, DumpConfigResponse, ErrorBody, GetResponse, HexdumpResponse, IdentifyResponse,
+    ListResponse, ReloadResponse, Request, RequestError, SetResponse, SnapshotResponse, StatsResponse, TestResponse,
+};
+
+
+#[test]
+fn requests_round_trip() {
+
+    for request in [
+        json!({"TEST": "ping"}),
+        json!({"TEST": {"key": "ping", "probe": true, "count": 3}}),
+        json!({"GET": ["/plant/meter/level", "/plant/meter/total"]}),
+        json!({"GET": {"paths": ["/plant/meter/level"], "etag": "5f1c", "with_units": true, "describe": true}}),
+        json!({"SET": {"/plant/meter/level": 7, "/plant/meter/relay": true}}),
+        json!({"SET": {"values": {"/plant/meter/level": 7}, "on_error": "abort", "coerce_strings": true, "idempotency_key": "k1"}}),
+        json!({"DUMP_CONFIG": "plant"}),
+        json!({"DUMP_CONFIG": {"device": "plant", "layout": true, "format": "table"}}),
+        json!({"AGGREGATE": {"interface": "plant", "point": "level", "op": "avg"}}),
+        json!({"SNAPSHOT": null}),
+        json!({"IDENTIFY": {"interface": "plant", "slave": "meter"}}),
+        json!({"HEXDUMP": {"interface": "plant", "slave": "meter", "block": "hr", "start": 16, "count": 4}}),
+        json!({"LIST": null}),
+        json!({"STATS": null}),
+        json!({"RELOAD": null}),
+    ] {
+        let (parsed, _) = match Request::parse(&request.to_string()) {
+            Ok(parsed) => parsed,
+            Err(e) => panic!("{} does not parse: {}", request, e),
+        };
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), request);
+    }
+
+}
+
+#[test]
+fn method_names_are_case_insensitive() {
+
+    let (parsed, body) = Request::parse(r#"{"get": ["/plant/meter/level"]}"#).unwrap_or_else(|e| panic!("{}", e));
+    assert_eq!(parsed.method().as_str(), "GET");
+    assert_eq!(body, json!(["/plant/meter/level"]));
+
+}
+
+// the ErrorBody a malformed request is answered with
+fn parse_error(request: &str) -> Value {
+
+    match Request::parse(request) {
+        Ok(_) => panic!("{} was accepted", request),
+        Err(e) => serde_json::to_value(ErrorBody::from(e)).unwrap(),
+    }
+
+}
+
+#[test]
+fn parse_errors_name_the_field() {
+
+    assert!(matches!(Request::parse("{\"GET\": "), Err(RequestError::Malformed(_))));
+    assert!(matches!(Request::parse(r#"{"GET": [], "SET": {}}"#), Err(RequestError::Malformed(_))));
+
+    let error = parse_error(r#"{"FETCH": ["/plant/meter/level"]}"#);
+    assert_eq!(error["ERROR"], "INVAILED METHOD");
+    assert_eq!(error["reason"], "unknown method 'FETCH'");
+
+    let error = parse_error(r#"{"HEXDUMP": {"interface": "plant", "slave": "meter", "block": "hr", "start": "16", "count": 4}}"#);
+    assert_eq!(error["ERROR"], "INVAILED HEXDUMP");
+    assert!(error["reason"].as_str().unwrap().starts_with("start: invalid type"), "{}", error);
+
+    let error = parse_error(r#"{"SET": {"values": {"/plant/meter/level": 7}, "on_error": "retry"}}"#);
+    assert!(error["reason"].as_str().unwrap().starts_with("on_error: unknown variant"), "{}", error);
+
+}
+
+fn assert_round_trip<T: Serialize + DeserializeOwned>(response: Value) {
+
+    let typed: T = serde_json::from_value(response.clone()).unwrap_or_else(|e| panic!("{} does not parse: {}", response, e));
+    assert_eq!(serde_json::to_value(&typed).unwrap(), response);
+
+}
+
+#[test]
+fn responses_round_trip() {
+
+    assert_round_trip::<TestResponse>(json!({"TEST": "ping", "connections": {"plant": {"connect": "eager", "connected": true}}}));
+    assert_round_trip::<TestResponse>(json!({"TEST": "ping", "connections": {}, "probe": {"plant": {"meter": [1.5, null]}}, "errors": {"boiler": "offline"}}));
+    assert_round_trip::<GetResponse>(json!({"GET": {"level": 7}, "etag": "5f1c"}));
+    assert_round_trip::<GetResponse>(json!({"GET": null, "etag": "5f1c", "unchanged": true}));
+    assert_round_trip::<SetResponse>(json!({"SET": {"/plant/meter/level": 7}}));
+    assert_round_trip::<SetResponse>(json!({"SET": {}, "coerced": ["/plant/meter/level"], "failed": {"/plant/meter/relay": "timeout"}, "skipped": ["/plant/meter/total"], "simulated": true}));
+    assert_round_trip::<DumpConfigResponse>(json!({"DUMP_CONFIG": "protocol: tcp\n"}));
+    assert_round_trip::<AggregateResponse>(json!({"AGGREGATE": {"op": "sum", "value": 12.5, "count": 2, "skipped": ["heater_3"]}}));
+    assert_round_trip::<AggregateResponse>(json!({"AGGREGATE": {"op": "max", "value": null, "count": 0, "skipped": []}}));
+    assert_round_trip::<SnapshotResponse>(json!({"SNAPSHOT": {"/plant/meter/level": 7}, "status": {"plant": "ok"}, "errors": {"boiler": "offline"}}));
+    assert_round_trip::<IdentifyResponse>(json!({"IDENTIFY": {"vendor_name": "ACME"}}));
+    assert_round_trip::<HexdumpResponse>(json!({"HEXDUMP": "0010: 0001 0002"}));
+    assert_round_trip::<ListResponse>(json!({"LIST": {"plant": ["meter"]}}));
+    assert_round_trip::<StatsResponse>(json!({"STATS": {
+        "interval_s": 60, "rps": 2.5, "mean_ms": 12.0, "p50_ms": 10.0, "p95_ms": 30.0, "p99_ms": 45.0,
+        "error_rate": 0.5, "sessions": 1, "recoveries": 2, "device_recoveries": {"boiler": 2},
+        "rtt_ms": {"/boiler/burner": 8.5}, "writes": {"boiler": "simulate"},
+    }}));
+    assert_round_trip::<StatsResponse>(json!({"STATS": {
+        "interval_s": 60, "rps": 0.0, "mean_ms": 0.0, "p50_ms": 0.0, "p95_ms": 0.0, "p99_ms": 0.0,
+        "error_rate": 0.0, "sessions": 0, "recoveries": 0,
+    }}));
+    assert_round_trip::<ReloadResponse>(json!({"RELOAD": {"added": [], "removed": [], "changed": [], "unchanged": ["plant"]}}));
+    assert_round_trip::<ReloadResponse>(json!({"RELOAD": {"added": [], "removed": [], "changed": ["plant"], "unchanged": []},
+        "changes": {"plant": {"added": ["meter/total"], "removed": [], "modified": {}, "reconnect": [], "settings": []}}}));
+    assert_round_trip::<ErrorBody>(json!({"ERROR": "INVAILED GET", "DETAILS": "{}", "reason": "missing field `paths`"}));
+
+}
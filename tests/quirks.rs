@@ -0,0 +1,165 @@
+mod common;
+
+use serde_json::{json, Value};
+
+use zero_modbus::interface::Interface;
+use zero_modbus::modbus::{self, ModbusError};
+use common::{get, set, MockDevice, MockQuirks, MockRtuDevice};
+
+
+async fn read(device: &MockDevice, slaves: &str, name: &str) -> Result<Value, ModbusError> {
+
+    modbus::batch_request(String::from("dev"), device.interface(slaves), vec![get("meter", name)]).await
+        .map(|mut results| results.remove(0).value)
+
+}
+
+async fn switch(interface: Interface, state: bool) -> Result<Value, ModbusError> {
+
+    modbus::batch_request(String::from("dev"), interface, vec![set("meter", "relay", json!(state))]).await
+        .map(|mut results| results.remove(0).value)
+
+}
+
+const LENIENT: &str = "
+lenient_width: true
+slaves:
+- meter:
+    id: 1
+    hr:
+    - energy: {addr: 0, type: u32}
+";
+
+#[tokio::test]
+async fn lenient_width_zero_pads_a_short_answer() {
+
+    let device = MockDevice::start().await;
+    device.set_holding_registers(0, &[0x1234, 0x5678]);
+    device.set_quirks(MockQuirks { read_delta: -1, ..MockQuirks::default() });
+
+    // the single word answered is the low word, the missing high word reads as zero
+    assert_eq!(read(&device, LENIENT, "energy").await.ok(), Some(json!(0x1234)));
+
+}
+
+#[tokio::test]
+async fn short_answer_fails_without_lenient_width() {
+
+    let device = MockDevice::start().await;
+    device.set_quirks(MockQuirks { read_delta: -1, ..MockQuirks::default() });
+
+    let slaves = LENIENT.replace("lenient_width: true", "");
+    let error = read(&device, &slaves, "energy").await.expect_err("a short answer was accepted");
+    assert!(error.to_string().contains("expected 2, received 1"), "{}", error);
+
+}
+
+#[tokio::test]
+async fn miscounted_answer_names_function_address_and_payload() {
+
+    let device = MockDevice::start().await;
+    device.set_holding_registers(0, &[0x1234, 0x5678]);
+
+    device.set_quirks(MockQuirks { read_delta: 1, ..MockQuirks::default() });
+    let error = read(&device, LENIENT, "energy").await.expect_err("a long answer was accepted");
+    assert!(error.to_string().contains("ResponseSizeNotMatch: function 0x03 at 0 expected 2, received 3 [0x1234, 0x5678, 0x0000]"), "{}", error);
+
+    let slaves = LENIENT.replace("lenient_width: true", "");
+    device.set_quirks(MockQuirks { read_delta: -1, ..MockQuirks::default() });
+    let error = read(&device, &slaves, "energy").await.expect_err("a short answer was accepted");
+    assert!(error.to_string().contains("ResponseSizeNotMatch: function 0x03 at 0 expected 2, received 1 [0x1234]"), "{}", error);
+
+}
+
+#[tokio::test]
+async fn padded_read_serves_the_point_from_the_start() {
+
+    let device = MockDevice::start().await;
+    device.set_holding_registers(5, &[42, 99]);
+    device.set_quirks(MockQuirks { min_read_quantity: 2, ..MockQuirks::default() });
+
+    let slaves = "
+slaves:
+- meter:
+    id: 1
+    min_read_quantity: 2
+    hr:
+    - level: {addr: 5, type: u16}
+";
+    assert_eq!(read(&device, slaves, "level").await.ok(), Some(json!(42)));
+    assert_eq!(device.requests(), vec![(1, String::from("ReadHoldingRegisters(5, 2)"))]);
+
+}
+
+#[tokio::test]
+async fn lenient_width_pads_a_short_padded_read_to_the_point() {
+
+    let device = MockDevice::start().await;
+    device.set_holding_registers(0, &[0x1234, 0x5678, 0, 0]);
+    // asked for the padded four words, the narrow device answers one
+    device.set_quirks(MockQuirks { min_read_quantity: 4, read_delta: -3, ..MockQuirks::default() });
+
+    let slaves = "
+lenient_width: true
+slaves:
+- meter:
+    id: 1
+    min_read_quantity: 4
+    hr:
+    - energy: {addr: 0, type: u32}
+";
+    assert_eq!(read(&device, slaves, "energy").await.ok(), Some(json!(0x1234)));
+
+}
+
+const RELAY: &str = "
+slaves:
+- meter:
+    id: 1
+    co:
+    - relay: {addr: 3, func: single}
+";
+
+#[tokio::test]
+async fn nonzero_coil_echo_is_accepted_when_lenient() {
+
+    let device = MockDevice::start().await;
+    device.set_quirks(MockQuirks { coil_echo: Some(0x0001), ..MockQuirks::default() });
+
+    let lenient = format!("lenient_coil_echo: true{}", RELAY);
+    assert_eq!(switch(device.interface(&lenient), true).await.ok(), Some(json!(true)));
+    assert!(device.coil(3));
+
+    let error = switch(device.interface(RELAY), true).await.expect_err("a nonstandard echo was accepted");
+    assert!(error.to_string().contains("Invalid coil value"), "{}", error);
+
+}
+
+#[tokio::test]
+async fn lenient_coil_echo_only_covers_a_coil_switched_on() {
+
+    let device = MockDevice::start().await;
+    let lenient = format!("lenient_coil_echo: true{}", RELAY);
+
+    // a nonzero echo does not confirm switching off
+    device.set_quirks(MockQuirks { coil_echo: Some(0x0001), ..MockQuirks::default() });
+    assert!(switch(device.interface(&lenient), false).await.is_err());
+
+    // a well formed echo of the other state is a failed write, not a malformed one
+    device.set_quirks(MockQuirks { coil_echo: Some(0x0000), ..MockQuirks::default() });
+    let error = switch(device.interface(&lenient), true).await.expect_err("the wrong state was accepted");
+    assert!(error.to_string().contains("Unexpected echo of coil 3"), "{}", error);
+
+}
+
+#[tokio::test]
+async fn nonzero_coil_echo_is_accepted_over_rtu() {
+
+    let device = MockRtuDevice::start().await;
+    device.set_quirks(MockQuirks { coil_echo: Some(0x0001), ..MockQuirks::default() });
+
+    let lenient = format!("lenient_coil_echo: true{}", RELAY);
+    assert_eq!(switch(device.interface(&lenient), true).await.ok(), Some(json!(true)));
+    assert!(switch(device.interface(RELAY), true).await.is_err());
+
+}
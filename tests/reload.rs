@@ -0,0 +1,161 @@
+mod common;
+
+use std::{collections::HashMap, fs, path::PathBuf, sync::atomic::Ordering};
+use serde_json::json;
+
+use zero_modbus::interface::DeviceSource;
+use zero_modbus::modbus;
+use zero_modbus::server::ServerOptions;
+use common::{Gateway, MockDevice};
+
+
+const SLAVES: &str = "
+connect: eager
+slaves:
+- meter:
+    id: 1
+    hr:
+    - level: {addr: 0, type: u16}
+";
+
+const MORE_SLAVES: &str = "
+connect: eager
+slaves:
+- meter:
+    id: 1
+    hr:
+    - level: {addr: 0, type: u16}
+    - total: {addr: 1, type: u16}
+";
+
+fn device_file(name: &str, yaml: &str) -> PathBuf {
+
+    let path = std::env::temp_dir().join(format!("zero_modbus_reload_{}_{}.yaml", name, std::process::id()));
+    fs::write(&path, yaml).unwrap();
+    path
+
+}
+
+// every device loaded from its own file, connected as the server would on start
+async fn gateway(device: &MockDevice, names: &[&str], options: ServerOptions) -> (Gateway, Vec<PathBuf>) {
+
+    let mut device_sources = Vec::new();
+    let mut devices = HashMap::new();
+    for name in names {
+        let path = device_file(name, &device.yaml(SLAVES));
+        let source = DeviceSource { name: name.to_string(), path: path.to_string_lossy().to_string(), csv_map: None };
+        let interface = source.load().unwrap_or_else(|e| panic!("{} does not load: {}", name, e));
+        modbus::connect_eager(name, &interface).await.unwrap_or_else(|e| panic!("no connection: {}", e));
+        devices.insert(name.to_string(), interface);
+        device_sources.push(source);
+    }
+    let paths = device_sources.iter().map(|source| PathBuf::from(&source.path)).collect();
+    (Gateway::new(devices, ServerOptions { device_sources, ..options }), paths)
+
+}
+
+#[tokio::test]
+async fn reload_reconnects_only_the_changed_device() {
+
+    let device = MockDevice::start().await;
+    device.set_holding_registers(0, &[7, 8]);
+    let (mut gateway, paths) = gateway(&device, &["reload_kept", "reload_edited"], ServerOptions::default()).await;
+    assert_eq!(device.connections(), 2);
+
+    fs::write(&paths[1], device.yaml(MORE_SLAVES)).unwrap();
+    let response = gateway.request(json!({"RELOAD": null})).await;
+    assert_eq!(response["RELOAD"], json!({
+        "added": [],
+        "removed": [],
+        "changed": ["reload_edited"],
+        "unchanged": ["reload_kept"],
+    }), "{}", response);
+    assert_eq!(response["changes"]["reload_edited"]["added"], json!(["meter/total"]), "{}", response);
+    assert_eq!(modbus::connection_state("reload_edited"), Some(true), "the edited device did not connect again");
+
+    let response = gateway.request(json!({"GET": ["/reload_kept/meter/level", "/reload_edited/meter/total"]})).await;
+    assert_eq!(response["GET"], json!({"level": 7, "total": 8}), "{}", response);
+    // the kept session and the new one of the edited device serve the request
+    assert_eq!(device.connections(), 3);
+
+    for path in paths {
+        fs::remove_file(path).unwrap();
+    }
+
+}
+
+#[tokio::test]
+async fn broken_file_keeps_the_running_config() {
+
+    let device = MockDevice::start().await;
+    let (mut gateway, paths) = gateway(&device, &["reload_broken"], ServerOptions::default()).await;
+
+    fs::write(&paths[0], "protocol: tcp\nslaves: [").unwrap();
+    let response = gateway.request(json!({"RELOAD": null})).await;
+    assert_eq!(response["ERROR"], "RELOAD_FAILED", "{}", response);
+    assert!(response["DETAILS"].as_str().is_some_and(|details| details.starts_with("reload_broken: ")), "{}", response);
+
+    let response = gateway.request(json!({"LIST": null})).await;
+    assert_eq!(response["LIST"], json!({"reload_broken": {"meter": ["level"]}}), "{}", response);
+    assert_eq!(modbus::connection_state("reload_broken"), Some(true), "the session was closed");
+
+    fs::remove_file(&paths[0]).unwrap();
+
+}
+
+#[tokio::test]
+async fn reload_without_device_files_is_refused() {
+
+    let device = MockDevice::start().await;
+    let devices = HashMap::from([(String::from("reload_none"), device.interface(SLAVES))]);
+    let mut gateway = Gateway::new(devices, ServerOptions::default());
+
+    let response = gateway.request(json!({"RELOAD": null})).await;
+    assert_eq!(response["ERROR"], "RELOAD_FAILED", "{}", response);
+
+}
+
+#[tokio::test]
+async fn hangup_reloads_while_idle() {
+
+    let device = MockDevice::start().await;
+    let (mut gateway, paths) = gateway(&device, &["reload_hangup"], ServerOptions::default()).await;
+
+    fs::write(&paths[0], device.yaml(MORE_SLAVES)).unwrap();
+    gateway.server.reload_handle().store(true, Ordering::Relaxed);
+    // nothing is pending, so the call returns after the reload instead of waiting for a request
+    assert!(gateway.server.serve_one(&mut gateway.devices).await, "server stopped");
+    assert!(!gateway.server.reload_handle().load(Ordering::Relaxed));
+
+    let response = gateway.request(json!({"LIST": null})).await;
+    assert_eq!(response["LIST"], json!({"reload_hangup": {"meter": ["level", "total"]}}), "{}", response);
+
+    fs::remove_file(&paths[0]).unwrap();
+
+}
+
+#[tokio::test]
+async fn reload_drops_the_stored_set_responses_of_the_device() {
+
+    let device = MockDevice::start().await;
+    let options = ServerOptions { idempotency_keys: 10, idempotency_ttl_s: 600, ..ServerOptions::default() };
+    let (mut gateway, paths) = gateway(&device, &["reload_replayed"], options).await;
+    let set = json!({"SET": {"values": {"/reload_replayed/meter/level": 5}, "idempotency_key": "k1"}});
+
+    gateway.request(set.clone()).await;
+    assert_eq!(device.holding_registers(0, 1), vec![5]);
+    // a retry is answered from the stored response, nothing is written
+    device.set_holding_registers(0, &[0]);
+    let response = gateway.request(set.clone()).await;
+    assert_eq!(response["SET"], json!({"/reload_replayed/meter/level": 5}), "{}", response);
+    assert_eq!(device.holding_registers(0, 1), vec![0]);
+
+    fs::write(&paths[0], device.yaml(MORE_SLAVES)).unwrap();
+    gateway.request(json!({"RELOAD": null})).await;
+    let response = gateway.request(set).await;
+    assert_eq!(response["SET"], json!({"/reload_replayed/meter/level": 5}), "{}", response);
+    assert_eq!(device.holding_registers(0, 1), vec![5], "the response stored before the reload was replayed");
+
+    fs::remove_file(&paths[0]).unwrap();
+
+}
@@ -0,0 +1,342 @@
+mod common;
+
+use std::{collections::HashMap, sync::atomic::{AtomicUsize, Ordering}, time::Duration};
+use serde_json::{json, Value};
+
+use zero_modbus::modbus::{self, ModbusError};
+use zero_modbus::server::{ServerOptions, TaskPlan};
+use common::{get, set, Gateway, MockDevice, MockQuirks};
+
+
+async fn read(device: &MockDevice, name: &str) -> Result<Value, ModbusError> {
+
+    let interface = device.fixture("roundtrip.yaml");
+    modbus::batch_request(String::from("dev"), interface, vec![get("meter", name)]).await
+        .map(|mut results| results.remove(0).value)
+
+}
+
+async fn write(device: &MockDevice, name: &str, value: Value) -> Result<Value, ModbusError> {
+
+    let interface = device.fixture("roundtrip.yaml");
+    modbus::batch_request(String::from("dev"), interface, vec![set("meter", name, value)]).await
+        .map(|mut results| results.remove(0).value)
+
+}
+
+#[tokio::test]
+async fn holding_registers_round_trip_every_value_type() {
+
+    let device = MockDevice::start().await;
+    let cases = [
+        ("setpoint_u16", json!(65535), 0, vec![0xFFFF]),
+        ("setpoint_i16", json!(-2), 1, vec![0xFFFE]),
+        ("counter_u32", json!(70000), 2, vec![0x0001, 0x1170]),
+        ("offset_i32", json!(-70000), 4, vec![0xFFFE, 0xEE90]),
+        ("gain_f32", json!(1.5), 6, vec![0x3FC0, 0x0000]),
+        ("code_bcd16", json!(1234), 8, vec![0x1234]),
+        ("total_bcd32", json!(12345678), 10, vec![0x1234, 0x5678]),
+        ("single_u16", json!(7), 12, vec![0x0007]),
+    ];
+    for (name, value, address, words) in cases {
+        let written = write(&device, name, value.clone()).await
+            .unwrap_or_else(|e| panic!("writing {}: {}", name, e));
+        assert_eq!(written, value, "written value of {}", name);
+        assert_eq!(device.holding_registers(address, words.len()), words, "registers of {}", name);
+        let read_back = read(&device, name).await
+            .unwrap_or_else(|e| panic!("reading {}: {}", name, e));
+        assert_eq!(read_back, value, "read back {}", name);
+    }
+
+}
+
+#[tokio::test]
+async fn scattered_words_round_trip_in_list_order() {
+
+    let device = MockDevice::start().await;
+
+    assert_eq!(write(&device, "scattered_u32", json!(70000)).await.ok(), Some(json!(70000)));
+    assert_eq!(device.holding_registers(20, 1), vec![0x0001]);
+    assert_eq!(device.holding_registers(15, 1), vec![0x1170]);
+    let requests: Vec<String> = device.requests().into_iter().map(|(_, request)| request).collect();
+    assert_eq!(requests, vec!["WriteMultipleRegisters(20, [1])", "WriteMultipleRegisters(15, [4464])"]);
+
+    device.set_holding_registers(20, &[0x0002]);
+    assert_eq!(read(&device, "scattered_u32").await.ok(), Some(json!(0x0002_1170)));
+
+}
+
+#[tokio::test]
+async fn coils_round_trip() {
+
+    let device = MockDevice::start().await;
+
+    assert_eq!(write(&device, "relay", json!(true)).await.ok(), Some(json!(true)));
+    assert!(device.coil(0));
+    assert_eq!(read(&device, "relay").await.ok(), Some(json!(true)));
+
+    let states = json!([true, false, true, true]);
+    assert_eq!(write(&device, "relay_bank", states.clone()).await.ok(), Some(states.clone()));
+    assert_eq!((8..12).map(|address| device.coil(address)).collect::<Vec<_>>(), vec![true, false, true, true]);
+    assert_eq!(read(&device, "relay_bank").await.ok(), Some(states));
+
+}
+
+#[tokio::test]
+async fn input_blocks_are_read() {
+
+    let device = MockDevice::start().await;
+    device.set_discrete_input(0, true);
+    device.set_input_registers(0, &[230, 0x3FC0, 0x0000]);
+
+    assert_eq!(read(&device, "door").await.ok(), Some(json!(true)));
+    assert_eq!(read(&device, "voltage").await.ok(), Some(json!(230)));
+    assert_eq!(read(&device, "current").await.ok(), Some(json!(1.5)));
+
+}
+
+#[tokio::test]
+async fn active_low_input_reads_inverted() {
+
+    let device = MockDevice::start().await;
+
+    // a normally closed contact, open on the wire is active
+    assert_eq!(read(&device, "contact").await.ok(), Some(json!(true)));
+    device.set_discrete_input(1, true);
+    assert_eq!(read(&device, "contact").await.ok(), Some(json!(false)));
+
+}
+
+#[tokio::test]
+async fn input_blocks_refuse_writes() {
+
+    let device = MockDevice::start().await;
+
+    let error = write(&device, "voltage", json!(1)).await.expect_err("write to an input register succeeded");
+    assert!(error.to_string().contains("WriteInputValue"), "{}", error);
+    assert!(device.requests().is_empty(), "nothing may reach the bus: {:?}", device.requests());
+
+}
+
+#[tokio::test]
+async fn illegal_address_is_reported_as_exception() {
+
+    let device = MockDevice::start().await;
+
+    let error = read(&device, "beyond_bank").await.expect_err("read beyond the bank succeeded");
+    assert!(error.is_exception(), "{}", error);
+    assert!(error.to_string().contains("Illegal data address"), "{}", error);
+
+}
+
+#[tokio::test]
+async fn rejected_write_names_the_words_sent() {
+
+    let device = MockDevice::start().await;
+    device.set_quirks(MockQuirks { reject_writes: true, ..MockQuirks::default() });
+
+    let error = write(&device, "counter_u32", json!(0x0001_0002)).await.expect_err("the rejected write succeeded");
+    assert!(error.is_exception(), "{}", error);
+    assert!(error.to_string().contains("Illegal data value, sent words [0x0001, 0x0002]"), "{}", error);
+    assert_eq!(device.holding_registers(2, 2), vec![0, 0]);
+
+}
+
+#[tokio::test]
+async fn out_of_range_values_are_refused_before_the_bus() {
+
+    let device = MockDevice::start().await;
+
+    for (name, value) in [("setpoint_u16", json!(65536)), ("setpoint_i16", json!(40000)), ("counter_u32", json!(-1))] {
+        assert!(write(&device, name, value.clone()).await.is_err(), "{} accepted {}", name, value);
+    }
+    assert!(device.requests().is_empty(), "nothing may reach the bus: {:?}", device.requests());
+
+}
+
+fn gateway(device: &MockDevice) -> Gateway {
+
+    let devices = HashMap::from([(String::from("dev"), device.fixture("roundtrip.yaml"))]);
+    Gateway::new(devices, ServerOptions::default())
+
+}
+
+#[tokio::test]
+async fn server_get_and_set() {
+
+    let device = MockDevice::start().await;
+    let mut gateway = gateway(&device);
+
+    let response = gateway.request(json!({"SET": {"/dev/meter/setpoint_u16": 42, "/dev/meter/gain_f32": 2.5}})).await;
+    assert_eq!(response["SET"], json!({"/dev/meter/setpoint_u16": 42, "/dev/meter/gain_f32": 2.5}), "{}", response);
+
+    let response = gateway.request(json!({"GET": ["/dev/meter/setpoint_u16", "/dev/meter/gain_f32"]})).await;
+    assert_eq!(response["GET"], json!({"setpoint_u16": 42, "gain_f32": 2.5}), "{}", response);
+
+}
+
+#[tokio::test]
+async fn server_reports_unknown_paths() {
+
+    let device = MockDevice::start().await;
+    let mut gateway = gateway(&device);
+
+    let response = gateway.request(json!({"GET": ["/dev/meter/setpoint_u61"]})).await;
+    assert_eq!(response["ERROR"], "UNKNOWN_DATAPOINT", "{}", response);
+    assert!(response["suggestions"].as_array().unwrap().contains(&json!("setpoint_u16")), "{}", response);
+
+    let response = gateway.request(json!({"GET": ["/dev/mater/setpoint_u16"]})).await;
+    assert_eq!(response["ERROR"], "UNKNOWN_SLAVE", "{}", response);
+
+    assert!(device.requests().is_empty(), "nothing may reach the bus: {:?}", device.requests());
+
+}
+
+#[tokio::test]
+async fn server_reports_device_exceptions() {
+
+    let device = MockDevice::start().await;
+    let mut gateway = gateway(&device);
+
+    let response = gateway.request(json!({"GET": ["/dev/meter/beyond_bank"]})).await;
+    assert_eq!(response["ERROR"], "MODBUS ERROR", "{}", response);
+    assert!(response["DETAILS"].as_str().unwrap().contains("dev/meter/beyond_bank"), "{}", response);
+
+}
+
+#[tokio::test]
+async fn integer_written_to_a_float_point() {
+
+    let device = MockDevice::start().await;
+
+    assert_eq!(write(&device, "gain_f32", json!(5)).await.ok(), Some(json!(5.0)));
+    assert_eq!(device.holding_registers(6, 2), vec![0x40A0, 0x0000]);
+
+}
+
+#[tokio::test]
+async fn inverted_coil_is_flipped_both_ways() {
+
+    let device = MockDevice::start().await;
+
+    for state in [true, false] {
+        assert_eq!(write(&device, "relay_nc", json!(state)).await.ok(), Some(json!(state)));
+        assert_eq!(device.coil(1), !state);
+        assert_eq!(read(&device, "relay_nc").await.ok(), Some(json!(state)));
+    }
+
+}
+
+#[tokio::test]
+async fn pulse_coil_is_reset_after_pulse_ms() {
+
+    let device = MockDevice::start().await;
+    let devices = HashMap::from([(String::from("dev"), device.interface("
+slaves:
+- meter:
+    id: 1
+    co:
+    - burner: {addr: 3, pulse_ms: 200}
+"))]);
+    let mut gateway = Gateway::new(devices, ServerOptions::default());
+
+    let response = gateway.request(json!({"SET": {"/dev/meter/burner": true}})).await;
+    assert_eq!(response["SET"], json!({"/dev/meter/burner": true}), "{}", response);
+    let requests: Vec<String> = device.requests().into_iter().map(|(_, request)| request).collect();
+    assert_eq!(requests, vec!["WriteMultipleCoils(3, [true])", "WriteMultipleCoils(3, [false])"]);
+    let arrivals = device.arrivals();
+    let pulse = arrivals[1] - arrivals[0];
+    assert!(pulse >= Duration::from_millis(200) && pulse < Duration::from_millis(1000), "pulse lasted {:?}", pulse);
+    assert!(!device.coil(3));
+
+}
+
+#[tokio::test]
+async fn inverted_coil_array_is_flipped_per_element() {
+
+    let device = MockDevice::start().await;
+
+    assert_eq!(write(&device, "relay_bank_nc", json!([true, false, true])).await.ok(), Some(json!([true, false, true])));
+    assert_eq!((12..15).map(|address| device.coil(address)).collect::<Vec<_>>(), vec![false, true, false]);
+    assert_eq!(read(&device, "relay_bank_nc").await.ok(), Some(json!([true, false, true])));
+
+}
+
+#[tokio::test]
+async fn inverted_input_reads_flipped() {
+
+    let device = MockDevice::start().await;
+
+    assert_eq!(read(&device, "door_nc").await.ok(), Some(json!(true)));
+    device.set_discrete_input(2, true);
+    assert_eq!(read(&device, "door_nc").await.ok(), Some(json!(false)));
+
+}
+
+#[tokio::test]
+async fn inverted_flags_are_flipped_per_bit() {
+
+    let device = MockDevice::start().await;
+
+    assert_eq!(read(&device, "status_nc").await.ok(), Some(json!({"running": true, "fault": true})));
+    // only the named bit is written, the other keeps its raw state
+    write(&device, "status_nc", json!({"running": false})).await.unwrap_or_else(|e| panic!("{}", e));
+    assert_eq!(device.holding_registers(13, 1), vec![0x0001]);
+    assert_eq!(read(&device, "status_nc").await.ok(), Some(json!({"running": false, "fault": true})));
+
+}
+
+#[tokio::test]
+async fn duplicated_get_path_is_read_once() {
+
+    let device = MockDevice::start().await;
+    device.set_holding_registers(0, &[42]);
+    let devices = HashMap::from([(String::from("dev"), device.fixture("roundtrip.yaml"))]);
+    let mut gateway = Gateway::new(devices, ServerOptions { dedup: true, ..ServerOptions::default() });
+
+    let response = gateway.request(json!({"GET": ["/dev/meter/setpoint_u16", "/dev/meter/setpoint_u16"]})).await;
+    assert_eq!(response["GET"], json!({"setpoint_u16": 42}), "{}", response);
+    assert_eq!(device.requests().len(), 1, "{:?}", device.requests());
+
+    // the duplicate is already dropped when the batch is planned
+    let mut planner = TaskPlan::with_dedup(true);
+    planner.push("/dev/meter/setpoint_u16", None);
+    planner.push("/dev/meter/setpoint_u16", None);
+    assert_eq!(planner.plan().iter().map(|(_, request_info)| request_info.len()).sum::<usize>(), 1);
+
+}
+
+#[tokio::test]
+async fn registered_value_type_decodes_and_encodes_its_points() {
+
+    static DECODED: AtomicUsize = AtomicUsize::new(0);
+    // 48 bit unsigned, most significant word first
+    let decode = |words: &[u16]| {
+        DECODED.fetch_add(1, Ordering::Relaxed);
+        Some(Value::from(words.iter().fold(0u64, |value, word| value << 16 | u64::from(*word))))
+    };
+    let encode = |value: &Value| value.as_u64()
+        .filter(|value| *value < 1 << 48)
+        .map(|value| vec![(value >> 32) as u16, (value >> 16) as u16, value as u16]);
+    modbus::register_value_type("packed_u48", 3, decode, encode).unwrap_or_else(|e| panic!("{}", e));
+
+    let device = MockDevice::start().await;
+    device.set_holding_registers(30, &[0x0001, 0x0002, 0x0003]);
+    let interface = device.interface("
+slaves:
+- meter:
+    id: 1
+    hr:
+    - energy: {addr: 30, type: packed_u48}
+");
+
+    let results = modbus::batch_request(String::from("dev"), interface.clone(), vec![get("meter", "energy")]).await
+        .unwrap_or_else(|e| panic!("{}", e));
+    assert_eq!(results[0].value, json!(0x0001_0002_0003u64));
+    assert_eq!(DECODED.load(Ordering::Relaxed), 1, "the registered decoder was not called");
+
+    modbus::batch_request(String::from("dev"), interface, vec![set("meter", "energy", json!(0x0004_0005_0006u64))]).await
+        .unwrap_or_else(|e| panic!("{}", e));
+    assert_eq!(device.holding_registers(30, 3), vec![0x0004, 0x0005, 0x0006]);
+
+}
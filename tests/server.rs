@@ -0,0 +1,370 @@
+mod common;
+
+use std::{collections::HashMap, time::Duration};
+use tokio::time;
+use serde_json::json;
+
+use zero_modbus::server::{KeyCase, ServerOptions};
+use common::{Gateway, MockDevice, MockQuirks, MockRtuDevice};
+
+
+// two devices on the same mock, so every path resolves the same on both
+fn gateway(device: &MockDevice, names: &[&str]) -> Gateway {
+
+    let devices = names.iter()
+        .map(|name| (name.to_string(), device.fixture("roundtrip.yaml")))
+        .collect::<HashMap<_, _>>();
+    Gateway::new(devices, ServerOptions::default())
+
+}
+
+#[tokio::test]
+async fn set_with_unknown_device_writes_nothing() {
+
+    let device = MockDevice::start().await;
+    let mut gateway = gateway(&device, &["plant", "boiler"]);
+
+    // the plan is unordered, repeat so the unknown device is not always checked last
+    for _ in 0..16 {
+        let response = gateway.request(json!({"SET": {
+            "/plant/meter/setpoint_u16": 1,
+            "/boiler/meter/setpoint_u16": 2,
+            "/plnat/meter/setpoint_u16": 3,
+        }})).await;
+        assert_eq!(response["ERROR"], "DEVICE_NOT_FOUND", "{}", response);
+        assert_eq!(response["devices"], json!(["plnat"]), "{}", response);
+        assert_eq!(response["suggestions"], json!({"plnat": ["boiler", "plant"]}), "{}", response);
+    }
+    assert!(device.requests().is_empty(), "nothing may reach the bus: {:?}", device.requests());
+
+}
+
+#[tokio::test]
+async fn get_with_unknown_device_is_refused() {
+
+    let device = MockDevice::start().await;
+    let mut gateway = gateway(&device, &["plant"]);
+
+    let response = gateway.request(json!({"GET": ["/plant/meter/setpoint_u16", "/plnat/meter/setpoint_u16"]})).await;
+    assert_eq!(response["ERROR"], "DEVICE_NOT_FOUND", "{}", response);
+    assert_eq!(response["devices"], json!(["plnat"]), "{}", response);
+    assert_eq!(response["suggestions"], json!({"plnat": ["plant"]}), "{}", response);
+    assert!(device.requests().is_empty(), "nothing may reach the bus: {:?}", device.requests());
+
+}
+
+#[tokio::test]
+async fn set_lists_read_only_paths_of_every_device() {
+
+    let device = MockDevice::start().await;
+    let mut gateway = gateway(&device, &["plant", "boiler"]);
+
+    let response = gateway.request(json!({"SET": {
+        "/plant/meter/voltage": 1,
+        "/plant/meter/setpoint_u16": 1,
+        "/boiler/meter/door": true,
+        "/boiler/meter/current": 1.5,
+    }})).await;
+    assert_eq!(response["ERROR"], "WRITE_TO_READ_ONLY_REGISTER", "{}", response);
+    assert_eq!(response["registers"], json!([
+        "/boiler/meter/current",
+        "/boiler/meter/door",
+        "/plant/meter/voltage",
+    ]), "{}", response);
+    assert!(device.requests().is_empty(), "nothing may reach the bus: {:?}", device.requests());
+
+}
+
+#[tokio::test]
+async fn get_over_max_batch_names_the_limit() {
+
+    let device = MockDevice::start().await;
+    let devices = HashMap::from([(String::from("batched"), device.interface("
+max_batch: 2
+slaves:
+- meter:
+    id: 1
+    hr:
+    - level: {addr: 0, type: u16}
+    - total: {addr: 1, type: u16}
+    - flow: {addr: 2, type: u16}
+"))]);
+    let mut gateway = Gateway::new(devices, ServerOptions::default());
+
+    let response = gateway.request(json!({"GET": ["/batched/meter/level", "/batched/meter/total", "/batched/meter/flow"]})).await;
+    assert_eq!(response["ERROR"], "BATCH_TOO_LARGE", "{}", response);
+    assert_eq!(response["device"], "batched", "{}", response);
+    assert_eq!(response["limit"], 2, "{}", response);
+    assert_eq!(response["count"], 3, "{}", response);
+    assert!(device.requests().is_empty(), "nothing may reach the bus: {:?}", device.requests());
+
+    let response = gateway.request(json!({"GET": ["/batched/meter/level", "/batched/meter/total"]})).await;
+    assert_eq!(response["GET"], json!({"level": 0, "total": 0}), "{}", response);
+
+}
+
+#[tokio::test]
+async fn aggregate_skips_a_failing_slave() {
+
+    let device = MockDevice::start().await;
+    device.set_holding_registers(0, &[10, 20, 30]);
+    let devices = HashMap::from([(String::from("tanks"), device.interface("
+slaves:
+- tank_a:
+    id: 1
+    hr:
+    - level: {addr: 0, type: u16}
+- tank_b:
+    id: 2
+    hr:
+    - level: {addr: 1, type: u16}
+- tank_c:
+    id: 3
+    hr:
+    - level: {addr: 2, type: u16}
+- tank_broken:
+    id: 4
+    hr:
+    - level: {addr: 250, type: u16}
+"))]);
+    let mut gateway = Gateway::new(devices, ServerOptions::default());
+
+    let response = gateway.request(json!({"AGGREGATE": {"interface": "tanks", "point": "level", "op": "sum"}})).await;
+    assert_eq!(response["AGGREGATE"], json!({"op": "sum", "value": 60.0, "count": 3, "skipped": ["tank_broken"]}), "{}", response);
+
+}
+
+#[tokio::test]
+async fn identify_follows_more_follows_to_the_last_object() {
+
+    let device = MockDevice::start().await;
+    device.set_identification(&[(0x00, "ACME"), (0x01, "PM-100"), (0x02, "v1.2")]);
+    let mut gateway = gateway(&device, &["identified"]);
+
+    let response = gateway.request(json!({"IDENTIFY": {"interface": "identified", "slave": "meter"}})).await;
+    assert_eq!(response["IDENTIFY"], json!({"vendor_name": "ACME", "product_code": "PM-100", "revision": "v1.2"}), "{}", response);
+    // the basic objects do not fit one answer
+    assert_eq!(device.requests().len(), 2, "{:?}", device.requests());
+
+}
+
+#[tokio::test]
+async fn identify_without_support_is_reported() {
+
+    let device = MockDevice::start().await;
+    let mut gateway = gateway(&device, &["unidentified"]);
+
+    let response = gateway.request(json!({"IDENTIFY": {"interface": "unidentified", "slave": "meter"}})).await;
+    assert_eq!(response["ERROR"], "UNSUPPORTED", "{}", response);
+
+}
+
+#[tokio::test]
+async fn dump_config_resolves_the_template_of_every_slave() {
+
+    let device = MockDevice::start().await;
+    let devices = HashMap::from([(String::from("templated"), device.fixture("template_interface.yaml"))]);
+    let mut gateway = Gateway::new(devices, ServerOptions::default());
+
+    // the template is resolved into each slave, with the addresses its points landed on
+    let response = gateway.request(json!({"DUMP_CONFIG": {"device": "templated", "layout": true}})).await;
+    let config = &response["DUMP_CONFIG"];
+    assert!(config.get("templates").is_none(), "{}", response);
+    for (index, slave_name) in ["tcp_slave_1", "tcp_slave_2"].into_iter().enumerate() {
+        let slave = &config["slaves"][index][slave_name];
+        assert_eq!(slave["id"], index, "{}", response);
+        assert_eq!(slave["hr"], json!([{"holding_value": {"addr": 2, "func": "multiple", "type": "i16"}}]), "{}", response);
+        assert_eq!(slave["ir"], json!([{"rdonly_value": {"addr": 3, "type": "f32"}}]), "{}", response);
+        assert_eq!(config["layout"][slave_name]["ir"], json!([{"start": 3, "len": 2, "name": "rdonly_value"}]), "{}", response);
+    }
+    assert_eq!(config["slaves"][1]["tcp_slave_2"]["di"], json!([{"input_status": {"addr": 1, "type": "bool"}}]), "{}", response);
+
+}
+
+#[tokio::test]
+async fn get_describe_names_where_each_value_came_from() {
+
+    let device = MockDevice::start().await;
+    device.set_holding_registers(2, &[0x3FC0, 0x0000]);
+    device.set_discrete_input(5, true);
+    let devices = HashMap::from([(String::from("described"), device.interface("
+slaves:
+- meter:
+    id: 1
+    hr:
+    - gain: {addr: 2, type: f32, unit: m}
+    di:
+    - door: {addr: 5}
+"))]);
+    let mut gateway = Gateway::new(devices, ServerOptions::default());
+
+    let response = gateway.request(json!({"GET": {"paths": ["/described/meter/gain", "/described/meter/door"], "describe": true}})).await;
+    assert_eq!(response["GET"], json!({
+        "gain": {"value": 1.5, "block": "hr", "address": 2, "type": "f32", "unit": "m"},
+        "door": {"value": true, "block": "di", "address": 5, "type": "bool"},
+    }), "{}", response);
+
+    // without describe the values stay bare
+    let response = gateway.request(json!({"GET": {"paths": ["/described/meter/door"]}})).await;
+    assert_eq!(response["GET"], json!({"door": true}), "{}", response);
+
+}
+
+#[tokio::test]
+async fn snapshot_reads_by_poll_priority() {
+
+    let device = MockDevice::start().await;
+    let devices = HashMap::from([(String::from("plant"), device.interface("
+slaves:
+- meter:
+    id: 1
+    hr:
+    - energy: {addr: 0, type: u32}
+    - level: {addr: 40, type: u16, poll_priority: 10}
+    co:
+    - estop: {addr: 7, poll_priority: 255}
+"))]);
+    let mut gateway = Gateway::new(devices, ServerOptions::default());
+
+    let response = gateway.request(json!({"SNAPSHOT": null})).await;
+    assert_eq!(response["status"]["plant"]["meter"]["points_read"], 3, "{}", response);
+    let requests: Vec<String> = device.requests().into_iter().map(|(_, request)| request).collect();
+    assert_eq!(requests, vec!["ReadCoils(7, 1)", "ReadHoldingRegisters(40, 1)", "ReadHoldingRegisters(0, 2)"]);
+
+}
+
+#[tokio::test]
+async fn snapshot_reports_a_silent_slave_as_unreachable() {
+
+    let device = MockRtuDevice::start().await;
+    device.set_holding_registers(0, &[7]);
+    device.set_quirks(MockQuirks { absent_slaves: vec![2], ..MockQuirks::default() });
+    // the watchdog times each transaction, a silent slave is not waited for forever
+    let devices = HashMap::from([(String::from("line"), device.interface("
+watchdog: {timeouts: 5}
+slaves:
+- present:
+    id: 1
+    hr:
+    - level: {addr: 0, type: u16}
+- absent:
+    id: 2
+    hr:
+    - level: {addr: 0, type: u16}
+"))]);
+    let mut gateway = Gateway::new(devices, ServerOptions::default());
+
+    let response = gateway.request(json!({"SNAPSHOT": null})).await;
+    assert_eq!(response["SNAPSHOT"]["line"]["present"], json!({"level": 7}), "{}", response);
+    assert_eq!(response["status"]["line"]["present"]["reachable"], true, "{}", response);
+    assert_eq!(response["status"]["line"]["absent"], json!({"reachable": false, "points_read": 0, "points_failed": 1}), "{}", response);
+
+}
+
+#[tokio::test]
+async fn client_killed_before_the_reply_does_not_hold_up_the_next() {
+
+    let device = MockDevice::start().await;
+    device.set_holding_registers(0, &[7]);
+    let mut gateway = gateway(&device, &["plant"]);
+
+    let context = zmq::Context::new();
+    let doomed = context.socket(zmq::REQ).unwrap();
+    doomed.connect(&gateway.server.endpoint()).unwrap();
+    doomed.send(json!({"GET": ["/plant/meter/setpoint_u16"]}).to_string().as_str(), 0).unwrap();
+    // delivered to the server, then the client goes away without waiting for the reply
+    time::sleep(Duration::from_millis(100)).await;
+    doomed.set_linger(0).unwrap();
+    drop(doomed);
+    assert!(time::timeout(Duration::from_secs(5), gateway.server.serve_one(&mut gateway.devices)).await.unwrap_or(false), "server stopped");
+
+    let response = gateway.request(json!({"GET": ["/plant/meter/setpoint_u16"]})).await;
+    assert_eq!(response["GET"], json!({"setpoint_u16": 7}), "{}", response);
+    assert_eq!(device.requests().len(), 2, "the request of the killed client was not served");
+    assert_eq!(gateway.server.responses_dropped(), 0);
+
+}
+
+#[tokio::test]
+async fn response_keys_follow_the_chosen_case() {
+
+    let device = MockDevice::start().await;
+    device.set_holding_registers(0, &[7]);
+    let devices = HashMap::from([(String::from("plant"), device.fixture("roundtrip.yaml"))]);
+    let mut gateway = Gateway::new(devices, ServerOptions { key_case: KeyCase::Snake, ..ServerOptions::default() });
+
+    let response = gateway.request(json!({"GET": ["/plant/meter/setpoint_u16"]})).await;
+    assert_eq!(response, json!({"get": {"setpoint_u16": 7}}), "{}", response);
+    let response = gateway.request(json!({"GOT": null})).await;
+    assert!(response["error"].is_string() && response["details"].is_string(), "{}", response);
+    assert!(response.get("ERROR").is_none() && response.get("DETAILS").is_none(), "{}", response);
+
+}
+
+#[tokio::test]
+async fn pretty_responses_are_indented() {
+
+    let device = MockDevice::start().await;
+    device.set_holding_registers(0, &[7]);
+    let devices = HashMap::from([(String::from("plant"), device.fixture("roundtrip.yaml"))]);
+    let mut gateway = Gateway::new(devices, ServerOptions { pretty: true, ..ServerOptions::default() });
+
+    let response = gateway.request_text(json!({"GET": ["/plant/meter/setpoint_u16"]})).await;
+    assert_eq!(response, "{\n  \"GET\": {\n    \"setpoint_u16\": 7\n  }\n}", "{}", response);
+
+    // compact by default
+    let mut gateway = Gateway::new(gateway.devices, ServerOptions::default());
+    let response = gateway.request_text(json!({"GET": ["/plant/meter/setpoint_u16"]})).await;
+    assert!(!response.contains('\n'), "{}", response);
+
+}
+
+#[tokio::test]
+async fn set_on_error_abort_skips_the_rest_and_continue_writes_it() {
+
+    let device = MockDevice::start().await;
+    device.set_quirks(MockQuirks { reject_writes: true, ..MockQuirks::default() });
+    let mut gateway = gateway(&device, &["plant"]);
+    // the register write is rejected by the device, the coil write would go through
+    let values = json!({"/plant/meter/gain_f32": 1.5, "/plant/meter/relay": true});
+
+    let response = gateway.request(json!({"SET": {"values": values, "on_error": "abort"}})).await;
+    assert_eq!(response["SET"], json!({}), "{}", response);
+    assert!(response["failed"]["/plant/meter/gain_f32"].is_string(), "{}", response);
+    assert_eq!(response["skipped"], json!(["/plant/meter/relay"]), "{}", response);
+    assert!(!device.coil(0), "a write after the failure went out");
+
+    let response = gateway.request(json!({"SET": {"values": values, "on_error": "continue"}})).await;
+    assert_eq!(response["SET"], json!({"/plant/meter/relay": true}), "{}", response);
+    assert!(response["failed"]["/plant/meter/gain_f32"].is_string(), "{}", response);
+    assert!(response.get("skipped").is_none(), "{}", response);
+    assert!(device.coil(0));
+
+}
+
+#[tokio::test]
+async fn with_units_answers_quantities_for_points_with_a_unit() {
+
+    let device = MockDevice::start().await;
+    device.set_holding_registers(0, &[215, 3]);
+    let devices = HashMap::from([(String::from("boiler"), device.interface("
+slaves:
+- meter:
+    id: 1
+    hr:
+    - temperature: {addr: 0, type: u16, unit: \"°C\"}
+    - stage: {addr: 1, type: u16}
+"))]);
+    let mut gateway = Gateway::new(devices, ServerOptions::default());
+    let paths = json!(["/boiler/meter/temperature", "/boiler/meter/stage"]);
+
+    let response = gateway.request(json!({"GET": {"paths": paths, "with_units": true}})).await;
+    assert_eq!(response["GET"], json!({
+        "temperature": {"value": 215, "unit": "°C"},
+        "stage": 3,
+    }), "{}", response);
+
+    let response = gateway.request(json!({"GET": {"paths": paths}})).await;
+    assert_eq!(response["GET"], json!({"temperature": 215, "stage": 3}), "{}", response);
+
+}
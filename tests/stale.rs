@@ -0,0 +1,57 @@
+mod common;
+
+use std::{collections::HashMap, time::Duration};
+use serde_json::json;
+
+use zero_modbus::server::ServerOptions;
+use common::{Gateway, MockDevice, MockQuirks};
+
+
+const SLAVES: &str = "
+slaves:
+- meter:
+    id: 1
+    hr:
+    - energy: {addr: 0, type: u32}
+";
+
+fn gateway(device: &MockDevice, stale_ttl_s: u64) -> Gateway {
+
+    let devices = HashMap::from([(String::from("plant"), device.interface(SLAVES))]);
+    Gateway::new(devices, ServerOptions { serve_stale: true, stale_ttl_s, ..ServerOptions::default() })
+
+}
+
+#[tokio::test]
+async fn failed_read_serves_the_last_value() {
+
+    let device = MockDevice::start().await;
+    device.set_holding_registers(0, &[0, 7]);
+    let mut gateway = gateway(&device, 0);
+
+    let response = gateway.request(json!({"GET": ["/plant/meter/energy"]})).await;
+    assert_eq!(response["GET"], json!({"energy": 7}), "{}", response);
+
+    // a short answer fails the read
+    device.set_quirks(MockQuirks { read_delta: -1, ..MockQuirks::default() });
+    let response = gateway.request(json!({"GET": ["/plant/meter/energy"]})).await;
+    let stale = &response["GET"]["energy"];
+    assert_eq!((&stale["value"], &stale["stale"]), (&json!(7), &json!(true)), "{}", response);
+    assert!(stale["age_ms"].is_u64(), "{}", response);
+
+}
+
+#[tokio::test]
+async fn expired_value_is_not_served() {
+
+    let device = MockDevice::start().await;
+    device.set_holding_registers(0, &[0, 7]);
+    let mut gateway = gateway(&device, 1);
+
+    gateway.request(json!({"GET": ["/plant/meter/energy"]})).await;
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    device.set_quirks(MockQuirks { read_delta: -1, ..MockQuirks::default() });
+    let response = gateway.request(json!({"GET": ["/plant/meter/energy"]})).await;
+    assert!(response.get("ERROR").is_some(), "an expired value was served: {}", response);
+
+}
@@ -0,0 +1,39 @@
+use std::process::{Command, Output};
+use serde_json::Value;
+
+
+// the binary runs from the manifest directory, so the reported paths match the fixture
+fn validate(args: &[&str]) -> Output {
+
+    Command::new(env!("CARGO_BIN_EXE_zero_modbus"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("--validate")
+        .args(args)
+        .output()
+        .expect("Failed to run the binary")
+
+}
+
+#[test]
+fn json_report_matches_fixture() {
+
+    let output = validate(&["--json", "tests/fixtures/validate/valid.yaml", "tests/fixtures/validate/invalid.yaml"]);
+    assert_eq!(output.status.code(), Some(1));
+
+    let report: Value = serde_json::from_slice(&output.stdout).expect("the report is not JSON");
+    let path = format!("{}/tests/fixtures/validate/report.json", env!("CARGO_MANIFEST_DIR"));
+    let expected: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(report, expected);
+
+}
+
+#[test]
+fn valid_files_exit_zero() {
+
+    let output = validate(&["--json", "tests/fixtures/validate/valid.yaml"]);
+    assert_eq!(output.status.code(), Some(0));
+
+    let report: Value = serde_json::from_slice(&output.stdout).expect("the report is not JSON");
+    assert_eq!(report["valid"], true);
+
+}
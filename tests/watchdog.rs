@@ -0,0 +1,67 @@
+mod common;
+
+use std::time::{Duration, Instant};
+use serde_json::json;
+
+use zero_modbus::modbus::{self, ModbusError, OnError};
+use common::{get, MockQuirks, MockRtuDevice};
+
+
+const SLAVES: &str = "
+watchdog: {timeouts: 2, reopen_attempts: 2}
+slaves:
+- left:
+    id: 1
+    hr:
+    - level: {addr: 0, type: u16}
+    - total: {type: u32, words: [2, 3]}
+- right:
+    id: 2
+    hr:
+    - level: {addr: 0, type: u16}
+";
+
+#[tokio::test]
+async fn wedged_port_is_reopened_and_counted_per_device() {
+
+    let device = MockRtuDevice::start().await;
+    let interface = device.interface(SLAVES);
+    device.set_holding_registers(0, &[7]);
+
+    device.set_quirks(MockQuirks { mute: true, ..MockQuirks::default() });
+    let results = modbus::batch_request_items(String::from("wedged"), interface.clone(),
+        vec![get("left", "level"), get("right", "level")], OnError::Continue).await
+        .unwrap_or_else(|e| panic!("the session does not open: {}", e));
+    for result in &results {
+        assert!(matches!(result, Err(error) if error.to_string().contains("Timeout")), "{:?}", result.as_ref().err().map(ModbusError::to_string));
+    }
+    assert_eq!(modbus::watchdog_recoveries().get("wedged"), Some(&1));
+    assert_eq!(modbus::watchdog_recoveries().get("healthy"), None);
+
+    device.set_quirks(MockQuirks::default());
+    let results = modbus::batch_request(String::from("wedged"), interface,
+        vec![get("left", "level"), get("right", "level")]).await
+        .unwrap_or_else(|e| panic!("no recovery: {}", e));
+    assert_eq!(results.iter().map(|result| result.value.clone()).collect::<Vec<_>>(), vec![json!(7), json!(7)]);
+    assert_eq!(modbus::watchdog_recoveries().get("wedged"), Some(&1));
+
+}
+
+#[tokio::test]
+async fn slow_device_is_timed_per_transaction() {
+
+    let device = MockRtuDevice::start().await;
+    let interface = device.interface(SLAVES);
+    device.set_holding_registers(2, &[0x0001, 0x0002]);
+
+    // two round trips of 600ms each, more than one response timeout in total but not each
+    device.set_quirks(MockQuirks { stall: Some(Duration::from_millis(600)), ..MockQuirks::default() });
+    let started = Instant::now();
+    let results = modbus::batch_request(String::from("healthy"), interface, vec![get("left", "total")]).await
+        .unwrap_or_else(|e| panic!("a slow device timed out: {}", e));
+    assert_eq!(results[0].value, json!(0x0001_0002));
+    assert!(started.elapsed() > Duration::from_millis(1000), "{:?}", started.elapsed());
+    assert_eq!(device.requests().len(), 2);
+    assert_eq!(modbus::watchdog_recoveries().get("healthy"), None);
+
+}
@@ -14,7 +14,7 @@ class ZmbClient:
         self.__socket = zmq.Context().socket(zmq.REQ)
         self.__socket.connect(address)
     
-    def __do_request(self, body:dict) -> dict|None:
+    def __do_request(self, body:dict, whole:bool=False) -> dict|None:
         exception = None
         key, value = 'ERROR', 'ZMQ ERROR'
         try:
@@ -22,22 +22,72 @@ class ZmbClient:
             recv = self.__socket.recv().decode('ASCII')
             response:dict = loads(str(recv))
             assert type(response) == dict
-            assert len(response.keys()) == 1
-            key, value = response.popitem()
-            assert key in ('ERROR', 'TEST', 'GET', 'SET')
+            if 'ERROR' in response:
+                key, value = 'ERROR', response
+            else:
+                key = next(k for k in response if k.isupper())
+                value = response[key]
+            assert key in ('ERROR', 'TEST', 'GET', 'SET', 'DUMP_CONFIG', 'IDENTIFY', 'HEXDUMP', 'AGGREGATE', 'SNAPSHOT', 'LIST', 'STATS', 'RELOAD')
         except Exception as e:
             exception = ZeroModbusError("INVAILED RESPONSE", e)
         if exception: raise exception
         if key == 'ERROR':
-            raise ZeroModbusError(response[key])
-        return value
+            raise ZeroModbusError(value.pop('ERROR'), value)
+        return response if whole else value
 
     def test(self) -> bool:
         uuid = str(gen_uuid())
         return uuid == self.__do_request({ 'TEST': uuid })
 
-    def get(self, paths:list) -> None:
+    def probe(self, count:int=5) -> dict:
+        return self.__do_request({ 'TEST': { 'key': str(gen_uuid()), 'probe': True, 'count': count } }, whole=True)['probe']
+
+    def get(self, paths:list, with_units:bool=False) -> None:
+        if with_units:
+            return self.__do_request({ 'GET': { 'paths': paths, 'with_units': True } })
         return self.__do_request({ 'GET': paths })
 
-    def set(self, pairs:dict) -> None:
-        return self.__do_request({ 'SET': pairs })
\ No newline at end of file
+    def get_if_changed(self, paths:list, etag:str|None=None) -> tuple[dict|None, str]:
+        response = self.__do_request({ 'GET': { 'paths': paths, 'etag': etag } }, whole=True)
+        return response['GET'], response['etag']
+
+    def set(self, pairs:dict, on_error:str='continue', coerce_strings:bool=False, idempotency_key:str=None) -> None:
+        options = { 'values': pairs, 'on_error': on_error, 'coerce_strings': coerce_strings }
+        if idempotency_key is not None:
+            options['idempotency_key'] = idempotency_key
+        response = self.__do_request({ 'SET': options }, whole=True)
+        if 'failed' in response:
+            raise ZeroModbusError('SET FAILED', response)
+        return response['SET']
+
+    def dump_config(self, interface:str, layout:bool=False) -> dict:
+        if layout:
+            return self.__do_request({ 'DUMP_CONFIG': { 'device': interface, 'layout': True } })
+        return self.__do_request({ 'DUMP_CONFIG': interface })
+
+    def register_table(self, interface:str) -> str:
+        return self.__do_request({ 'DUMP_CONFIG': { 'device': interface, 'format': 'table' } })
+
+    def identify(self, interface:str, slave:str) -> dict:
+        return self.__do_request({ 'IDENTIFY': { 'interface': interface, 'slave': slave } })
+
+    def hexdump(self, interface:str, slave:str, block:str, start:int, count:int) -> str:
+        return self.__do_request({ 'HEXDUMP': { 'interface': interface, 'slave': slave, 'block': block, 'start': start, 'count': count } })
+
+    def aggregate(self, interface:str, point:str, op:str) -> dict:
+        return self.__do_request({ 'AGGREGATE': { 'interface': interface, 'point': point, 'op': op } })
+
+    def snapshot(self) -> dict:
+        return self.__do_request({ 'SNAPSHOT': None })
+
+    def snapshot_status(self) -> dict:
+        return self.__do_request({ 'SNAPSHOT': None }, whole=True)['status']
+
+    def list(self) -> dict:
+        return self.__do_request({ 'LIST': None })
+
+    def stats(self) -> dict:
+        return self.__do_request({ 'STATS': None })
+
+    def reload(self) -> dict:
+        return self.__do_request({ 'RELOAD': None }, whole=True)